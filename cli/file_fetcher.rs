@@ -7,6 +7,7 @@ use crate::http_util::fetch_once;
 use crate::http_util::CacheSemantics;
 use crate::http_util::FetchOnceArgs;
 use crate::http_util::FetchOnceResult;
+use crate::http_util::HeadersMap;
 use crate::text_encoding;
 use crate::version::get_user_agent;
 
@@ -76,6 +77,16 @@ impl FileCache {
     let mut cache = self.0.lock();
     cache.insert(specifier, file)
   }
+
+  pub fn remove(&self, specifier: &ModuleSpecifier) -> Option<File> {
+    let mut cache = self.0.lock();
+    cache.remove(specifier)
+  }
+
+  pub fn clear(&self) {
+    let mut cache = self.0.lock();
+    cache.clear();
+  }
 }
 
 /// Indicates how cached source files should be handled.
@@ -251,7 +262,7 @@ pub struct FileFetcher {
   cache: FileCache,
   cache_setting: CacheSetting,
   pub(crate) http_cache: HttpCache,
-  http_client: reqwest::Client,
+  pub(crate) http_client: reqwest::Client,
   blob_store: BlobStore,
   download_log_level: log::Level,
 }
@@ -284,11 +295,32 @@ impl FileFetcher {
     })
   }
 
+  /// Creates a fetcher which shares the HTTP client and the settings of this
+  /// fetcher, but caches remote files in `http_cache`, and has an in memory
+  /// cache of its own.
+  pub fn with_http_cache(&self, http_cache: HttpCache) -> Self {
+    Self {
+      cache: Default::default(),
+      http_cache,
+      ..self.clone()
+    }
+  }
+
   /// Sets the log level to use when outputting the download message.
   pub fn set_download_log_level(&mut self, level: log::Level) {
     self.download_log_level = level;
   }
 
+  /// Sets how the cache is used when fetching remote files.
+  pub fn set_cache_setting(&mut self, cache_setting: CacheSetting) {
+    self.cache_setting = cache_setting;
+  }
+
+  /// How the cache is used when fetching remote files.
+  pub fn cache_setting(&self) -> &CacheSetting {
+    &self.cache_setting
+  }
+
   /// Creates a `File` structure for a remote file.
   fn build_remote_file(
     &self,
@@ -484,6 +516,7 @@ impl FileFetcher {
     specifier: &ModuleSpecifier,
     permissions: &mut Permissions,
     redirect_limit: i64,
+    maybe_headers: Option<HeadersMap>,
   ) -> Pin<Box<dyn Future<Output = Result<File, AnyError>> + Send>> {
     debug!("FileFetcher::fetch_remote() - specifier: {}", specifier);
     if redirect_limit < 0 {
@@ -525,9 +558,13 @@ impl FileFetcher {
       specifier
     );
 
-    let maybe_etag = match self.http_cache.get(specifier) {
-      Ok((_, headers, _)) => headers.get("etag").cloned(),
-      _ => None,
+    let (maybe_etag, maybe_last_modified) = match self.http_cache.get(specifier)
+    {
+      Ok((_, headers, _)) => (
+        headers.get("etag").cloned(),
+        headers.get("last-modified").cloned(),
+      ),
+      _ => (None, None),
     };
     let maybe_auth_token = self.auth_tokens.get(specifier);
     let specifier = specifier.clone();
@@ -540,18 +577,30 @@ impl FileFetcher {
         client,
         url: specifier.clone(),
         maybe_etag,
+        maybe_last_modified,
         maybe_auth_token,
+        maybe_headers: maybe_headers.clone(),
       })
       .await?
       {
         FetchOnceResult::NotModified => {
+          // the cached response was revalidated, so it is fresh again
+          file_fetcher.http_cache.touch(&specifier)?;
           let file = file_fetcher.fetch_cached(&specifier, 10)?.unwrap();
           Ok(file)
         }
         FetchOnceResult::Redirect(redirect_url, headers) => {
           file_fetcher.http_cache.set(&specifier, headers, &[])?;
+          // additional headers are never forwarded to a different origin
+          let maybe_headers = maybe_headers
+            .filter(|_| redirect_url.origin() == specifier.origin());
           file_fetcher
-            .fetch_remote(&redirect_url, &mut permissions, redirect_limit - 1)
+            .fetch_remote(
+              &redirect_url,
+              &mut permissions,
+              redirect_limit - 1,
+              maybe_headers,
+            )
             .await
         }
         FetchOnceResult::Code(bytes, headers) => {
@@ -572,39 +621,77 @@ impl FileFetcher {
     &self,
     specifier: &ModuleSpecifier,
     permissions: &mut Permissions,
+  ) -> Result<File, AnyError> {
+    self.fetch_with_headers(specifier, permissions, None).await
+  }
+
+  /// Fetch a source file and asynchronously return it, sending any additional
+  /// headers along with a request to a remote. The headers are not sent if the
+  /// request is redirected to a different origin.
+  pub async fn fetch_with_headers(
+    &self,
+    specifier: &ModuleSpecifier,
+    permissions: &mut Permissions,
+    maybe_headers: Option<HeadersMap>,
   ) -> Result<File, AnyError> {
     debug!("FileFetcher::fetch() - specifier: {}", specifier);
     let scheme = get_validated_scheme(specifier)?;
     permissions.check_specifier(specifier)?;
     if let Some(file) = self.cache.get(specifier) {
-      Ok(file)
-    } else if scheme == "file" {
-      // we do not in memory cache files, as this would prevent files on the
-      // disk changing effecting things like workers and dynamic imports.
-      fetch_local(specifier)
-    } else if scheme == "data" {
-      let result = self.fetch_data_url(specifier);
+      return Ok(file);
+    }
+    let result = self
+      .fetch_no_cache(specifier, &scheme, permissions, maybe_headers)
+      .await;
+    // we do not in memory cache files, as this would prevent files on the
+    // disk changing effecting things like workers and dynamic imports.
+    if scheme != "file" {
       if let Ok(file) = &result {
         self.cache.insert(specifier.clone(), file.clone());
       }
-      result
+    }
+    result
+  }
+
+  /// Fetch a source file without the in memory cache, so that a remote file
+  /// is always loaded through the HTTP cache, which revalidates it according
+  /// to the cache setting, and the file isn't kept in memory afterwards.
+  pub async fn fetch_uncached(
+    &self,
+    specifier: &ModuleSpecifier,
+    permissions: &mut Permissions,
+    maybe_headers: Option<HeadersMap>,
+  ) -> Result<File, AnyError> {
+    debug!("FileFetcher::fetch_uncached() - specifier: {}", specifier);
+    let scheme = get_validated_scheme(specifier)?;
+    permissions.check_specifier(specifier)?;
+    self
+      .fetch_no_cache(specifier, &scheme, permissions, maybe_headers)
+      .await
+  }
+
+  async fn fetch_no_cache(
+    &self,
+    specifier: &ModuleSpecifier,
+    scheme: &str,
+    permissions: &mut Permissions,
+    maybe_headers: Option<HeadersMap>,
+  ) -> Result<File, AnyError> {
+    if scheme == "file" {
+      fetch_local(specifier)
+    } else if scheme == "data" {
+      self.fetch_data_url(specifier)
     } else if scheme == "blob" {
-      let result = self.fetch_blob_url(specifier).await;
-      if let Ok(file) = &result {
-        self.cache.insert(specifier.clone(), file.clone());
-      }
-      result
+      self.fetch_blob_url(specifier).await
     } else if !self.allow_remote {
       Err(custom_error(
         "NoRemote",
         format!("A remote specifier was requested: \"{}\", but --no-remote is specified.", specifier),
       ))
     } else {
-      let result = self.fetch_remote(specifier, permissions, 10).await;
-      if let Ok(file) = &result {
-        self.cache.insert(specifier.clone(), file.clone());
-      }
-      result
+      self
+        .fetch_remote(specifier, permissions, 10, maybe_headers)
+        .await
     }
   }
 
@@ -643,6 +730,17 @@ impl FileFetcher {
   pub fn insert_cached(&self, file: File) -> Option<File> {
     self.cache.insert(file.specifier.clone(), file)
   }
+
+  /// Remove a module from the in memory cache for the file fetcher, so that a
+  /// subsequent fetch will consult the HTTP cache or the remote again.
+  pub fn remove_cached(&self, specifier: &ModuleSpecifier) -> Option<File> {
+    self.cache.remove(specifier)
+  }
+
+  /// Remove all modules from the in memory cache for the file fetcher.
+  pub fn clear_cached(&self) {
+    self.cache.clear();
+  }
 }
 
 #[cfg(test)]
@@ -710,7 +808,7 @@ mod tests {
     let _http_server_guard = test_util::http_server();
     let (file_fetcher, _) = setup(CacheSetting::ReloadAll, None);
     let result: Result<File, AnyError> = file_fetcher
-      .fetch_remote(specifier, &mut Permissions::allow_all(), 1)
+      .fetch_remote(specifier, &mut Permissions::allow_all(), 1, None)
       .await;
     assert!(result.is_ok());
     let (_, headers, _) = file_fetcher.http_cache.get(specifier).unwrap();
@@ -1375,12 +1473,12 @@ mod tests {
         .unwrap();
 
     let result = file_fetcher
-      .fetch_remote(&specifier, &mut Permissions::allow_all(), 2)
+      .fetch_remote(&specifier, &mut Permissions::allow_all(), 2, None)
       .await;
     assert!(result.is_ok());
 
     let result = file_fetcher
-      .fetch_remote(&specifier, &mut Permissions::allow_all(), 1)
+      .fetch_remote(&specifier, &mut Permissions::allow_all(), 1, None)
       .await;
     assert!(result.is_err());
 
@@ -181,6 +181,44 @@ impl HttpCache {
     };
     metadata.write(&cache_filename)
   }
+
+  /// Mark a cached entry as having been cached now, which is done when it is
+  /// revalidated, so that its age is counted from the revalidation.
+  pub fn touch(&self, url: &Url) -> Result<(), AnyError> {
+    let cache_filename = self.location.join(
+      url_to_filename(url)
+        .ok_or_else(|| generic_error("Can't convert url to filename."))?,
+    );
+    let mut metadata = Metadata::read(&cache_filename)?;
+    metadata.now = SystemTime::now();
+    metadata.write(&cache_filename)
+  }
+
+  /// Remove a cached entry along with its metadata, if it exists.
+  pub fn remove(&self, url: &Url) -> Result<(), AnyError> {
+    let cache_filename = self.location.join(
+      url_to_filename(url)
+        .ok_or_else(|| generic_error("Can't convert url to filename."))?,
+    );
+    let metadata_filename = Metadata::filename(&cache_filename);
+    for filename in [cache_filename, metadata_filename] {
+      match fs::remove_file(filename) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+          return Err(err.into())
+        }
+        _ => (),
+      }
+    }
+    Ok(())
+  }
+
+  /// Remove all of the cached entries, along with the location of the cache.
+  pub fn clear(&self) -> Result<(), AnyError> {
+    match fs::remove_dir_all(&self.location) {
+      Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+      _ => Ok(()),
+    }
+  }
 }
 
 #[cfg(test)]
@@ -246,6 +284,20 @@ mod tests {
     assert_eq!(headers.get("foobar"), None);
   }
 
+  #[test]
+  fn test_touch() {
+    let dir = TempDir::new().unwrap();
+    let cache = HttpCache::new(dir.path());
+    let url = Url::parse("https://deno.land/x/welcome.ts").unwrap();
+    assert!(cache.touch(&url).is_err());
+    cache.set(&url, HashMap::new(), b"Hello world").unwrap();
+    let (_, _, cached) = cache.get(&url).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    cache.touch(&url).unwrap();
+    let (_, _, touched) = cache.get(&url).unwrap();
+    assert!(touched > cached);
+  }
+
   #[test]
   fn test_url_to_filename() {
     let test_cases = [
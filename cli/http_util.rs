@@ -10,12 +10,14 @@ use deno_core::error::AnyError;
 use deno_core::url::Url;
 use deno_runtime::deno_fetch::reqwest::header::HeaderValue;
 use deno_runtime::deno_fetch::reqwest::header::AUTHORIZATION;
+use deno_runtime::deno_fetch::reqwest::header::IF_MODIFIED_SINCE;
 use deno_runtime::deno_fetch::reqwest::header::IF_NONE_MATCH;
 use deno_runtime::deno_fetch::reqwest::header::LOCATION;
 use deno_runtime::deno_fetch::reqwest::Client;
 use deno_runtime::deno_fetch::reqwest::StatusCode;
 use log::debug;
 use std::collections::HashMap;
+use std::fmt;
 use std::time::Duration;
 use std::time::SystemTime;
 
@@ -193,11 +195,58 @@ impl CacheSemantics {
     true
   }
 
-  fn time_to_live(&self) -> Duration {
+  pub fn time_to_live(&self) -> Duration {
     self.max_age().checked_sub(self.age()).unwrap_or_default()
   }
 }
 
+/// The context of the error for a response with the status
+/// `429 Too Many Requests`, which keeps the value of the `Retry-After` header
+/// of the response, if any, so that the request can be held off for as long
+/// as the server asked. It displays as the error itself.
+#[derive(Debug)]
+pub struct TooManyRequests {
+  message: String,
+  pub retry_after: Option<String>,
+}
+
+impl TooManyRequests {
+  pub fn new(message: String, retry_after: Option<String>) -> Self {
+    Self {
+      message,
+      retry_after,
+    }
+  }
+}
+
+impl fmt::Display for TooManyRequests {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(&self.message)
+  }
+}
+
+/// The context of the error for a response with any other client or server
+/// error status than `404 Not Found` and `429 Too Many Requests`, which keeps
+/// the status of the response, so that it can be told what kind of error it
+/// is without relying on the message. It displays as the error itself.
+#[derive(Debug)]
+pub struct ErrorStatus {
+  message: String,
+  pub status: StatusCode,
+}
+
+impl ErrorStatus {
+  pub fn new(message: String, status: StatusCode) -> Self {
+    Self { message, status }
+  }
+}
+
+impl fmt::Display for ErrorStatus {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(&self.message)
+  }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum FetchOnceResult {
   Code(Vec<u8>, HeadersMap),
@@ -210,7 +259,12 @@ pub struct FetchOnceArgs {
   pub client: Client,
   pub url: Url,
   pub maybe_etag: Option<String>,
+  /// The `Last-Modified` of a cached response, which the response is
+  /// revalidated against with an `If-Modified-Since` request header.
+  pub maybe_last_modified: Option<String>,
   pub maybe_auth_token: Option<AuthToken>,
+  /// Any additional headers to send with the request.
+  pub maybe_headers: Option<HeadersMap>,
 }
 
 /// Asynchronously fetches the given HTTP URL one pass only.
@@ -227,11 +281,21 @@ pub async fn fetch_once(
     let if_none_match_val = HeaderValue::from_str(&etag).unwrap();
     request = request.header(IF_NONE_MATCH, if_none_match_val);
   }
+  if let Some(last_modified) = args.maybe_last_modified {
+    if let Ok(if_modified_since_val) = HeaderValue::from_str(&last_modified) {
+      request = request.header(IF_MODIFIED_SINCE, if_modified_since_val);
+    }
+  }
   if let Some(auth_token) = args.maybe_auth_token {
     let authorization_val =
       HeaderValue::from_str(&auth_token.to_string()).unwrap();
     request = request.header(AUTHORIZATION, authorization_val);
   }
+  if let Some(headers) = args.maybe_headers {
+    for (key, value) in headers {
+      request = request.header(key.as_str(), value.as_str());
+    }
+  }
   let response = request.send().await?;
 
   if response.status() == StatusCode::NOT_MODIFIED {
@@ -281,12 +345,17 @@ pub async fn fetch_once(
         "NotFound",
         format!("Import '{}' failed, not found.", args.url),
       )
+    } else if response.status() == StatusCode::TOO_MANY_REQUESTS {
+      let message =
+        format!("Import '{}' failed: {}", args.url, response.status());
+      let retry_after = headers_.get("retry-after").cloned();
+      generic_error(message.clone())
+        .context(TooManyRequests::new(message, retry_after))
     } else {
-      generic_error(format!(
-        "Import '{}' failed: {}",
-        args.url,
-        response.status()
-      ))
+      let message =
+        format!("Import '{}' failed: {}", args.url, response.status());
+      generic_error(message.clone())
+        .context(ErrorStatus::new(message, response.status()))
     };
     return Err(err);
   }
@@ -325,7 +394,9 @@ mod tests {
       client,
       url,
       maybe_etag: None,
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
     if let Ok(FetchOnceResult::Code(body, headers)) = result {
@@ -349,7 +420,9 @@ mod tests {
       client,
       url,
       maybe_etag: None,
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
     if let Ok(FetchOnceResult::Code(body, headers)) = result {
@@ -365,6 +438,26 @@ mod tests {
     }
   }
 
+  #[tokio::test]
+  async fn test_fetch_with_last_modified() {
+    let _http_server_guard = test_util::http_server();
+    let url = Url::parse(
+      "http://127.0.0.1:4545/lsp/registries/revalidated_modules.json",
+    )
+    .unwrap();
+    let client = create_test_client();
+    let res = fetch_once(FetchOnceArgs {
+      client,
+      url,
+      maybe_etag: None,
+      maybe_last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+      maybe_auth_token: None,
+      maybe_headers: None,
+    })
+    .await;
+    assert_eq!(res.unwrap(), FetchOnceResult::NotModified);
+  }
+
   #[tokio::test]
   async fn test_fetch_with_etag() {
     let _http_server_guard = test_util::http_server();
@@ -374,7 +467,9 @@ mod tests {
       client: client.clone(),
       url: url.clone(),
       maybe_etag: None,
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
     if let Ok(FetchOnceResult::Code(body, headers)) = result {
@@ -393,7 +488,9 @@ mod tests {
       client,
       url,
       maybe_etag: Some("33a64df551425fcc55e".to_string()),
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
     assert_eq!(res.unwrap(), FetchOnceResult::NotModified);
@@ -410,7 +507,9 @@ mod tests {
       client,
       url,
       maybe_etag: None,
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
     if let Ok(FetchOnceResult::Code(body, headers)) = result {
@@ -439,7 +538,9 @@ mod tests {
       client,
       url,
       maybe_etag: None,
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
     if let Ok(FetchOnceResult::Redirect(url, _)) = result {
@@ -512,7 +613,9 @@ mod tests {
       client,
       url,
       maybe_etag: None,
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
     if let Ok(FetchOnceResult::Code(body, headers)) = result {
@@ -544,7 +647,9 @@ mod tests {
       client,
       url,
       maybe_etag: None,
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
 
@@ -579,7 +684,9 @@ mod tests {
       client,
       url,
       maybe_etag: None,
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
 
@@ -615,7 +722,9 @@ mod tests {
       client,
       url,
       maybe_etag: None,
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
     if let Ok(FetchOnceResult::Code(body, headers)) = result {
@@ -654,7 +763,9 @@ mod tests {
       client: client.clone(),
       url: url.clone(),
       maybe_etag: None,
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
     if let Ok(FetchOnceResult::Code(body, headers)) = result {
@@ -674,7 +785,9 @@ mod tests {
       client,
       url,
       maybe_etag: Some("33a64df551425fcc55e".to_string()),
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
     assert_eq!(res.unwrap(), FetchOnceResult::NotModified);
@@ -706,7 +819,9 @@ mod tests {
       client,
       url,
       maybe_etag: None,
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
     if let Ok(FetchOnceResult::Code(body, headers)) = result {
@@ -733,7 +848,9 @@ mod tests {
       client,
       url,
       maybe_etag: None,
+      maybe_last_modified: None,
       maybe_auth_token: None,
+      maybe_headers: None,
     })
     .await;
     assert!(result.is_err());
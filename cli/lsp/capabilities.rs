@@ -96,6 +96,7 @@ pub fn server_capabilities(
       work_done_progress_options: WorkDoneProgressOptions {
         work_done_progress: None,
       },
+      completion_item: None,
     }),
     signature_help_provider: Some(SignatureHelpOptions {
       trigger_characters: Some(vec![
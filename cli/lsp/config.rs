@@ -26,7 +26,9 @@ pub const SETTINGS_SECTION: &str = "deno";
 #[derive(Debug, Clone, Default)]
 pub struct ClientCapabilities {
   pub code_action_disabled_support: bool,
+  pub label_details_support: bool,
   pub line_folding_only: bool,
+  pub snippet_support: bool,
   pub status_notification: bool,
   pub workspace_configuration: bool,
   pub workspace_did_change_watched_files: bool,
@@ -408,6 +410,18 @@ impl Config {
         .as_ref()
         .and_then(|it| it.disabled_support)
         .unwrap_or(false);
+      self.client_capabilities.snippet_support = text_document
+        .completion
+        .as_ref()
+        .and_then(|it| it.completion_item.as_ref())
+        .and_then(|it| it.snippet_support)
+        .unwrap_or(false);
+      self.client_capabilities.label_details_support = text_document
+        .completion
+        .as_ref()
+        .and_then(|it| it.completion_item.as_ref())
+        .and_then(|it| it.label_details_support)
+        .unwrap_or(false);
     }
   }
 
@@ -452,6 +466,7 @@ mod tests {
       Ok(lspower::lsp::InitializeResult {
         capabilities: lspower::lsp::ServerCapabilities::default(),
         server_info: None,
+        offset_encoding: None,
       })
     }
 
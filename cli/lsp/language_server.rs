@@ -502,6 +502,34 @@ impl Inner {
 
   async fn update_registries(&mut self) -> Result<(), AnyError> {
     let mark = self.performance.mark("update_registries", None::<()>);
+    self
+      .module_registries
+      .set_snippet_support(self.config.client_capabilities.snippet_support);
+    self.module_registries.set_label_details_support(
+      self.config.client_capabilities.label_details_support,
+    );
+    // registry configurations kept in the workspace are picked up as they are
+    // edited, and so are only watched while such a registry is enabled
+    let has_local_registry = self
+      .config
+      .get_workspace_settings()
+      .suggest
+      .imports
+      .hosts
+      .iter()
+      .any(|(registry, enabled)| {
+        *enabled
+          && matches!(
+            ModuleSpecifier::parse(registry),
+            Ok(url) if url.scheme() == "file"
+          )
+      });
+    if let Err(err) = self
+      .module_registries
+      .set_reload_on_config_change(has_local_registry)
+    {
+      error!("Unable to watch registry configurations: {}", err);
+    }
     for (registry, enabled) in self
       .config
       .get_workspace_settings()
@@ -510,7 +538,14 @@ impl Inner {
       .hosts
       .iter()
     {
-      if *enabled {
+      if registry.contains('*') {
+        if *enabled {
+          lsp_log!("Enabling import suggestions for pattern: {}", registry);
+          self.module_registries.enable_pattern(registry)?;
+        } else {
+          self.module_registries.disable_pattern(registry);
+        }
+      } else if *enabled {
         lsp_log!("Enabling import suggestions for: {}", registry);
         self.module_registries.enable(registry).await?;
       } else {
@@ -707,6 +742,7 @@ impl Inner {
     Ok(InitializeResult {
       capabilities,
       server_info: Some(server_info),
+      offset_encoding: None,
     })
   }
 
@@ -744,6 +780,10 @@ impl Inner {
   }
 
   async fn shutdown(&self) -> LspResult<()> {
+    lsp_log!(
+      "Import intellisense stats: {:?}",
+      self.module_registries.stats()
+    );
     Ok(())
   }
 
@@ -1683,6 +1723,7 @@ impl Inner {
     // completions, we will use internal logic and if there are completions
     // for imports, we will return those and not send a message into tsc, where
     // other completions come from.
+    self.module_registries.reload_changed_configs().await;
     let snapshot = self.snapshot()?;
     let response = if let Some(response) = completions::get_import_completions(
       &specifier,
@@ -1747,9 +1788,9 @@ impl Inner {
     params: CompletionItem,
   ) -> LspResult<CompletionItem> {
     let mark = self.performance.mark("completion_resolve", Some(&params));
-    let completion_item = if let Some(data) = &params.data {
+    let completion_item = if let Some(value) = &params.data {
       let data: completions::CompletionItemData =
-        serde_json::from_value(data.clone()).map_err(|err| {
+        serde_json::from_value(value.clone()).map_err(|err| {
           error!("{}", err);
           LspError::invalid_params(
             "Could not decode data field of completion item.",
@@ -1773,9 +1814,14 @@ impl Inner {
           );
           params
         }
-      } else if let Some(url) = data.documentation {
+      } else if data.documentation.is_some() {
+        let documentation = self
+          .module_registries
+          .get_completion_details(value)
+          .await
+          .and_then(|details| details.documentation);
         CompletionItem {
-          documentation: self.module_registries.get_documentation(&url).await,
+          documentation,
           data: None,
           ..params
         }
@@ -37,6 +37,7 @@ use std::iter::Peekable;
 lazy_static::lazy_static! {
   static ref ESCAPE_STRING_RE: Regex =
     Regex::new(r"([.+*?=^!:${}()\[\]|/\\])").unwrap();
+  static ref ESCAPE_CLASS_RE: Regex = Regex::new(r"([\^\-\[\]\\])").unwrap();
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -62,6 +63,12 @@ fn escape_string(s: &str) -> String {
   ESCAPE_STRING_RE.replace_all(s, r"\$1").to_string()
 }
 
+/// Escape the characters that have a special meaning within a character
+/// class, so that a string can be used as the set of characters of a class.
+fn escape_class(s: &str) -> String {
+  ESCAPE_CLASS_RE.replace_all(s, r"\$1").to_string()
+}
+
 fn lexer(s: &str) -> Result<Vec<LexToken>, AnyError> {
   let mut tokens = Vec::new();
   let mut chars = s.chars().peekable();
@@ -309,14 +316,19 @@ pub enum Token {
 
 #[derive(Debug, Default)]
 pub struct ParseOptions {
-  delimiter: Option<String>,
-  prefixes: Option<String>,
+  /// The characters that delimit the segments of a path, which default to
+  /// `/#?`.
+  pub delimiter: Option<String>,
+  /// The characters that are treated as the prefix of a key when they
+  /// directly precede it, which default to `./`.
+  pub prefixes: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct TokensToCompilerOptions {
-  sensitive: bool,
-  validate: bool,
+  /// When `false`, key patterns are validated without regard to case.
+  pub sensitive: bool,
+  pub validate: bool,
 }
 
 impl Default for TokensToCompilerOptions {
@@ -330,12 +342,13 @@ impl Default for TokensToCompilerOptions {
 
 #[derive(Debug)]
 pub struct TokensToRegexOptions {
-  sensitive: bool,
-  strict: bool,
-  end: bool,
-  start: bool,
-  delimiter: Option<String>,
-  ends_with: Option<String>,
+  /// When `false`, paths are matched without regard to case.
+  pub sensitive: bool,
+  pub strict: bool,
+  pub end: bool,
+  pub start: bool,
+  pub delimiter: Option<String>,
+  pub ends_with: Option<String>,
 }
 
 impl Default for TokensToRegexOptions {
@@ -419,7 +432,7 @@ pub fn parse(
   let options = maybe_options.unwrap_or_default();
   let prefixes = options.prefixes.unwrap_or_else(|| "./".to_string());
   let default_pattern = if let Some(delimiter) = options.delimiter {
-    format!("[^{}]+?", escape_string(&delimiter))
+    format!("[^{}]+?", escape_class(&delimiter))
   } else {
     "[^/#?]+?".to_string()
   };
@@ -541,8 +554,10 @@ pub fn tokens_to_regex(
   } = maybe_options.unwrap_or_default();
   let has_ends_with = ends_with.is_some();
   let ends_with = format!(r"[{}]|$", ends_with.unwrap_or_default());
-  let delimiter =
-    format!(r"[{}]", delimiter.unwrap_or_else(|| "/#?".to_string()));
+  let delimiter = format!(
+    r"[{}]",
+    escape_class(&delimiter.unwrap_or_else(|| "/#?".to_string()))
+  );
   let mut route = if start {
     "^".to_string()
   } else {
@@ -553,7 +568,7 @@ pub fn tokens_to_regex(
 
   for token in tokens {
     let value = match token {
-      Token::String(s) => s.to_string(),
+      Token::String(s) => escape_string(s),
       Token::Key(key) => {
         if !key.pattern.is_empty() {
           keys.push(key.clone());
@@ -841,6 +856,10 @@ impl Matcher {
             m.as_str().into()
           };
           params.insert(key.name.clone(), value);
+        } else if key.modifier == Some("*".to_string()) {
+          // a repeated key that is optional matches no segments at all, which
+          // is still a value for the key, albeit an empty one
+          params.insert(key.name.clone(), StringOrVec::Vec(Vec::new()));
         }
       }
     }
@@ -958,4 +977,92 @@ mod tests {
       &[("/TEST", Some(("/TEST", 0, 5))), ("/test", None)],
     );
   }
+
+  #[test]
+  fn test_literal_special_characters() {
+    test_path(
+      "/mod.ts\\?v=:version",
+      None,
+      &[
+        ("/mod.ts?v=1.0.0", Some(("/mod.ts?v=1.0.0", 0, 15))),
+        ("/modxts?v=1.0.0", None),
+        ("/mod.tv=1.0.0", None),
+      ],
+    );
+  }
+
+  #[test]
+  fn test_parse_custom_delimiter() {
+    let schema = "/x/:module@:version?/:path*";
+    let tokens = parse(
+      schema,
+      Some(ParseOptions {
+        delimiter: Some("/@#?".to_string()),
+        prefixes: Some("./@".to_string()),
+      }),
+    )
+    .unwrap();
+    assert_eq!(tokens.len(), 4);
+    match &tokens[2] {
+      Token::Key(k) => {
+        assert_eq!(k.name, StringOrNumber::String("version".to_string()));
+        assert_eq!(k.prefix, Some("@".to_string()));
+        assert_eq!(k.pattern, "[^/@#?]+?");
+        assert_eq!(k.modifier, Some("?".to_string()));
+      }
+      _ => panic!("expected a key"),
+    }
+    let matcher = Matcher::new(
+      &tokens,
+      Some(TokensToRegexOptions {
+        delimiter: Some("/@#?".to_string()),
+        ..Default::default()
+      }),
+    )
+    .unwrap();
+    let result = matcher.matches("/x/std@0.1.0/fs/mod.ts").unwrap();
+    assert!(
+      matches!(result.get("module"), Some(StringOrVec::String(s)) if s == "std")
+    );
+    assert!(
+      matches!(result.get("version"), Some(StringOrVec::String(s)) if s == "0.1.0")
+    );
+    let compiler = Compiler::new(&tokens, None);
+    assert_eq!(
+      compiler.to_path(&result.params).unwrap(),
+      "/x/std@0.1.0/fs/mod.ts"
+    );
+    let result = matcher.matches("/x/std/fs/mod.ts").unwrap();
+    assert!(
+      matches!(result.get("module"), Some(StringOrVec::String(s)) if s == "std")
+    );
+    assert!(result.get("version").is_none());
+    assert_eq!(
+      compiler.to_path(&result.params).unwrap(),
+      "/x/std/fs/mod.ts"
+    );
+  }
+
+  #[test]
+  fn test_matcher_case_sensitivity() {
+    let tokens = parse("/X/:module([a-z0-9_]*)", None).unwrap();
+    let matcher = Matcher::new(&tokens, None).unwrap();
+    let result = matcher.matches("/x/Std").expect("should match");
+    assert_eq!(result.path, "/x/Std");
+    assert!(
+      matches!(result.get("module"), Some(StringOrVec::String(s)) if s == "Std")
+    );
+    assert!(matcher.matches("/X/std").is_some());
+    let matcher = Matcher::new(
+      &tokens,
+      Some(TokensToRegexOptions {
+        sensitive: true,
+        ..Default::default()
+      }),
+    )
+    .unwrap();
+    assert!(matcher.matches("/x/std").is_none());
+    assert!(matcher.matches("/X/Std").is_none());
+    assert!(matcher.matches("/X/std").is_some());
+  }
 }
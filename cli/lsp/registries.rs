@@ -6,36 +6,128 @@ use super::path_to_regex::Compiler;
 use super::path_to_regex::Key;
 use super::path_to_regex::MatchResult;
 use super::path_to_regex::Matcher;
+use super::path_to_regex::ParseOptions;
 use super::path_to_regex::StringOrNumber;
 use super::path_to_regex::StringOrVec;
 use super::path_to_regex::Token;
+use super::path_to_regex::TokensToCompilerOptions;
+use super::path_to_regex::TokensToRegexOptions;
 
 use crate::deno_dir;
 use crate::file_fetcher::CacheSetting;
+use crate::file_fetcher::File;
 use crate::file_fetcher::FileFetcher;
+use crate::fs_util::canonicalize_path;
 use crate::http_cache::HttpCache;
+use crate::http_util::CacheSemantics;
+use crate::http_util::ErrorStatus;
+use crate::http_util::HeadersMap;
+use crate::http_util::TooManyRequests;
+use crate::version::get_user_agent;
 
+use chrono::DateTime;
 use deno_core::anyhow::anyhow;
 use deno_core::anyhow::Context;
 use deno_core::error::AnyError;
+use deno_core::futures::future;
+use deno_core::parking_lot::Mutex;
 use deno_core::resolve_url;
+use deno_core::serde::de;
 use deno_core::serde::Deserialize;
 use deno_core::serde_json;
 use deno_core::serde_json::json;
 use deno_core::serde_json::Value;
 use deno_core::url::ParseError;
-use deno_core::url::Position;
 use deno_core::url::Url;
 use deno_core::ModuleSpecifier;
+use deno_runtime::deno_fetch::create_http_client;
+use deno_runtime::deno_fetch::reqwest;
+use deno_runtime::deno_tls::create_client_config;
 use deno_runtime::deno_web::BlobStore;
 use deno_runtime::permissions::Permissions;
+use log::debug;
 use log::error;
+use log::info;
+use log::warn;
 use lspower::lsp;
+use notify::event::Event as NotifyEvent;
+use notify::event::EventKind;
+use notify::Error as NotifyError;
+use notify::RecommendedWatcher;
+use notify::RecursiveMode;
+use notify::Watcher;
 use regex::Regex;
+use semver_parser::version::parse as semver_parse;
+use semver_parser::version::Identifier;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::future::Future;
+use std::io::Read;
 use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use tokio::sync::mpsc;
+use tokio::sync::watch;
+use tokio::sync::Semaphore;
 
 const CONFIG_PATH: &str = "/.well-known/deno-import-intellisense.json";
+/// The content type of a variable endpoint response which contains an item
+/// per line instead of a single JSON document.
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+/// The `Accept` header sent when fetching documentation, which lets an
+/// endpoint serve documentation which isn't JSON in a format the client can
+/// display, with a `Content-Type` which says which format it is in.
+const DOCUMENTATION_ACCEPT: &str =
+  "application/json, text/markdown, text/plain;q=0.9";
+/// The version of the intellisense protocol supported by the client, which a
+/// registry configuration can require a minimum of with `minClientVersion`.
+const CLIENT_PROTOCOL_VERSION: u32 = 1;
+/// The default maximum number of completion items returned for a single
+/// completion request.
+const DEFAULT_MAX_COMPLETION_ITEMS: usize = 250;
+/// How many times the maximum number of completion items are read from a
+/// response whose items are fuzzy matched, which bounds how many items are
+/// parsed and scored for each completion request.
+const FUZZY_ITEMS_FACTOR: usize = 10;
+/// The default maximum number of requests to registries in flight at once.
+const DEFAULT_MAX_CONCURRENT_FETCHES: usize = 6;
+/// The default length of time parsed variable items are kept in memory.
+const DEFAULT_ITEMS_CACHE_TTL: Duration = Duration::from_secs(3);
+/// The default length of time a request for variable items which failed is
+/// not retried for, which is kept short so that an endpoint which was only
+/// missing for a moment recovers quickly.
+const DEFAULT_FAILED_ITEMS_TTL: Duration = Duration::from_secs(1);
+/// The maximum number of endpoint responses, and of documentation responses,
+/// kept in memory at once.
+const DEFAULT_ITEMS_CACHE_CAPACITY: usize = 100;
+/// The default minimum length of time between requests for variable items to
+/// the same origin.
+const DEFAULT_MIN_FETCH_INTERVAL: Duration = Duration::from_millis(100);
+/// How long to wait for a response from a registry before giving up.
+const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a registry configuration that was served without any caching
+/// headers is cached for by default.
+const DEFAULT_CONFIG_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+/// How many times a request to a registry is retried after a transient error.
+const DEFAULT_MAX_RETRIES: u32 = 2;
+/// How long to wait before the first retry of a request to a registry, which
+/// doubles for each subsequent retry.
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(100);
+/// The longest a registry can ask for requests to it to be held off for with
+/// a `Retry-After` header.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+/// The maximum number of pages of variable items fetched for a single
+/// completion by following the `nextUrl` of each page.
+const MAX_ITEMS_PAGES: usize = 3;
 const COMPONENT: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS
   .add(b' ')
   .add(b'"')
@@ -64,10 +156,373 @@ const COMPONENT: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS
 lazy_static::lazy_static! {
   static ref REPLACEMENT_VARIABLE_RE: Regex =
     Regex::new(r"\$\{\{?(\w+)\}?\}").unwrap();
+  static ref HTML_TAG_RE: Regex =
+    Regex::new(r"(?s)<(/?)([a-zA-Z][a-zA-Z0-9]*)([^>]*)>").unwrap();
+  static ref HREF_RE: Regex =
+    Regex::new(r#"(?i)href\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+  static ref ASSERTION_TYPE_RE: Regex =
+    Regex::new(r#"\btype\s*:\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+  static ref BLANK_LINES_RE: Regex = Regex::new(r"\n\s*\n\s*\n+").unwrap();
 }
 
+/// The origin of a URL. `file:` URLs have an opaque origin, so all of the
+/// registries kept on the local file system share the origin `file://`, and
+/// are instead told apart by their base.
 fn base_url(url: &Url) -> String {
-  url.origin().ascii_serialization()
+  if is_local(url) {
+    "file://".to_string()
+  } else {
+    url.origin().ascii_serialization()
+  }
+}
+
+/// Determine if a URL is for the local file system, where a registry
+/// configuration can be kept within a workspace.
+fn is_local(url: &Url) -> bool {
+  url.scheme() == "file"
+}
+
+/// Determine if a URL can belong to a registry, which is any URL with an
+/// origin, along with `file:` URLs.
+fn is_registry_url(url: &Url) -> bool {
+  url.origin().is_tuple() || is_local(url)
+}
+
+/// Parse the origin or base of a registry, rejecting URLs with an opaque
+/// origin, like `data:` URLs, which can't be registries and would otherwise
+/// all serialize to the same `"null"` origin.
+fn parse_registry_url(origin: &str) -> Result<Url, AnyError> {
+  let url = Url::parse(origin)?;
+  if is_registry_url(&url) {
+    Ok(url)
+  } else {
+    Err(anyhow!(
+      "The URL \"{}\" does not have an origin which can be used as a registry.",
+      origin
+    ))
+  }
+}
+
+/// Determine the base of a registry, which is the origin of the URL along with
+/// any path prefix that the registry is served from, without a trailing slash.
+fn registry_base(url: &Url) -> String {
+  format!("{}{}", base_url(url), url.path().trim_end_matches('/'))
+}
+
+/// Determine the base a registry enabled for an origin is stored under, which
+/// is the origin itself, except for a local registry, which is the directory
+/// the registry is kept in.
+fn enabled_base(url: &Url) -> String {
+  if is_local(url) {
+    registry_base(url)
+  } else {
+    base_url(url)
+  }
+}
+
+/// A string specifier from the client whose host was typed in Unicode, like
+/// `http://münchen.example/x/`, converted to the ASCII form of the host, which
+/// is the form that the bases of the enabled registries are stored in. This
+/// allows the specifier to be completed as if it had been typed in ASCII, and
+/// the completions to be converted back into the form that was typed.
+struct AsciiHost {
+  /// The specifier with its host in ASCII.
+  specifier: String,
+  /// The scheme and authority of the specifier, as typed.
+  typed: String,
+  /// The scheme and authority of the specifier, with the host in ASCII.
+  ascii: String,
+}
+
+impl AsciiHost {
+  /// Convert a string specifier if its host isn't ASCII, returning `None` when
+  /// it is already ASCII or the host isn't valid.
+  fn parse(current_specifier: &str) -> Option<Self> {
+    let (scheme, rest) = current_specifier.split_once("://")?;
+    let end = rest.find(&['/', '?', '#'][..]).unwrap_or(rest.len());
+    let authority = &rest[..end];
+    if authority.is_ascii() {
+      return None;
+    }
+    let url = Url::parse(&format!("{}://{}/", scheme, authority)).ok()?;
+    let typed = format!("{}://{}", scheme, authority);
+    let ascii = url.as_str().trim_end_matches('/').to_string();
+    let specifier = format!("{}{}", ascii, &rest[end..]);
+    Some(Self {
+      specifier,
+      typed,
+      ascii,
+    })
+  }
+
+  /// The string specifier and offset to complete, which are converted to ASCII
+  /// when the host of the specifier was typed in Unicode.
+  fn to_ascii<'a>(
+    maybe_ascii_host: &'a Option<Self>,
+    current_specifier: &'a str,
+    offset: usize,
+  ) -> (&'a str, usize) {
+    match maybe_ascii_host {
+      Some(ascii_host) => (
+        ascii_host.specifier.as_str(),
+        ascii_host.to_ascii_offset(offset),
+      ),
+      None => (current_specifier, offset),
+    }
+  }
+
+  /// The difference in UTF-16 code units between the length of the authority
+  /// in ASCII and as typed.
+  fn delta(&self) -> i64 {
+    self.ascii.encode_utf16().count() as i64
+      - self.typed.encode_utf16().count() as i64
+  }
+
+  /// Convert an offset in UTF-16 code units of the specifier as typed into an
+  /// offset of the specifier in ASCII.
+  fn to_ascii_offset(&self, offset: usize) -> usize {
+    if offset >= self.typed.encode_utf16().count() {
+      (offset as i64 + self.delta()) as usize
+    } else {
+      offset
+    }
+  }
+
+  /// Convert the completions of the specifier in ASCII into completions of the
+  /// specifier as typed, where `range` is the range of the whole specifier.
+  fn to_typed_list(
+    &self,
+    mut list: lsp::CompletionList,
+    range: &lsp::Range,
+  ) -> lsp::CompletionList {
+    let authority_end =
+      range.start.character as i64 + self.ascii.encode_utf16().count() as i64;
+    let to_typed_position = |position: lsp::Position| {
+      if position.line == range.start.line
+        && position.character as i64 >= authority_end
+      {
+        lsp::Position {
+          line: position.line,
+          character: (position.character as i64 - self.delta()) as u32,
+        }
+      } else {
+        position
+      }
+    };
+    let to_typed_text = |text: &mut String| {
+      if let Some(rest) = text.strip_prefix(&self.ascii) {
+        *text = format!("{}{}", self.typed, rest);
+      }
+    };
+    for item in list.items.iter_mut() {
+      if let Some(filter_text) = item.filter_text.as_mut() {
+        to_typed_text(filter_text);
+      }
+      match item.text_edit.as_mut() {
+        Some(lsp::CompletionTextEdit::Edit(edit)) => {
+          to_typed_text(&mut edit.new_text);
+          // the range of the whole specifier is already as typed
+          if edit.range != *range {
+            edit.range.start = to_typed_position(edit.range.start);
+            edit.range.end = to_typed_position(edit.range.end);
+          }
+        }
+        Some(lsp::CompletionTextEdit::InsertAndReplace(edit)) => {
+          to_typed_text(&mut edit.new_text);
+        }
+        None => (),
+      }
+    }
+    list
+  }
+}
+
+/// Determine if `s` starts with `prefix`, ignoring the case of ASCII
+/// characters, as the scheme and host of a URL are case insensitive.
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+  match s.get(..prefix.len()) {
+    Some(start) => start.eq_ignore_ascii_case(prefix),
+    None => false,
+  }
+}
+
+/// Convert an offset in UTF-16 code units, which is how positions are measured
+/// by the LSP, into a byte index of `s` which is on a character boundary. The
+/// index is clamped to the length of `s`.
+fn to_byte_index(s: &str, offset: usize) -> usize {
+  let mut utf16_offset = 0;
+  for (index, c) in s.char_indices() {
+    if utf16_offset >= offset {
+      return index;
+    }
+    utf16_offset += c.len_utf16();
+  }
+  s.len()
+}
+
+/// Determine if an error fetching a resource from a registry is transient, like
+/// a connection error or a server error, and so worth retrying. Client errors,
+/// like a resource not being found, are not.
+fn is_transient_error(err: &AnyError) -> bool {
+  if let Some(err) = err.downcast_ref::<ErrorStatus>() {
+    return err.status.is_server_error();
+  }
+  match err.downcast_ref::<reqwest::Error>() {
+    // a request which couldn't be sent is only retried when the connection
+    // failed, like when it was reset, and not when the request is invalid
+    Some(err) => {
+      err.is_connect()
+        || err.is_timeout()
+        || err.is_body()
+        || (err.is_request() && has_io_source(err))
+    }
+    None => false,
+  }
+}
+
+/// Determine if an error was caused by an I/O error.
+fn has_io_source(err: &(dyn std::error::Error + 'static)) -> bool {
+  let mut source = err.source();
+  while let Some(err) = source {
+    if err.is::<std::io::Error>() {
+      return true;
+    }
+    source = err.source();
+  }
+  false
+}
+
+/// Determine how long a registry asked for requests to be held off, when the
+/// error is for a `429 Too Many Requests` response with a `Retry-After` header
+/// of either a number of seconds or an HTTP date. The delay is capped at
+/// `MAX_RETRY_AFTER`.
+fn get_retry_after(err: &AnyError) -> Option<Duration> {
+  let retry_after = err
+    .downcast_ref::<TooManyRequests>()?
+    .retry_after
+    .as_deref()?
+    .trim();
+  let delay = if let Ok(secs) = retry_after.parse::<u64>() {
+    Duration::from_secs(secs)
+  } else {
+    let date = DateTime::parse_from_rfc2822(retry_after).ok()?;
+    // a date too far in the future to be represented is held off for as long
+    // as possible
+    SystemTime::UNIX_EPOCH
+      .checked_add(Duration::from_secs(date.timestamp().max(0) as _))
+      .map_or(MAX_RETRY_AFTER, |date| {
+        date.duration_since(SystemTime::now()).unwrap_or_default()
+      })
+  };
+  Some(delay.min(MAX_RETRY_AFTER))
+}
+
+/// Percent-decode the path of a specifier so that it can be matched against a
+/// schema. Characters which have a structural meaning in a path are left
+/// encoded, so that an encoded `/` in a module name, for example, is not
+/// mistaken for the start of another segment.
+fn decode_path(path: &str) -> String {
+  let bytes = path.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    if bytes[i] == b'%'
+      && i + 2 < bytes.len()
+      && bytes[i + 1].is_ascii_hexdigit()
+      && bytes[i + 2].is_ascii_hexdigit()
+    {
+      let b = u8::from_str_radix(&path[i + 1..i + 3], 16).unwrap();
+      if !matches!(b, b'/' | b'?' | b'#' | b'@' | b'%') {
+        decoded.push(b);
+        i += 3;
+        continue;
+      }
+    }
+    decoded.push(bytes[i]);
+    i += 1;
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Join the base of a registry and a path which is relative to it, making sure
+/// there is a single `/` between them whether or not the base has a trailing
+/// slash or the path a leading one.
+fn join_base_str(base: &str, path: &str) -> String {
+  let base = base.trim_end_matches('/');
+  if path.is_empty() || path.starts_with(['?', '#']) {
+    format!("{}{}", base, path)
+  } else {
+    format!("{}/{}", base, path.trim_start_matches('/'))
+  }
+}
+
+/// Resolve a path which is relative to the base of a registry.
+fn join_base(base: &str, path: &str) -> Result<ModuleSpecifier, ParseError> {
+  Url::parse(&join_base_str(base, path))
+}
+
+/// A fetch of variable items that is needed to complete a specifier for a
+/// registry. These are gathered for all of the registries of an origin, so
+/// that the fetches can be made concurrently.
+#[allow(clippy::large_enum_variant)]
+enum PendingItems<'a> {
+  /// Items for a key of a schema that matched the specifier.
+  Key {
+    registry: &'a RegistryConfiguration,
+    tokens: &'a [Token],
+    last_key_name: StringOrNumber,
+    match_result: MatchResult,
+    /// The values matched from the specifier along with the defaults of the
+    /// keys which weren't matched, which fill the templates of the variable.
+    template_match: MatchResult,
+    key: Key,
+    prefix: Option<String>,
+    index: usize,
+    variable: &'a RegistryConfigurationVariable,
+  },
+  /// Items for the first key of a schema, when the specifier only matches the
+  /// prefix of that key.
+  FirstKey {
+    registry: &'a RegistryConfiguration,
+    tokens: &'a [Token],
+    last_key_name: StringOrNumber,
+    key: &'a Key,
+    prefix: &'a str,
+    variable: &'a RegistryConfigurationVariable,
+  },
+}
+
+/// The details shared by the completion items for the variable items of a
+/// key, which are worked out once for all of the items.
+struct KeyItems<'a> {
+  registry: &'a RegistryConfiguration,
+  tokens: &'a [Token],
+  /// The index of the key in the tokens.
+  index: usize,
+  last_key_name: &'a StringOrNumber,
+  key: &'a Key,
+  variable: &'a RegistryConfigurationVariable,
+  specifier: &'a ModuleSpecifier,
+  range: &'a lsp::Range,
+  warning: Option<String>,
+  deprecated: HashSet<String>,
+  preselect: Option<String>,
+}
+
+/// The outcome of completing a specifier against the enabled registries,
+/// which tells apart the reasons that no registry completions were provided.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CompletionOutcome {
+  /// The specifier does not belong to an enabled registry origin.
+  NotAnOrigin,
+  /// The specifier belongs to a registry origin, but the cursor is before the
+  /// path of the specifier.
+  BeforePath,
+  /// None of the schemas of the registries matched the specifier.
+  NoMatch,
+  /// A schema of the registries matched the specifier. The list can be empty,
+  /// in which case no other sources of completions should be looked for.
+  Matched(lsp::CompletionList),
 }
 
 #[derive(Debug)]
@@ -137,18 +592,299 @@ fn get_completor_type(
   None
 }
 
+/// Determine the range of the segment of a specifier that completing the key
+/// at `index` of the tokens replaces, which is the value matched for the key,
+/// or when the prefix of the key hasn't been typed yet, what has been typed of
+/// the prefix. The `range` is of the whole specifier, and `base_len` is the
+/// length in bytes of the base of the registry at the start of the specifier.
+fn get_segment_range(
+  current_specifier: &str,
+  base_len: usize,
+  range: &lsp::Range,
+  tokens: &[Token],
+  index: usize,
+  match_result: &MatchResult,
+  path_offset: usize,
+) -> lsp::Range {
+  let value_len = |k: &Key| match &k.name {
+    StringOrNumber::String(name) => match_result
+      .get(name)
+      .map_or(0, |s| s.to_string(Some(k)).chars().count()),
+    _ => 0,
+  };
+  let mut start = 0;
+  for token in &tokens[..index] {
+    match token {
+      Token::String(s) => start += s.chars().count(),
+      Token::Key(k) => {
+        start += k.prefix.as_deref().unwrap_or_default().chars().count();
+        start += value_len(k);
+        start += k.suffix.as_deref().unwrap_or_default().chars().count();
+      }
+    }
+  }
+  let (start, end) = match &tokens[index] {
+    Token::Key(k) => {
+      let prefix_len = k.prefix.as_deref().unwrap_or_default().chars().count();
+      if path_offset < start + prefix_len {
+        (start, path_offset)
+      } else {
+        let start = start + prefix_len;
+        (start, start + value_len(k))
+      }
+    }
+    Token::String(_) => (start, path_offset),
+  };
+  let position = |offset| lsp::Position {
+    line: range.start.line,
+    character: range.start.character
+      + to_specifier_offset(current_specifier, base_len, offset) as u32,
+  };
+  lsp::Range {
+    start: position(start),
+    end: position(end),
+  }
+}
+
+/// Convert an offset in characters of the decoded path of a specifier, which
+/// starts at the byte index `path_start`, into an offset in UTF-16 code units
+/// of the specifier.
+fn to_specifier_offset(
+  specifier: &str,
+  path_start: usize,
+  offset: usize,
+) -> usize {
+  let path = specifier.get(path_start..).unwrap_or_default();
+  let index = path
+    .char_indices()
+    .map(|(index, _)| index)
+    .chain(std::iter::once(path.len()))
+    .find(|index| decode_path(&path[..*index]).chars().count() >= offset)
+    .unwrap_or(path.len());
+  specifier[..path_start + index].encode_utf16().count()
+}
+
+/// Determine the characters which accept a completion for the key at `index`
+/// of the tokens, which is the first character that follows the value of the
+/// key in the schema. Returns `None` when nothing follows the key.
+fn get_commit_characters(
+  tokens: &[Token],
+  index: usize,
+) -> Option<Vec<String>> {
+  let follows = match tokens.get(index)? {
+    Token::Key(Key {
+      suffix: Some(suffix),
+      ..
+    }) if !suffix.is_empty() => suffix.as_str(),
+    _ => match tokens.get(index + 1)? {
+      Token::String(s) => s.as_str(),
+      Token::Key(k) => k.prefix.as_deref()?,
+    },
+  };
+  follows.chars().next().map(|c| vec![c.to_string()])
+}
+
+/// Determine the tokens of the path which omits the optional key at `index`,
+/// for the `latest` item of the key. Any prefix of the key is omitted along
+/// with it, and so is the literal which separates a key without a prefix from
+/// the rest of its segment, like the `@` of `/x/:module@:version?`.
+fn get_latest_tokens(tokens: &[Token], index: usize) -> Vec<Token> {
+  let mut latest = tokens[..index].to_vec();
+  let has_prefix = matches!(
+    tokens.get(index),
+    Some(Token::Key(Key { prefix: Some(prefix), .. })) if !prefix.is_empty()
+  );
+  if !has_prefix {
+    if let Some(Token::String(s)) = latest.last_mut() {
+      let len = s
+        .trim_end_matches(|c: char| c != '/' && !c.is_alphanumeric())
+        .len();
+      s.truncate(len);
+    }
+  }
+  latest
+}
+
+/// Order completion items by their sort text and then their label, so that
+/// the completions for a specifier are always returned in the same order,
+/// even to clients which don't order them by their sort text.
+fn sort_completions(items: &mut [lsp::CompletionItem]) {
+  items.sort_by(|a, b| {
+    a.sort_text
+      .cmp(&b.sort_text)
+      .then_with(|| a.label.cmp(&b.label))
+  });
+}
+
+/// Determine the detail of a completion item for a key, which is the
+/// description of the item falling back to the name of the key, along with any
+/// warning the registry has about the item.
+fn get_detail(
+  description: Option<String>,
+  key: &Key,
+  maybe_warning: Option<&str>,
+) -> Option<String> {
+  let detail = description.unwrap_or_else(|| format!("({})", key.name));
+  match maybe_warning {
+    Some(warning) => Some(format!("{} - Warning: {}", detail, warning)),
+    None => Some(detail),
+  }
+}
+
+/// Determine the tags of a completion item for a variable item, marking the
+/// items that the registry has flagged as deprecated.
+fn get_tags(
+  item: &str,
+  deprecated: &HashSet<String>,
+) -> Option<Vec<lsp::CompletionItemTag>> {
+  if deprecated.contains(item) {
+    Some(vec![lsp::CompletionItemTag::DEPRECATED])
+  } else {
+    None
+  }
+}
+
+/// The minimum length of the query for a variable, if the value typed for its
+/// key is shorter than it, in which case the items of the variable shouldn't be
+/// requested yet.
+fn get_min_query_length(
+  variable: &RegistryConfigurationVariable,
+  typed: &str,
+) -> Option<usize> {
+  variable
+    .min_query_length
+    .filter(|min_query_length| typed.chars().count() < *min_query_length)
+}
+
+/// A placeholder completion item offered in place of the items of a variable
+/// until enough of the value of its key has been typed. Accepting the item
+/// leaves the specifier as it is.
+fn get_min_query_placeholder(
+  key: &Key,
+  min_query_length: usize,
+  current_specifier: &str,
+  range: &lsp::Range,
+) -> lsp::CompletionItem {
+  lsp::CompletionItem {
+    label: "keep typing to search\u{2026}".to_string(),
+    kind: Some(lsp::CompletionItemKind::TEXT),
+    detail: Some(format!(
+      "({} needs at least {} characters)",
+      key.name, min_query_length
+    )),
+    sort_text: Some("0".to_string()),
+    filter_text: Some(current_specifier.to_string()),
+    text_edit: Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+      range: *range,
+      new_text: current_specifier.to_string(),
+    })),
+    ..Default::default()
+  }
+}
+
+/// Insert a completion item for each of the examples of a variable, which are
+/// offered in place of its items when its endpoint has none. The examples sort
+/// after any other completions and, as they aren't real values, accepting one
+/// leaves the specifier as it is.
+fn insert_example_items(
+  completions: &mut HashMap<String, lsp::CompletionItem>,
+  variable: &RegistryConfigurationVariable,
+  key: &Key,
+  current_specifier: &str,
+  range: &lsp::Range,
+) {
+  for (idx, example) in variable.examples.iter().enumerate() {
+    completions.insert(
+      format!("{}\0example\0{}", key.name, example),
+      lsp::CompletionItem {
+        label: example.clone(),
+        kind: Some(lsp::CompletionItemKind::TEXT),
+        detail: Some(format!("(example {})", key.name)),
+        sort_text: Some(format!("~{:0>10}", idx + 1)),
+        filter_text: Some(current_specifier.to_string()),
+        text_edit: Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+          range: *range,
+          new_text: current_specifier.to_string(),
+        })),
+        ..Default::default()
+      },
+    );
+  }
+}
+
+/// The documentation format of a response with a content type, if the content
+/// type is one that documentation can be displayed in.
+fn get_content_type_format(content_type: &str) -> Option<DocumentationFormat> {
+  let essence = content_type.split(';').next().unwrap_or_default().trim();
+  match essence.to_ascii_lowercase().as_str() {
+    "text/markdown" => Some(DocumentationFormat::Markdown),
+    "text/plain" => Some(DocumentationFormat::Plaintext),
+    "text/html" => Some(DocumentationFormat::Html),
+    _ => None,
+  }
+}
+
+/// The specifier that a completion item inserts, if it inserts one. Snippets,
+/// which insert the tabstops of a schema, and placeholders, which insert the
+/// specifier as it is, don't.
+fn get_completion_specifier(
+  item: &lsp::CompletionItem,
+) -> Option<ModuleSpecifier> {
+  if item.insert_text_format == Some(lsp::InsertTextFormat::SNIPPET)
+    || item.kind == Some(lsp::CompletionItemKind::TEXT)
+  {
+    return None;
+  }
+  match item.text_edit.as_ref()? {
+    lsp::CompletionTextEdit::Edit(edit) => {
+      ModuleSpecifier::parse(&edit.new_text).ok()
+    }
+    lsp::CompletionTextEdit::InsertAndReplace(edit) => {
+      ModuleSpecifier::parse(&edit.new_text).ok()
+    }
+  }
+}
+
+/// Generate the label details of a completion item for a variable item, which
+/// shows the description of the item, or otherwise the name of the key it
+/// completes, alongside the label.
+fn get_label_details(
+  description: Option<&str>,
+  key: &Key,
+) -> lsp::CompletionItemLabelDetails {
+  lsp::CompletionItemLabelDetails {
+    detail: None,
+    description: Some(
+      description.map_or_else(|| key.name.to_string(), String::from),
+    ),
+  }
+}
+
+/// Generate the documentation of a completion item from the inline description
+/// of a variable item, so that the client doesn't need to resolve the item to
+/// show it. Items without a description are documented lazily through the
+/// `data` of the item instead.
+fn get_documentation(description: Option<&str>) -> Option<lsp::Documentation> {
+  description.map(|value| {
+    lsp::Documentation::MarkupContent(lsp::MarkupContent {
+      kind: lsp::MarkupKind::Markdown,
+      value: value.to_string(),
+    })
+  })
+}
+
 /// Generate a data value for a completion item that will instruct the client to
 /// resolve the completion item to obtain further information, in this case, the
 /// details/documentation endpoint for the item if it exists in the registry
 /// configuration
 fn get_data(
-  registry: &RegistryConfiguration,
+  variable: &RegistryConfigurationVariable,
   base: &ModuleSpecifier,
-  variable: &Key,
+  key: &Key,
   value: &str,
 ) -> Option<Value> {
-  let url = registry.get_documentation_url_for_key(variable)?;
-  get_endpoint(url, base, variable, Some(value))
+  let url = variable.documentation.as_deref()?;
+  get_endpoint(url, base, key, Some(value))
     .ok()
     .map(|specifier| json!({ "documentation": specifier }))
 }
@@ -178,10 +914,24 @@ fn get_endpoint_with_match(
   match_result: &MatchResult,
   maybe_value: Option<&str>,
 ) -> Result<ModuleSpecifier, AnyError> {
-  let mut url = url.to_string();
+  let url = fill_template(variable, url, tokens, match_result, maybe_value);
+  parse_url_with_base(&url, base)
+}
+
+/// Replace the variables in a templated string, like the URL or the body of a
+/// request for variable items, with the values matched from a specifier. The
+/// variable being completed is replaced with `maybe_value`, or blanked out.
+fn fill_template(
+  variable: &Key,
+  template: &str,
+  tokens: &[Token],
+  match_result: &MatchResult,
+  maybe_value: Option<&str>,
+) -> String {
+  let mut template = template.to_string();
   let has_value = maybe_value.is_some();
   if has_value {
-    url = replace_variable(&url, variable, maybe_value);
+    template = replace_variable(&template, variable, maybe_value);
   }
   for (key, value) in match_result.params.iter() {
     if let StringOrNumber::String(name) = key {
@@ -189,8 +939,9 @@ fn get_endpoint_with_match(
         Token::Key(k) if k.name == *key => Some(k),
         _ => None,
       });
-      url = url.replace(&format!("${{{}}}", name), &value.to_string(maybe_key));
-      url = url.replace(
+      template = template
+        .replace(&format!("${{{}}}", name), &value.to_string(maybe_key));
+      template = template.replace(
         &format!("${{{{{}}}}}", name),
         &percent_encoding::percent_encode(
           value.to_string(maybe_key).as_bytes(),
@@ -201,9 +952,25 @@ fn get_endpoint_with_match(
     }
   }
   if !has_value {
-    url = replace_variable(&url, variable, None);
+    template = replace_variable(&template, variable, None);
+  }
+  template
+}
+
+/// Determine which item should be preselected, which is the item that exactly
+/// equals what has been typed for the key when there is one, so that accepting
+/// the completion keeps what was typed, and otherwise the item that the
+/// registry preselected.
+fn get_typed_preselect(
+  items: &[ItemWithDescription],
+  partial: &str,
+  preselect: Option<String>,
+) -> Option<String> {
+  if !partial.is_empty() && items.iter().any(|(item, _)| item == partial) {
+    Some(partial.to_string())
+  } else {
+    preselect
   }
-  parse_url_with_base(&url, base)
 }
 
 /// Based on the preselect response from the registry, determine if this item
@@ -216,6 +983,78 @@ fn get_preselect(item: String, preselect: Option<String>) -> Option<bool> {
   }
 }
 
+/// Escape the characters that have a special meaning in the LSP snippet
+/// syntax, so that the text is inserted literally.
+fn escape_snippet(s: &str) -> String {
+  s.replace('\\', "\\\\")
+    .replace('$', "\\$")
+    .replace('}', "\\}")
+}
+
+/// Lay out the tokens of a schema as a snippet, where each key becomes a
+/// numbered tabstop placeholder and everything else is literal text.
+fn get_snippet(tokens: &[Token]) -> String {
+  let mut snippet = String::new();
+  let mut tabstop = 0;
+  for token in tokens {
+    match token {
+      Token::String(s) => snippet.push_str(&escape_snippet(s)),
+      Token::Key(k) => {
+        tabstop += 1;
+        if let Some(prefix) = &k.prefix {
+          snippet.push_str(&escape_snippet(prefix));
+        }
+        snippet.push_str(&format!(
+          "${{{}:{}}}",
+          tabstop,
+          escape_snippet(&k.name.to_string())
+        ));
+        if let Some(suffix) = &k.suffix {
+          snippet.push_str(&escape_snippet(suffix));
+        }
+      }
+    }
+  }
+  snippet
+}
+
+/// The path of a schema up to where its first key starts, which is the path
+/// that the items of the first key are completed for. Returns `None` if the
+/// schema doesn't have any keys.
+fn get_first_key_path(tokens: &[Token]) -> Option<String> {
+  let mut path = String::new();
+  for token in tokens {
+    match token {
+      Token::String(s) => path.push_str(s),
+      Token::Key(k) => {
+        if let Some(prefix) = &k.prefix {
+          path.push_str(prefix);
+        }
+        return Some(path);
+      }
+    }
+  }
+  None
+}
+
+/// Score how well the partial `query` matches the `candidate` as a case
+/// insensitive subsequence, where lower scores are better matches. Returns
+/// `None` if the query is not a subsequence of the candidate.
+fn get_fuzzy_score(query: &str, candidate: &str) -> Option<usize> {
+  let mut score = 0;
+  let mut chars = candidate.chars().flat_map(char::to_lowercase).enumerate();
+  let mut maybe_last = None;
+  for q in query.chars().flat_map(char::to_lowercase) {
+    let (pos, _) = chars.find(|(_, c)| *c == q)?;
+    score += match maybe_last {
+      Some(last) => pos - last - 1,
+      None => pos,
+    };
+    maybe_last = Some(pos);
+  }
+  Some(score)
+}
+
 fn parse_replacement_variables<S: AsRef<str>>(s: S) -> Vec<String> {
   REPLACEMENT_VARIABLE_RE
     .captures_iter(s.as_ref())
@@ -256,28 +1095,255 @@ fn replace_variable(
   }
 }
 
-/// Validate a registry configuration JSON structure.
-fn validate_config(config: &RegistryConfigurationJson) -> Result<(), AnyError> {
-  if config.version < 1 || config.version > 2 {
-    return Err(anyhow!(
-      "Invalid registry configuration. Expected version 1 or 2 got {}.",
-      config.version
-    ));
+/// An error describing why a registry configuration is invalid.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum RegistryConfigError {
+  /// The configuration doesn't have a `version`, or it isn't an integer, in
+  /// which case the type that was found is included.
+  MissingVersion { found: Option<&'static str> },
+  /// The version of the configuration is not supported.
+  UnsupportedVersion { got: u32 },
+  /// The configuration requires a newer version of the intellisense protocol
+  /// than the client supports.
+  UnsupportedClientVersion { required: u32 },
+  /// The schema of a registry could not be parsed.
+  InvalidSchema { schema: String, message: String },
+  /// The schema of a registry doesn't start with a `/`, and so would never
+  /// match the path of a specifier.
+  RelativeSchema { schema: String },
+  /// A key appears more than once in the schema of a registry.
+  DuplicateKey { schema: String, key: String },
+  /// The default of a variable is for a key that isn't optional, or isn't a
+  /// value that the key could match.
+  InvalidDefault {
+    schema: String,
+    variable: String,
+    default: String,
+  },
+  /// A key in the schema of a registry has no variable declaration.
+  MissingVariable { schema: String, key: String },
+  /// A variable is declared that has no matching key in the schema.
+  MissingKey { schema: String, variable: String },
+  /// The url of a variable references the variable itself, which is only
+  /// supported in version 2 or later.
+  SelfReference {
+    schema: String,
+    variable: String,
+    url: String,
+    documentation: bool,
+  },
+  /// The completions for a variable are requested with a method other than
+  /// `GET` or `POST`.
+  UnsupportedMethod {
+    schema: String,
+    variable: String,
+    method: String,
+  },
+  /// The `when` condition of a variable can't be parsed.
+  InvalidCondition {
+    schema: String,
+    variable: String,
+    condition: String,
+  },
+  /// The `when` condition of a variable references a key that isn't in the
+  /// schema.
+  MissingConditionKey {
+    schema: String,
+    variable: String,
+    key: String,
+  },
+  /// None of the variables of a key apply when the keys their conditions
+  /// reference have the described values.
+  UnresolvableKey {
+    schema: String,
+    key: String,
+    condition: String,
+  },
+  /// The url of a variable doesn't resolve to a valid URL.
+  InvalidUrl {
+    schema: String,
+    variable: String,
+    url: String,
+    message: String,
+    documentation: bool,
+  },
+  /// The url of a variable references a key that is to the right of the
+  /// variable in the schema.
+  OutOfScopeVariable {
+    schema: String,
+    variable: String,
+    referenced: String,
+    url: String,
+    documentation: bool,
+  },
+}
+
+impl std::error::Error for RegistryConfigError {}
+
+impl fmt::Display for RegistryConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Invalid registry configuration. ")?;
+    match self {
+      Self::MissingVersion { found: None } => {
+        write!(f, "The configuration is missing the required integer field \"version\".")
+      }
+      Self::MissingVersion { found: Some(found) } => write!(
+        f,
+        "The required field \"version\" must be an integer, but is {}.",
+        found
+      ),
+      Self::UnsupportedVersion { got } => {
+        write!(f, "Expected version 1, 2 or 3 got {}.", got)
+      }
+      Self::UnsupportedClientVersion { required } => write!(
+        f,
+        "The registry requires client protocol v{}, but only v{} is supported.",
+        required, CLIENT_PROTOCOL_VERSION
+      ),
+      Self::InvalidSchema { schema, message } => {
+        write!(f, "Registry schema \"{}\" is invalid. {}", schema, message)
+      }
+      Self::RelativeSchema { schema } => write!(f, "Registry schema \"{}\" does not start with \"/\", but schemas are matched against the path of a specifier, which always does.", schema),
+      Self::InvalidDefault { schema, variable, default } => write!(f, "Registry with schema \"{}\" has the default \"{}\" for variable \"{}\", but only an optional key can have a default, which must be a value the key can match.", schema, default, variable),
+      Self::DuplicateKey { schema, key } => write!(f, "Registry with schema \"{}\" uses the key \"{}\" more than once, but each key in a schema must be unique.", schema, key),
+      Self::MissingVariable { schema, key } => write!(f, "Registry with schema \"{}\" is missing variable declaration for key \"{}\".", schema, key),
+      Self::MissingKey { schema, variable } => write!(f, "Registry with schema \"{}\" is missing a path parameter in schema for variable \"{}\".", schema, variable),
+      Self::SelfReference {
+        schema,
+        variable,
+        url,
+        documentation,
+      } => write!(f, "{} \"{}\" (for variable \"{}\" in registry with schema \"{}\") uses variable \"{}\", which is not allowed because that would be a self reference.", url_kind(*documentation), url, variable, schema, variable),
+      Self::UnsupportedMethod {
+        schema,
+        variable,
+        method,
+      } => write!(f, "Registry with schema \"{}\" uses the unsupported method \"{}\" for variable \"{}\". Expected \"GET\" or \"POST\".", schema, method, variable),
+      Self::InvalidCondition {
+        schema,
+        variable,
+        condition,
+      } => write!(f, "Registry with schema \"{}\" has an invalid condition \"{}\" for variable \"{}\". Expected a condition like `key == \"\"` or `key != \"\"`.", schema, condition, variable),
+      Self::MissingConditionKey {
+        schema,
+        variable,
+        key,
+      } => write!(f, "Registry with schema \"{}\" has a condition for variable \"{}\" on key \"{}\", which is not in the schema.", schema, variable, key),
+      Self::UnresolvableKey {
+        schema,
+        key,
+        condition,
+      } => write!(f, "Registry with schema \"{}\" has no variable for key \"{}\" which applies when {}.", schema, key, condition),
+      Self::InvalidUrl {
+        schema,
+        variable,
+        url,
+        message,
+        documentation,
+      } => write!(f, "{} \"{}\" (for variable \"{}\" in registry with schema \"{}\") does not resolve to a valid URL. {}", url_kind(*documentation), url, variable, schema, message),
+      Self::OutOfScopeVariable {
+        schema,
+        variable,
+        referenced,
+        url,
+        documentation,
+      } => write!(f, "{} \"{}\" (for variable \"{}\" in registry with schema \"{}\") uses variable \"{}\", which is not allowed because the schema defines \"{}\" to the right of \"{}\".", url_kind(*documentation), url, variable, schema, referenced, referenced, variable),
+    }
   }
-  for registry in &config.registries {
-    let (_, keys) = string_to_regex(&registry.schema, None)?;
-    let key_names: Vec<String> = keys.map_or_else(Vec::new, |keys| {
-      keys
-        .iter()
-        .filter_map(|k| {
-          if let StringOrNumber::String(s) = &k.name {
-            Some(s.clone())
-          } else {
-            None
+}
+
+fn url_kind(documentation: bool) -> &'static str {
+  if documentation {
+    "Documentation url"
+  } else {
+    "Url"
+  }
+}
+
+/// Describe the type of a JSON value, for errors about the value.
+fn json_type(value: &Value) -> &'static str {
+  match value {
+    Value::Null => "null",
+    Value::Bool(_) => "a boolean",
+    Value::Number(number) if number.is_i64() => "a negative number",
+    Value::Number(_) => "a non-integer number",
+    Value::String(_) => "a string",
+    Value::Array(_) => "an array",
+    Value::Object(_) => "an object",
+  }
+}
+
+/// Parse the source of a registry configuration. The version of the
+/// configuration is checked before the rest of it is deserialized, so that a
+/// configuration without a valid version gets a clearer error than the one
+/// from serde.
+fn parse_config(source: &str) -> Result<RegistryConfigurationJson, AnyError> {
+  let value: Value = serde_json::from_str(source)?;
+  if let Value::Object(map) = &value {
+    match map.get("version") {
+      Some(Value::Number(version)) if version.is_u64() => (),
+      maybe_version => {
+        return Err(
+          RegistryConfigError::MissingVersion {
+            found: maybe_version.map(json_type),
           }
-        })
-        .collect()
+          .into(),
+        )
+      }
+    }
+  }
+  Ok(serde_json::from_value(value)?)
+}
+
+/// Validate a registry configuration JSON structure.
+fn validate_config(
+  config: &RegistryConfigurationJson,
+) -> Result<(), RegistryConfigError> {
+  if config.version < 1 || config.version > 3 {
+    return Err(RegistryConfigError::UnsupportedVersion {
+      got: config.version,
     });
+  }
+  if let Some(required) = config.min_client_version {
+    if required > CLIENT_PROTOCOL_VERSION {
+      return Err(RegistryConfigError::UnsupportedClientVersion { required });
+    }
+  }
+  let base = Url::parse("https://registry.invalid/").unwrap();
+  for registry in &config.registries {
+    if !registry.schema.starts_with('/') {
+      return Err(RegistryConfigError::RelativeSchema {
+        schema: registry.schema.clone(),
+      });
+    }
+    let (_, keys) = string_to_regex(&registry.schema, None).map_err(|err| {
+      RegistryConfigError::InvalidSchema {
+        schema: registry.schema.clone(),
+        message: err.to_string(),
+      }
+    })?;
+    let keys = keys.unwrap_or_default();
+    let key_names: Vec<String> = keys
+      .iter()
+      .filter_map(|k| {
+        if let StringOrNumber::String(s) = &k.name {
+          Some(s.clone())
+        } else {
+          None
+        }
+      })
+      .collect();
+
+    // keys are looked up by name, so a repeated key would only ever refer to
+    // its first occurrence
+    for (i, key_name) in key_names.iter().enumerate() {
+      if key_names[..i].contains(key_name) {
+        return Err(RegistryConfigError::DuplicateKey {
+          schema: registry.schema.clone(),
+          key: key_name.clone(),
+        });
+      }
+    }
 
     for key_name in &key_names {
       if !registry
@@ -286,34 +1352,249 @@ fn validate_config(config: &RegistryConfigurationJson) -> Result<(), AnyError> {
         .map(|var| var.key.to_owned())
         .any(|x| x == *key_name)
       {
-        return Err(anyhow!("Invalid registry configuration. Registry with schema \"{}\" is missing variable declaration for key \"{}\".", registry.schema, key_name));
+        return Err(RegistryConfigError::MissingVariable {
+          schema: registry.schema.clone(),
+          key: key_name.clone(),
+        });
       }
     }
 
     for variable in &registry.variables {
       let key_index = key_names.iter().position(|key| *key == variable.key);
-      let key_index = key_index.ok_or_else(||anyhow!("Invalid registry configuration. Registry with schema \"{}\" is missing a path parameter in schema for variable \"{}\".", registry.schema, variable.key))?;
+      let key_index =
+        key_index.ok_or_else(|| RegistryConfigError::MissingKey {
+          schema: registry.schema.clone(),
+          variable: variable.key.clone(),
+        })?;
 
-      let replacement_variables = parse_replacement_variables(&variable.url);
-      let limited_keys = key_names.get(0..key_index).unwrap();
-      for v in replacement_variables {
-        if variable.key == v && config.version == 1 {
-          return Err(anyhow!("Invalid registry configuration. Url \"{}\" (for variable \"{}\" in registry with schema \"{}\") uses variable \"{}\", which is not allowed because that would be a self reference.", variable.url, variable.key, registry.schema, v));
+      if let Some(default) = &variable.default {
+        let name = StringOrNumber::String(variable.key.clone());
+        let key = keys.iter().find(|k| k.name == name).unwrap();
+        let is_optional = matches!(key.modifier.as_deref(), Some("?" | "*"));
+        // a pattern which can't be compiled here is left to the matcher
+        let is_match = match Regex::new(&format!("^(?:{})$", key.pattern)) {
+          Ok(re) => re.is_match(default),
+          Err(_) => true,
+        };
+        if !is_optional || default.is_empty() || !is_match {
+          return Err(RegistryConfigError::InvalidDefault {
+            schema: registry.schema.clone(),
+            variable: variable.key.clone(),
+            default: default.clone(),
+          });
         }
+      }
 
-        let key_index = limited_keys.iter().position(|key| key == &v);
-
-        if key_index.is_none() && variable.key != v {
-          return Err(anyhow!("Invalid registry configuration. Url \"{}\" (for variable \"{}\" in registry with schema \"{}\") uses variable \"{}\", which is not allowed because the schema defines \"{}\" to the right of \"{}\".", variable.url, variable.key, registry.schema, v, v, variable.key));
+      if let Some(method) = &variable.method {
+        if !method.eq_ignore_ascii_case("GET")
+          && !method.eq_ignore_ascii_case("POST")
+        {
+          return Err(RegistryConfigError::UnsupportedMethod {
+            schema: registry.schema.clone(),
+            variable: variable.key.clone(),
+            method: method.clone(),
+          });
         }
       }
-    }
-  }
 
-  Ok(())
+      if let Some(when) = &variable.when {
+        let condition = VariableCondition::parse(when).ok_or_else(|| {
+          RegistryConfigError::InvalidCondition {
+            schema: registry.schema.clone(),
+            variable: variable.key.clone(),
+            condition: when.clone(),
+          }
+        })?;
+        if !key_names.contains(&condition.key) {
+          return Err(RegistryConfigError::MissingConditionKey {
+            schema: registry.schema.clone(),
+            variable: variable.key.clone(),
+            key: condition.key,
+          });
+        }
+      }
+
+      let limited_keys = key_names.get(0..key_index).unwrap();
+      let mut urls = vec![(false, &variable.url)];
+      if let Some(documentation) = &variable.documentation {
+        urls.push((true, documentation));
+      }
+      for (documentation, url) in urls {
+        let replacement_variables = parse_replacement_variables(url);
+        for v in replacement_variables {
+          if variable.key == v && config.version == 1 {
+            return Err(RegistryConfigError::SelfReference {
+              schema: registry.schema.clone(),
+              variable: variable.key.clone(),
+              url: url.clone(),
+              documentation,
+            });
+          }
+
+          let key_index = limited_keys.iter().position(|key| key == &v);
+
+          if key_index.is_none() && variable.key != v {
+            return Err(RegistryConfigError::OutOfScopeVariable {
+              schema: registry.schema.clone(),
+              variable: variable.key.clone(),
+              referenced: v,
+              url: url.clone(),
+              documentation,
+            });
+          }
+        }
+
+        // a placeholder is substituted for each of the variables, so that a
+        // template which could never be a URL is caught here instead of when
+        // items are fetched
+        let filled = REPLACEMENT_VARIABLE_RE.replace_all(url, "x");
+        if let Err(err) = parse_url_with_base(&filled, &base) {
+          return Err(RegistryConfigError::InvalidUrl {
+            schema: registry.schema.clone(),
+            variable: variable.key.clone(),
+            url: url.clone(),
+            message: err.to_string(),
+            documentation,
+          });
+        }
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// Validate a registry configuration and compile the schemas of its
+/// registries, so that the configuration is ready to be used.
+fn compile_config(
+  mut config: RegistryConfigurationJson,
+) -> Result<RegistryConfigurationJson, AnyError> {
+  validate_config(&config)?;
+  for registry in config.registries.iter_mut() {
+    if config.version < 3 {
+      registry.documentation_format = None;
+    }
+    let compiled = CompiledSchema::new(registry)?;
+    check_keys_resolve(registry, &compiled.tokens)?;
+    registry.compiled = Some(Arc::new(compiled));
+  }
+  Ok(config)
+}
+
+/// Check, without making any requests, that every key of a compiled schema
+/// has a variable which applies to it whatever the values matched for the keys
+/// referenced by conditions. This reports a misconfigured registry when it is
+/// enabled, instead of it silently missing completions.
+fn check_keys_resolve(
+  registry: &RegistryConfiguration,
+  tokens: &[Token],
+) -> Result<(), RegistryConfigError> {
+  for token in tokens {
+    let name = match token {
+      Token::Key(Key {
+        name: StringOrNumber::String(name),
+        ..
+      }) => name,
+      _ => continue,
+    };
+    // a condition only holds for either an empty or a non-empty value of a
+    // single key, so the variables apply whatever the values are only when
+    // one of them has no condition, or when there are conditions on both an
+    // empty and a non-empty value of the same key
+    let mut conditions: HashMap<String, bool> = HashMap::new();
+    let mut resolves = false;
+    for variable in registry.variables.iter().filter(|v| v.key == *name) {
+      match variable.when.as_deref().and_then(VariableCondition::parse) {
+        Some(condition) => match conditions.get(&condition.key) {
+          Some(empty) if *empty != condition.empty => resolves = true,
+          _ => {
+            conditions.insert(condition.key, condition.empty);
+          }
+        },
+        None => resolves = true,
+      }
+    }
+    if resolves {
+      continue;
+    }
+    // otherwise none of them apply when each key has the value its
+    // conditions don't hold for
+    let mut conditions: Vec<(String, bool)> = conditions.into_iter().collect();
+    conditions.sort();
+    let condition = conditions
+      .iter()
+      .map(|(key, empty)| {
+        let op = if *empty { "!=" } else { "==" };
+        format!("`{} {} \"\"`", key, op)
+      })
+      .collect::<Vec<_>>()
+      .join(" and ");
+    return Err(RegistryConfigError::UnresolvableKey {
+      schema: registry.schema.clone(),
+      key: name.clone(),
+      condition,
+    });
+  }
+  Ok(())
+}
+
+/// Validate the source of a registry configuration, like the content of a
+/// `deno-import-intellisense.json` file, without enabling the registry. The
+/// schemas are compiled as they would be when the registry is enabled, so
+/// that a configuration which validates here can't fail to be enabled.
+pub fn validate_config_str(source: &str) -> Result<(), AnyError> {
+  compile_config(parse_config(source)?)?;
+  Ok(())
+}
+
+/// Match a specifier against a single registry schema, without enabling a
+/// registry, returning the values matched for each key of the schema, or
+/// `None` if the specifier doesn't match. The specifier can be a URL, in which
+/// case its path is matched, or just a path. The values of repeated keys are
+/// joined by their prefix, and a repeated key that matched nothing has an
+/// empty value.
+pub fn match_schema(
+  schema: &str,
+  specifier: &str,
+) -> Result<Option<HashMap<String, String>>, AnyError> {
+  let registry = RegistryConfiguration {
+    schema: schema.to_string(),
+    ..Default::default()
+  };
+  let compiled = CompiledSchema::new(&registry)?;
+  let path = match Url::parse(specifier) {
+    Ok(url) => url.path().to_string(),
+    Err(_) => specifier.to_string(),
+  };
+  let match_result =
+    match compiled.matchers[compiled.tokens.len()].matches(&path) {
+      Some(match_result) => match_result,
+      None => return Ok(None),
+    };
+  let params = compiled
+    .tokens
+    .iter()
+    .filter_map(|token| match token {
+      Token::Key(key) => {
+        let value = match match_result.params.get(&key.name) {
+          Some(StringOrVec::String(value)) => value.clone(),
+          Some(StringOrVec::Vec(values)) => values.join(&format!(
+            "{}{}",
+            key.suffix.clone().unwrap_or_default(),
+            key.prefix.clone().unwrap_or_default()
+          )),
+          None => return None,
+        };
+        Some((key.name.to_string(), value))
+      }
+      Token::String(_) => None,
+    })
+    .collect();
+  Ok(Some(params))
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub(crate) struct RegistryConfigurationVariable {
   /// The name of the variable.
   key: String,
@@ -323,775 +1604,9068 @@ pub(crate) struct RegistryConfigurationVariable {
   /// The URL with variable substitutions of the endpoint that will provide
   /// completions for the variable.
   url: String,
+  /// An optional hint of the kind of completion items for the variable. When
+  /// not provided, the last variable of a schema is completed as files and
+  /// the others as folders.
+  kind: Option<VariableKind>,
+  /// The HTTP method used to request the completions for the variable, which
+  /// is either `GET`, the default, or `POST`.
+  method: Option<String>,
+  /// The body to send when the completions are requested with a `POST`, with
+  /// the same variable substitutions as the `url`.
+  body: Option<String>,
+  /// An optional condition on the value matched for another key, like
+  /// `version == ""`, which allows a schema to declare several variables for
+  /// the same key, of which the first one whose condition is met is used.
+  when: Option<String>,
+  /// The value used in place of the key of the variable in the templates of
+  /// other variables when the key is optional and no value was matched for
+  /// it, like `latest` for an omitted version.
+  default: Option<String>,
+  /// The number of characters of the value of the key that need to be typed
+  /// before the completions for the variable are requested, for registries
+  /// which are too large to return all of their items. Until then, a
+  /// placeholder item is offered instead.
+  min_query_length: Option<usize>,
+  /// Example values of the key, which are offered when the endpoint for the
+  /// variable has no items, to show what the values look like.
+  #[serde(default)]
+  examples: Vec<String>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub(crate) struct RegistryConfiguration {
-  /// A Express-like path which describes how URLs are composed for a registry.
-  schema: String,
-  /// The variables denoted in the `schema` should have a variable entry.
-  variables: Vec<RegistryConfigurationVariable>,
+impl RegistryConfigurationVariable {
+  /// The template of the body to post when requesting the completions for the
+  /// variable, which is only present when they are requested with a `POST`.
+  fn get_body(&self) -> Option<&str> {
+    match self.method.as_deref() {
+      Some(method) if method.eq_ignore_ascii_case("POST") => {
+        Some(self.body.as_deref().unwrap_or_default())
+      }
+      _ => None,
+    }
+  }
+
+  /// Determine if the variable applies to the values matched from a
+  /// specifier, based on its `when` condition, if any.
+  fn applies(&self, params: &HashMap<StringOrNumber, StringOrVec>) -> bool {
+    match self.when.as_deref().and_then(VariableCondition::parse) {
+      Some(condition) => condition.is_met(params),
+      None => true,
+    }
+  }
 }
 
-impl RegistryConfiguration {
-  fn get_url_for_key(&self, key: &Key) -> Option<&str> {
-    self.variables.iter().find_map(|v| {
-      if key.name == StringOrNumber::String(v.key.clone()) {
-        Some(v.url.as_str())
-      } else {
-        None
+/// The condition of a variable on the value matched for another key. To keep
+/// conditions simple, the value can only be compared with an empty string,
+/// like `version == ""` or `version != ""`, where a key that wasn't matched
+/// has an empty value.
+#[derive(Debug, Clone, PartialEq)]
+struct VariableCondition {
+  key: String,
+  /// If `true` the value must be empty, otherwise it must not be empty.
+  empty: bool,
+}
+
+impl VariableCondition {
+  fn parse(condition: &str) -> Option<Self> {
+    let (key, value, empty) = match condition.split_once("!=") {
+      Some((key, value)) => (key, value, false),
+      None => {
+        let (key, value) = condition.split_once("==")?;
+        (key, value, true)
       }
+    };
+    let key = key.trim();
+    if key.is_empty()
+      || !key.chars().all(|c| c.is_alphanumeric() || c == '_')
+      || value.trim() != "\"\""
+    {
+      return None;
+    }
+    Some(Self {
+      key: key.to_string(),
+      empty,
     })
   }
 
-  fn get_documentation_url_for_key(&self, key: &Key) -> Option<&str> {
-    self.variables.iter().find_map(|v| {
-      if key.name == StringOrNumber::String(v.key.clone()) {
-        v.documentation.as_deref()
-      } else {
-        None
-      }
-    })
+  fn is_met(&self, params: &HashMap<StringOrNumber, StringOrVec>) -> bool {
+    let is_empty = params
+      .get(&StringOrNumber::String(self.key.clone()))
+      .map(|value| value.to_string(None).is_empty())
+      .unwrap_or(true);
+    is_empty == self.empty
   }
 }
 
-/// A structure that represents the configuration of an origin and its module
-/// registries.
-#[derive(Debug, Deserialize)]
-struct RegistryConfigurationJson {
-  version: u32,
-  registries: Vec<RegistryConfiguration>,
+/// The kind of completion items a registry would like a variable to be
+/// completed as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum VariableKind {
+  Module,
+  Folder,
+  File,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct VariableItemsList {
-  pub items: Vec<String>,
-  #[serde(default)]
-  pub is_incomplete: bool,
-  pub preselect: Option<String>,
+impl From<VariableKind> for lsp::CompletionItemKind {
+  fn from(kind: VariableKind) -> Self {
+    match kind {
+      VariableKind::Module => lsp::CompletionItemKind::MODULE,
+      VariableKind::Folder => lsp::CompletionItemKind::FOLDER,
+      VariableKind::File => lsp::CompletionItemKind::FILE,
+    }
+  }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
-enum VariableItems {
-  Simple(Vec<String>),
-  List(VariableItemsList),
+/// The parsed tokens of a registry schema along with a matcher for each prefix
+/// of those tokens. These are built once when a configuration is fetched, so
+/// that completion requests don't have to re-parse the schema and recompile
+/// the regular expressions on every keystroke.
+#[derive(Debug)]
+struct CompiledSchema {
+  tokens: Vec<Token>,
+  /// The matchers, indexed by the number of leading tokens they match.
+  matchers: Vec<Matcher>,
 }
 
-/// A structure which holds the information about currently configured module
-/// registries and can provide completion information for URLs that match
-/// one of the enabled registries.
-#[derive(Debug, Clone)]
-pub struct ModuleRegistry {
-  origins: HashMap<String, Vec<RegistryConfiguration>>,
-  file_fetcher: FileFetcher,
+impl CompiledSchema {
+  fn new(registry: &RegistryConfiguration) -> Result<Self, AnyError> {
+    // the delimiters also act as the prefixes of keys, so that a key which
+    // follows a delimiter can be omitted along with it
+    let (delimiter, prefixes) = match &registry.delimiters {
+      Some(delimiters) => (
+        Some(format!("{}#?", delimiters)),
+        Some(format!(".{}", delimiters)),
+      ),
+      None => (None, None),
+    };
+    let tokens = parse(
+      &registry.schema,
+      Some(ParseOptions {
+        delimiter: delimiter.clone(),
+        prefixes,
+      }),
+    )?;
+    let matchers = (0..=tokens.len())
+      .map(|i| {
+        Matcher::new(
+          &tokens[..i],
+          Some(TokensToRegexOptions {
+            sensitive: !registry.case_insensitive,
+            delimiter: delimiter.clone(),
+            ..Default::default()
+          }),
+        )
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(Self { tokens, matchers })
+  }
 }
 
-impl Default for ModuleRegistry {
-  fn default() -> Self {
-    // This only gets used when creating the tsc runtime and for testing, and so
-    // it shouldn't ever actually access the DenoDir, so it doesn't support a
-    // custom root.
-    let dir = deno_dir::DenoDir::new(None).unwrap();
-    let location = dir.root.join("registries");
-    Self::new(&location)
+fn is_true() -> bool {
+  true
+}
+
+/// The format of documentation provided by a registry, used when a
+/// documentation endpoint responds with a bare string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DocumentationFormat {
+  Markdown,
+  Plaintext,
+  /// HTML, which is converted to markdown before it is displayed.
+  Html,
+}
+
+impl From<DocumentationFormat> for lsp::MarkupKind {
+  fn from(format: DocumentationFormat) -> Self {
+    match format {
+      DocumentationFormat::Markdown | DocumentationFormat::Html => {
+        lsp::MarkupKind::Markdown
+      }
+      DocumentationFormat::Plaintext => lsp::MarkupKind::PlainText,
+    }
   }
 }
 
-impl ModuleRegistry {
-  pub fn new(location: &Path) -> Self {
-    let http_cache = HttpCache::new(location);
-    let mut file_fetcher = FileFetcher::new(
-      http_cache,
-      CacheSetting::RespectHeaders,
-      true,
-      None,
-      BlobStore::default(),
-      None,
-    )
-    .context("Error creating file fetcher in module registry.")
-    .unwrap();
-    file_fetcher.set_download_log_level(super::logging::lsp_log_level());
+/// Push text from HTML, collapsing each run of whitespace into a single space
+/// like a browser does.
+fn push_collapsed(markdown: &mut String, text: &str) {
+  let mut in_whitespace = false;
+  for c in text.chars() {
+    if c.is_whitespace() {
+      if !in_whitespace {
+        markdown.push(' ');
+      }
+      in_whitespace = true;
+    } else {
+      markdown.push(c);
+      in_whitespace = false;
+    }
+  }
+}
 
-    Self {
-      origins: HashMap::new(),
-      file_fetcher,
+fn decode_html_entities(s: &str) -> String {
+  s.replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&quot;", "\"")
+    .replace("&#39;", "'")
+    .replace("&nbsp;", " ")
+    .replace("&amp;", "&")
+}
+
+/// Convert HTML documentation from a registry to markdown. Elements which have
+/// a markdown equivalent are converted, and all other markup is dropped along
+/// with the content of scripts and styles, so that no HTML from the registry
+/// is rendered by the client.
+fn html_to_markdown(html: &str) -> String {
+  let mut markdown = String::new();
+  let mut hrefs: Vec<Option<String>> = Vec::new();
+  let mut in_pre = false;
+  let mut in_code = false;
+  let mut skip_until: Option<String> = None;
+  let mut last = 0;
+  for captures in HTML_TAG_RE.captures_iter(html) {
+    let tag = captures.get(0).unwrap();
+    let text = &html[last..tag.start()];
+    last = tag.end();
+    let closing = &captures[1] == "/";
+    let name = captures[2].to_lowercase();
+    if let Some(until) = &skip_until {
+      if closing && name == *until {
+        skip_until = None;
+      }
+      continue;
+    }
+    if in_pre || in_code {
+      markdown.push_str(&decode_html_entities(text));
+    } else {
+      push_collapsed(&mut markdown, text);
+    }
+    match (name.as_str(), closing) {
+      ("script" | "style", false) => skip_until = Some(name),
+      ("b" | "strong", _) => markdown.push_str("**"),
+      ("i" | "em", _) => markdown.push('_'),
+      ("code", _) if !in_pre => {
+        in_code = !closing;
+        markdown.push('`');
+      }
+      ("pre", false) => {
+        in_pre = true;
+        markdown.push_str("\n\n```\n");
+      }
+      ("pre", true) => {
+        in_pre = false;
+        markdown.push_str("\n```\n\n");
+      }
+      ("a", false) => {
+        let href = HREF_RE.captures(&captures[3]).and_then(|c| {
+          c.get(1)
+            .or_else(|| c.get(2))
+            .map(|m| m.as_str().to_string())
+        });
+        if href.is_some() {
+          markdown.push('[');
+        }
+        hrefs.push(href);
+      }
+      ("a", true) => {
+        if let Some(Some(href)) = hrefs.pop() {
+          markdown.push_str(&format!("]({})", href));
+        }
+      }
+      ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+        let level = name[1..].parse().unwrap_or(1);
+        markdown.push_str(&format!("\n\n{} ", "#".repeat(level)));
+      }
+      ("li", false) => markdown.push_str("\n- "),
+      ("br", _) => markdown.push('\n'),
+      (
+        "p" | "div" | "ul" | "ol" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6",
+        _,
+      ) => markdown.push_str("\n\n"),
+      _ => (),
     }
   }
+  if skip_until.is_none() {
+    push_collapsed(&mut markdown, &html[last..]);
+  }
+  BLANK_LINES_RE
+    .replace_all(markdown.trim(), "\n\n")
+    .to_string()
+}
 
-  fn complete_literal(
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RegistryConfiguration {
+  /// A Express-like path which describes how URLs are composed for a registry.
+  schema: String,
+  /// The variables denoted in the `schema` should have a variable entry.
+  variables: Vec<RegistryConfigurationVariable>,
+  /// The format of bare string documentation returned from the documentation
+  /// endpoints of the registry. Only supported in version 3 or later.
+  documentation_format: Option<DocumentationFormat>,
+  /// The name of an environment variable containing a bearer token to send
+  /// with requests made to the origin of the registry.
+  token_env: Option<String>,
+  /// If `true`, a `latest` item is suggested when completing an optional key,
+  /// which completes to the specifier that omits the key, for registries that
+  /// serve the latest version when it is omitted.
+  #[serde(default)]
+  suggest_latest: bool,
+  /// Defaults to `true`, so the schema matches paths without regard to case.
+  /// Set to `false` for a registry whose paths are case sensitive.
+  #[serde(default = "is_true")]
+  case_insensitive: bool,
+  /// The characters which delimit the segments of the schema, which default
+  /// to `/`. A key which directly follows a delimiter uses it as its prefix.
+  delimiters: Option<String>,
+  /// If `true`, the schema is matched against the query of a specifier along
+  /// with its path. The `?` which starts the query needs to be escaped in the
+  /// schema, like `/:module\?v=:version`.
+  #[serde(default)]
+  match_query: bool,
+  /// If `true`, the schema is matched against the fragment of a specifier
+  /// along with its path, for registries which link to sections of their
+  /// documentation. The `#` which starts the fragment needs to be escaped in
+  /// the schema, like `/docs/:module\#:section`.
+  #[serde(default)]
+  match_fragment: bool,
+  /// If `true`, a schema which starts with a literal, like `/x/`, is only
+  /// suggested once the path has started to be typed past its leading `/`,
+  /// instead of for every path of the origin that the literal starts with,
+  /// which keeps origins with many registries from suggesting all of them.
+  #[serde(default)]
+  anchored: bool,
+  /// The text of an import assertion keyed by a file extension, including
+  /// the leading `.`, like `{ ".json": "with { type: \"json\" }" }`. When a
+  /// completion resolves to a specifier with the extension, the assertion is
+  /// inserted after the specifier as well.
+  #[serde(default)]
+  import_assertions: HashMap<String, String>,
+  /// The compiled form of `schema`, populated when the configuration is
+  /// fetched.
+  #[serde(skip)]
+  compiled: Option<Arc<CompiledSchema>>,
+}
+
+impl RegistryConfiguration {
+  /// The additional edits for a completion which insert the import assertion
+  /// for the extension of the specifier it resolves to, if the registry
+  /// declares one. The longest matching extension is used.
+  fn get_assertion_edits(
     &self,
-    s: String,
-    completions: &mut HashMap<String, lsp::CompletionItem>,
-    current_specifier: &str,
-    offset: usize,
+    specifier: &ModuleSpecifier,
     range: &lsp::Range,
-  ) {
-    let label = if s.starts_with('/') {
-      s[0..].to_string()
-    } else {
-      s.to_string()
+  ) -> Option<Vec<lsp::TextEdit>> {
+    let assertion = self.get_import_assertion(specifier)?;
+    // the range of the specifier excludes its closing quote, which the
+    // assertion follows
+    let position = lsp::Position {
+      line: range.end.line,
+      character: range.end.character + 1,
     };
-    let full_text = format!(
-      "{}{}{}",
-      &current_specifier[..offset],
-      s,
-      &current_specifier[offset..]
-    );
-    let text_edit = Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
-      range: *range,
-      new_text: full_text.clone(),
-    }));
-    let filter_text = Some(full_text);
-    completions.insert(
-      s,
-      lsp::CompletionItem {
-        label,
-        kind: Some(lsp::CompletionItemKind::FOLDER),
-        filter_text,
-        sort_text: Some("1".to_string()),
-        text_edit,
-        ..Default::default()
+    Some(vec![lsp::TextEdit {
+      range: lsp::Range {
+        start: position,
+        end: position,
       },
-    );
+      new_text: format!(" {}", assertion),
+    }])
   }
 
-  /// Disable a registry, removing its configuration, if any, from memory.
-  pub async fn disable(&mut self, origin: &str) -> Result<(), AnyError> {
-    let origin = base_url(&Url::parse(origin)?);
-    self.origins.remove(&origin);
-    Ok(())
+  /// The import assertion for the extension of a specifier, if the registry
+  /// declares one. The longest matching extension is used.
+  fn get_import_assertion(&self, specifier: &ModuleSpecifier) -> Option<&str> {
+    self
+      .import_assertions
+      .iter()
+      .filter(|(extension, _)| specifier.path().ends_with(extension.as_str()))
+      .max_by_key(|(extension, _)| extension.len())
+      .map(|(_, assertion)| assertion.as_str())
   }
 
-  /// Check to see if the given origin has a registry configuration.
-  pub(crate) async fn check_origin(
-    &self,
-    origin: &str,
-  ) -> Result<(), AnyError> {
-    let origin_url = Url::parse(origin)?;
-    let specifier = origin_url.join(CONFIG_PATH)?;
-    self.fetch_config(&specifier).await?;
-    Ok(())
+  /// The arguments of the `deno.cache` command for a specifier. Along with the
+  /// specifier, when the registry declares an import assertion with a `type`
+  /// for the extension of the specifier, the type is passed so that the
+  /// module can be cached as that type.
+  fn get_cache_arguments(&self, specifier: &ModuleSpecifier) -> Vec<Value> {
+    let mut arguments = vec![json!([specifier])];
+    let maybe_type = self
+      .get_import_assertion(specifier)
+      .and_then(|assertion| ASSERTION_TYPE_RE.captures(assertion))
+      .and_then(|captures| captures.get(1).or_else(|| captures.get(2)));
+    if let Some(assertion_type) = maybe_type {
+      arguments.push(json!({ "type": assertion_type.as_str() }));
+    }
+    arguments
   }
 
-  /// Fetch and validate the specifier to a registry configuration, resolving
-  /// with the configuration if valid.
-  async fn fetch_config(
+  /// Create a compiler for the leading `tokens` of the schema, which
+  /// validates values with the same case sensitivity as the schema matchers.
+  fn get_compiler(&self, tokens: &[Token]) -> Compiler {
+    Compiler::new(
+      tokens,
+      Some(TokensToCompilerOptions {
+        sensitive: !self.case_insensitive,
+        ..Default::default()
+      }),
+    )
+  }
+
+  /// The values matched from a specifier along with the defaults of the
+  /// variables whose keys weren't matched, which are used to fill in the
+  /// templates of the variable of `key`. A default is never used for `key`
+  /// itself, as that is the key being completed.
+  fn get_template_match(
     &self,
-    specifier: &ModuleSpecifier,
-  ) -> Result<Vec<RegistryConfiguration>, AnyError> {
-    let fetch_result = self
-      .file_fetcher
-      .fetch(specifier, &mut Permissions::allow_all())
-      .await;
-    // if there is an error fetching, we will cache an empty file, so that
-    // subsequent requests they are just an empty doc which will error without
-    // needing to connect to the remote URL. We will cache it for 1 week.
-    if fetch_result.is_err() {
-      let mut headers_map = HashMap::new();
-      headers_map.insert(
-        "cache-control".to_string(),
-        "max-age=604800, immutable".to_string(),
+    key: &Key,
+    match_result: &MatchResult,
+  ) -> MatchResult {
+    let mut params = match_result.params.clone();
+    for variable in &self.variables {
+      let default = match &variable.default {
+        Some(default) => default,
+        None => continue,
+      };
+      let name = StringOrNumber::String(variable.key.clone());
+      let is_matched = matches!(
+        params.get(&name),
+        Some(value) if !value.to_string(None).is_empty()
       );
-      self
-        .file_fetcher
-        .http_cache
-        .set(specifier, headers_map, &[])?;
+      if name == key.name
+        || is_matched
+        || !variable.applies(&match_result.params)
+      {
+        continue;
+      }
+      params.insert(name, StringOrVec::String(default.clone()));
     }
-    let file = fetch_result?;
-    let config: RegistryConfigurationJson = serde_json::from_str(&file.source)?;
-    validate_config(&config)?;
-    Ok(config.registries)
-  }
-
-  /// Enable a registry by attempting to retrieve its configuration and
-  /// validating it.
-  pub async fn enable(&mut self, origin: &str) -> Result<(), AnyError> {
-    let origin_url = Url::parse(origin)?;
-    let origin = base_url(&origin_url);
-    #[allow(clippy::map_entry)]
-    // we can't use entry().or_insert_with() because we can't use async closures
-    if !self.origins.contains_key(&origin) {
-      let specifier = origin_url.join(CONFIG_PATH)?;
-      let configs = self.fetch_config(&specifier).await?;
-      self.origins.insert(origin, configs);
+    MatchResult {
+      path: match_result.path.clone(),
+      index: match_result.index,
+      params,
     }
+  }
 
-    Ok(())
+  /// Find the variable for a key that applies to the values matched from a
+  /// specifier.
+  fn get_variable_for_key(
+    &self,
+    key: &Key,
+    params: &HashMap<StringOrNumber, StringOrVec>,
+  ) -> Option<&RegistryConfigurationVariable> {
+    self.variables.iter().find(|v| {
+      key.name == StringOrNumber::String(v.key.clone()) && v.applies(params)
+    })
   }
+}
 
-  #[cfg(test)]
-  /// This is only used during testing, as it directly provides the full URL
-  /// for obtaining the registry configuration, versus "guessing" at it.
-  async fn enable_custom(&mut self, specifier: &str) -> Result<(), AnyError> {
-    let specifier = Url::parse(specifier)?;
-    let origin = base_url(&specifier);
-    #[allow(clippy::map_entry)]
-    if !self.origins.contains_key(&origin) {
-      let configs = self.fetch_config(&specifier).await?;
-      self.origins.insert(origin, configs);
-    }
+/// A structure that represents the configuration of an origin and its module
+/// registries.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RegistryConfigurationJson {
+  /// The version of the configuration format.
+  version: u32,
+  /// The minimum version of the intellisense protocol a client must support
+  /// to use the configuration, which is independent of the configuration
+  /// format version.
+  min_client_version: Option<u32>,
+  registries: Vec<RegistryConfiguration>,
+  /// Additional headers to send with every request made to the origin.
+  #[serde(default)]
+  headers: HeadersMap,
+  /// The URLs of other configurations whose registries are included in this
+  /// one, relative to the URL of this configuration.
+  #[serde(default)]
+  includes: Vec<String>,
+}
 
-    Ok(())
-  }
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VariableItemsList {
+  pub items: Vec<String>,
+  #[serde(default)]
+  pub is_incomplete: bool,
+  pub preselect: Option<Preselect>,
+  /// A notice from the registry about the items, like a module being
+  /// deprecated, which is displayed alongside each of the items.
+  pub warning: Option<String>,
+  /// How the items should be ordered, which defaults to the order they were
+  /// returned in.
+  pub sort: Option<ItemsSort>,
+  /// The URL of the next page of items, relative to the URL of this page.
+  pub next_url: Option<String>,
+}
 
-  /// For a string specifier from the client, provide a set of completions, if
-  /// any, for the specifier.
-  pub(crate) async fn get_completions(
-    &self,
-    current_specifier: &str,
-    offset: usize,
-    range: &lsp::Range,
-    specifier_exists: impl Fn(&ModuleSpecifier) -> bool,
-  ) -> Option<lsp::CompletionList> {
-    if let Ok(specifier) = Url::parse(current_specifier) {
-      let origin = base_url(&specifier);
-      let origin_len = origin.chars().count();
-      if offset >= origin_len {
-        if let Some(registries) = self.origins.get(&origin) {
-          let path = &specifier[Position::BeforePath..];
-          let path_offset = offset - origin_len;
-          let mut completions = HashMap::<String, lsp::CompletionItem>::new();
-          let mut is_incomplete = false;
-          let mut did_match = false;
-          for registry in registries {
-            let tokens = parse(&registry.schema, None)
-              .map_err(|e| {
-                error!(
-                  "Error parsing registry schema for origin \"{}\". {}",
-                  origin, e
-                );
-              })
-              .ok()?;
-            let mut i = tokens.len();
-            let last_key_name =
-              StringOrNumber::String(tokens.iter().last().map_or_else(
-                || "".to_string(),
-                |t| {
-                  if let Token::Key(key) = t {
-                    if let StringOrNumber::String(s) = &key.name {
-                      return s.clone();
-                    }
-                  }
-                  "".to_string()
-                },
-              ));
-            loop {
-              let matcher = Matcher::new(&tokens[..i], None)
-                .map_err(|e| {
-                  error!(
-                    "Error creating matcher for schema for origin \"{}\". {}",
-                    origin, e
-                  );
-                })
-                .ok()?;
-              if let Some(match_result) = matcher.matches(path) {
-                did_match = true;
-                let completor_type =
-                  get_completor_type(path_offset, &tokens, &match_result);
-                match completor_type {
-                  Some(CompletorType::Literal(s)) => self.complete_literal(
-                    s,
-                    &mut completions,
-                    current_specifier,
-                    offset,
-                    range,
-                  ),
-                  Some(CompletorType::Key { key, prefix, index }) => {
-                    let maybe_url = registry.get_url_for_key(&key);
-                    if let Some(url) = maybe_url {
-                      if let Some(items) = self
-                        .get_variable_items(
-                          &key,
-                          url,
-                          &specifier,
-                          &tokens,
-                          &match_result,
-                        )
-                        .await
-                      {
-                        let compiler = Compiler::new(&tokens[..=index], None);
-                        let base = Url::parse(&origin).ok()?;
-                        let (items, preselect, incomplete) = match items {
-                          VariableItems::List(list) => {
-                            (list.items, list.preselect, list.is_incomplete)
-                          }
-                          VariableItems::Simple(items) => (items, None, false),
-                        };
-                        if incomplete {
-                          is_incomplete = true;
-                        }
-                        for (idx, item) in items.into_iter().enumerate() {
-                          let label = if let Some(p) = &prefix {
-                            format!("{}{}", p, item)
-                          } else {
-                            item.clone()
-                          };
-                          let kind = if key.name == last_key_name {
-                            Some(lsp::CompletionItemKind::FILE)
-                          } else {
-                            Some(lsp::CompletionItemKind::FOLDER)
-                          };
-                          let mut params = match_result.params.clone();
-                          params.insert(
-                            key.name.clone(),
-                            StringOrVec::from_str(&item, &key),
-                          );
-                          let path =
-                            compiler.to_path(&params).unwrap_or_default();
-                          let item_specifier = base.join(&path).ok()?;
-                          let full_text = item_specifier.as_str();
-                          let text_edit = Some(lsp::CompletionTextEdit::Edit(
-                            lsp::TextEdit {
-                              range: *range,
-                              new_text: full_text.to_string(),
-                            },
-                          ));
-                          let command = if key.name == last_key_name
-                            && !specifier_exists(&item_specifier)
-                          {
-                            Some(lsp::Command {
-                              title: "".to_string(),
-                              command: "deno.cache".to_string(),
-                              arguments: Some(vec![json!([item_specifier])]),
-                            })
-                          } else {
-                            None
-                          };
-                          let detail = Some(format!("({})", key.name));
-                          let filter_text = Some(full_text.to_string());
-                          let sort_text = Some(format!("{:0>10}", idx + 1));
-                          let preselect =
-                            get_preselect(item.clone(), preselect.clone());
-                          let data =
-                            get_data(registry, &specifier, &key, &item);
-                          completions.insert(
-                            item,
-                            lsp::CompletionItem {
-                              label,
-                              kind,
-                              detail,
-                              sort_text,
-                              filter_text,
-                              text_edit,
-                              command,
-                              preselect,
-                              data,
-                              ..Default::default()
-                            },
-                          );
-                        }
-                      }
-                    }
-                  }
-                  None => (),
-                }
-                break;
-              }
-              i -= 1;
-              // If we have fallen though to the first token, and we still
-              // didn't get a match
-              if i == 0 {
-                match &tokens[i] {
-                  // so if the first token is a string literal, we will return
-                  // that as a suggestion
-                  Token::String(s) => {
-                    if s.starts_with(path) {
-                      let label = s.to_string();
-                      let kind = Some(lsp::CompletionItemKind::FOLDER);
-                      let mut url = specifier.clone();
-                      url.set_path(s);
-                      let full_text = url.as_str();
-                      let text_edit =
-                        Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
-                          range: *range,
-                          new_text: full_text.to_string(),
-                        }));
-                      let filter_text = Some(full_text.to_string());
-                      completions.insert(
-                        s.to_string(),
-                        lsp::CompletionItem {
-                          label,
-                          kind,
-                          filter_text,
-                          sort_text: Some("1".to_string()),
-                          text_edit,
-                          preselect: Some(true),
-                          ..Default::default()
-                        },
-                      );
-                    }
-                  }
-                  // if the token though is a key, and the key has a prefix, and
-                  // the path matches the prefix, we will go and get the items
-                  // for that first key and return them.
-                  Token::Key(k) => {
-                    if let Some(prefix) = &k.prefix {
-                      let maybe_url = registry.get_url_for_key(k);
-                      if let Some(url) = maybe_url {
-                        if let Some(items) = self.get_items(url).await {
-                          let base = Url::parse(&origin).ok()?;
-                          let (items, preselect, incomplete) = match items {
-                            VariableItems::List(list) => {
-                              (list.items, list.preselect, list.is_incomplete)
-                            }
-                            VariableItems::Simple(items) => {
-                              (items, None, false)
-                            }
-                          };
-                          if (incomplete) {
-                            is_incomplete = true;
-                          }
-                          for (idx, item) in items.into_iter().enumerate() {
-                            let path = format!("{}{}", prefix, item);
-                            let kind = Some(lsp::CompletionItemKind::FOLDER);
-                            let item_specifier = base.join(&path).ok()?;
-                            let full_text = item_specifier.as_str();
-                            let text_edit = Some(
-                              lsp::CompletionTextEdit::Edit(lsp::TextEdit {
-                                range: *range,
-                                new_text: full_text.to_string(),
-                              }),
-                            );
-                            let command = if k.name == last_key_name
-                              && !specifier_exists(&item_specifier)
-                            {
-                              Some(lsp::Command {
-                                title: "".to_string(),
-                                command: "deno.cache".to_string(),
-                                arguments: Some(vec![json!([item_specifier])]),
-                              })
-                            } else {
-                              None
-                            };
-                            let detail = Some(format!("({})", k.name));
-                            let filter_text = Some(full_text.to_string());
-                            let sort_text = Some(format!("{:0>10}", idx + 1));
-                            let preselect =
-                              get_preselect(item.clone(), preselect.clone());
-                            let data = get_data(registry, &specifier, k, &path);
-                            completions.insert(
-                              item.clone(),
-                              lsp::CompletionItem {
-                                label: item,
-                                kind,
-                                detail,
-                                sort_text,
-                                filter_text,
-                                text_edit,
-                                command,
-                                preselect,
-                                data,
-                                ..Default::default()
-                              },
-                            );
-                          }
-                        }
-                      }
-                    }
-                  }
-                }
-                break;
-              }
-            }
-          }
-          // If we return None, other sources of completions will be looked for
-          // but if we did at least match part of a registry, we should send an
-          // empty vector so that no-completions will be sent back to the client
-          return if completions.is_empty() && !did_match {
-            None
-          } else {
-            Some(lsp::CompletionList {
-              items: completions.into_iter().map(|(_, i)| i).collect(),
-              is_incomplete,
-            })
-          };
-        }
-      }
+impl VariableItemsList {
+  /// Determine which item should be preselected. The LSP only allows a single
+  /// item to be preselected, so when several items are in the preselect list
+  /// the first one in the order returned by the endpoint is used.
+  fn get_preselect(&self) -> Option<String> {
+    match self.preselect.as_ref()? {
+      Preselect::Single(item) => Some(item.clone()),
+      Preselect::Multiple(preselect) => self
+        .items
+        .iter()
+        .find(|item| preselect.contains(item))
+        .cloned(),
     }
-
-    self.get_origin_completions(current_specifier, range)
   }
+}
 
-  pub async fn get_documentation(
-    &self,
-    url: &str,
-  ) -> Option<lsp::Documentation> {
-    let specifier = Url::parse(url).ok()?;
-    let file = self
-      .file_fetcher
-      .fetch(&specifier, &mut Permissions::allow_all())
-      .await
-      .ok()?;
-    serde_json::from_str(&file.source).ok()
+/// How a registry would like a list of items to be ordered when they are
+/// offered as completions.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ItemsSort {
+  /// The items are kept in the order they were returned in.
+  None,
+  /// The items are ordered from the lowest to the highest semver version.
+  SemverAsc,
+  /// The items are ordered from the highest to the lowest semver version.
+  SemverDesc,
+}
+
+/// The key a version is ordered by when sorting items by semver, which ranks
+/// a release above its pre-releases. An optional leading `v` is ignored, and
+/// items which aren't a valid version have no key.
+fn get_semver_key(
+  item: &str,
+) -> Option<(u64, u64, u64, bool, Vec<Identifier>)> {
+  let version = semver_parse(item.strip_prefix('v').unwrap_or(item)).ok()?;
+  Some((
+    version.major,
+    version.minor,
+    version.patch,
+    version.pre.is_empty(),
+    version.pre,
+  ))
+}
+
+/// Order the items as requested by the registry. Items which aren't a valid
+/// version are placed after all of the versions, in the order they were
+/// returned in.
+fn sort_items(items: Vec<String>, sort: ItemsSort) -> Vec<String> {
+  if sort == ItemsSort::None {
+    return items;
   }
+  let (mut versions, others): (Vec<_>, Vec<_>) = items
+    .into_iter()
+    .map(|item| (get_semver_key(&item), item))
+    .partition(|(key, _)| key.is_some());
+  versions.sort_by(|(a, _), (b, _)| {
+    if sort == ItemsSort::SemverDesc {
+      b.cmp(a)
+    } else {
+      a.cmp(b)
+    }
+  });
+  versions
+    .into_iter()
+    .chain(others)
+    .map(|(_, item)| item)
+    .collect()
+}
 
-  pub fn get_origin_completions(
-    &self,
-    current_specifier: &str,
-    range: &lsp::Range,
-  ) -> Option<lsp::CompletionList> {
-    let items = self
-      .origins
-      .keys()
-      .filter_map(|k| {
-        let mut origin = k.as_str().to_string();
-        if origin.ends_with('/') {
-          origin.pop();
-        }
-        if origin.starts_with(current_specifier) {
-          let text_edit = Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
-            range: *range,
-            new_text: origin.clone(),
-          }));
-          Some(lsp::CompletionItem {
-            label: origin,
-            kind: Some(lsp::CompletionItemKind::FOLDER),
-            detail: Some("(registry)".to_string()),
-            sort_text: Some("2".to_string()),
-            text_edit,
-            ..Default::default()
+/// The items a registry would like to have preselected, either as a single
+/// item or as a list of items.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Preselect {
+  Single(String),
+  Multiple(Vec<String>),
+}
+
+/// A variable item along with a short description of it, which is displayed
+/// as the detail of its completion item.
+#[derive(Debug, Clone, Deserialize)]
+struct DescribedVariableItem {
+  value: String,
+  description: Option<String>,
+  /// If the item is deprecated, which is shown by the client by striking
+  /// through the completion item.
+  #[serde(default)]
+  deprecated: bool,
+}
+
+/// The value of a variable item paired with its description, if any.
+type ItemWithDescription = (String, Option<String>);
+
+#[derive(Debug, Clone)]
+enum VariableItems {
+  Simple(Vec<String>),
+  Described(Vec<DescribedVariableItem>),
+  List(VariableItemsList),
+}
+
+/// A single item in the response from a variable endpoint.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum VariableItem {
+  Simple(String),
+  Described(DescribedVariableItem),
+}
+
+impl From<Vec<VariableItem>> for VariableItems {
+  fn from(items: Vec<VariableItem>) -> Self {
+    if items.iter().all(|i| matches!(i, VariableItem::Simple(_))) {
+      Self::Simple(
+        items
+          .into_iter()
+          .filter_map(|i| match i {
+            VariableItem::Simple(value) => Some(value),
+            VariableItem::Described(_) => None,
           })
-        } else {
-          None
-        }
-      })
-      .collect::<Vec<lsp::CompletionItem>>();
-    if !items.is_empty() {
-      Some(lsp::CompletionList {
-        items,
-        is_incomplete: false,
-      })
+          .collect(),
+      )
     } else {
-      None
+      Self::Described(
+        items
+          .into_iter()
+          .map(|i| match i {
+            VariableItem::Simple(value) => DescribedVariableItem {
+              value,
+              description: None,
+              deprecated: false,
+            },
+            VariableItem::Described(item) => item,
+          })
+          .collect(),
+      )
     }
   }
+}
 
-  async fn get_items(&self, url: &str) -> Option<VariableItems> {
-    let specifier = ModuleSpecifier::parse(url).ok()?;
-    let file = self
-      .file_fetcher
-      .fetch(&specifier, &mut Permissions::allow_all())
-      .await
-      .map_err(|err| {
-        error!(
-          "Internal error fetching endpoint \"{}\". {}",
-          specifier, err
-        );
-      })
-      .ok()?;
-    let items: VariableItems = serde_json::from_str(&file.source)
-      .map_err(|err| {
-        error!(
-          "Error parsing response from endpoint \"{}\". {}",
-          specifier, err
-        );
-      })
-      .ok()?;
-    Some(items)
+/// Parse the response from a variable endpoint as it is read, keeping at most
+/// `max_items` of the items and skipping over the rest, so that a response
+/// with a very large number of items is never held in memory in full.
+fn parse_variable_items(
+  source: &str,
+  max_items: Option<usize>,
+) -> Result<VariableItems, serde_json::Error> {
+  let mut deserializer = serde_json::Deserializer::from_str(source);
+  let items = de::DeserializeSeed::deserialize(
+    VariableItemsSeed { max_items },
+    &mut deserializer,
+  )?;
+  deserializer.end()?;
+  Ok(items)
+}
+
+/// Parse a newline delimited JSON response from a variable endpoint, where
+/// each line which isn't blank is a single item, reading at most `max_items`
+/// of the lines so that the rest of a long response is never parsed.
+fn parse_ndjson_items(
+  source: &str,
+  max_items: Option<usize>,
+) -> Result<VariableItems, serde_json::Error> {
+  let items = source
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty())
+    .take(max_items.unwrap_or(usize::MAX))
+    .map(serde_json::from_str::<VariableItem>)
+    .collect::<Result<Vec<_>, _>>()?;
+  Ok(items.into())
+}
+
+/// Determine if the content type of a response from a variable endpoint is
+/// newline delimited JSON.
+fn is_ndjson(maybe_content_type: Option<&str>) -> bool {
+  match maybe_content_type {
+    Some(content_type) => content_type
+      .split(';')
+      .next()
+      .unwrap_or_default()
+      .trim()
+      .eq_ignore_ascii_case(NDJSON_CONTENT_TYPE),
+    None => false,
   }
+}
 
-  async fn get_variable_items(
-    &self,
-    variable: &Key,
-    url: &str,
-    base: &Url,
-    tokens: &[Token],
-    match_result: &MatchResult,
-  ) -> Option<VariableItems> {
-    let specifier =
-      get_endpoint_with_match(variable, url, base, tokens, match_result, None)
-        .map_err(|err| {
-          error!("Internal error mapping endpoint \"{}\". {}", url, err);
-        })
-        .ok()?;
-    let file = self
-      .file_fetcher
-      .fetch(&specifier, &mut Permissions::allow_all())
-      .await
-      .map_err(|err| {
-        error!(
-          "Internal error fetching endpoint \"{}\". {}",
-          specifier, err
-        );
-      })
-      .ok()?;
-    let items: VariableItems = serde_json::from_str(&file.source)
-      .map_err(|err| {
-        error!(
-          "Error parsing response from endpoint \"{}\". {}",
-          specifier, err
-        );
+/// Deserializes either shape of the response from a variable endpoint, which
+/// is a list of items or an object containing the list of items.
+struct VariableItemsSeed {
+  max_items: Option<usize>,
+}
+
+impl<'de> de::DeserializeSeed<'de> for VariableItemsSeed {
+  type Value = VariableItems;
+
+  fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+  where
+    D: de::Deserializer<'de>,
+  {
+    deserializer.deserialize_any(self)
+  }
+}
+
+impl<'de> de::Visitor<'de> for VariableItemsSeed {
+  type Value = VariableItems;
+
+  fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str("a list of items or an object with a list of items")
+  }
+
+  fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+  where
+    A: de::SeqAccess<'de>,
+  {
+    let items = ItemsSeed {
+      max_items: self.max_items,
+    }
+    .visit_seq(seq)?;
+    Ok(items.into())
+  }
+
+  fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+  where
+    A: de::MapAccess<'de>,
+  {
+    let mut items = None;
+    let mut is_incomplete = false;
+    let mut preselect = None;
+    let mut warning = None;
+    let mut sort = None;
+    let mut next_url = None;
+    while let Some(key) = map.next_key::<String>()? {
+      match key.as_str() {
+        "items" => {
+          items = Some(map.next_value_seed(ItemsSeed {
+            max_items: self.max_items,
+          })?)
+        }
+        "isIncomplete" => is_incomplete = map.next_value()?,
+        "preselect" => preselect = map.next_value()?,
+        "warning" => warning = map.next_value()?,
+        "sort" => sort = map.next_value()?,
+        "nextUrl" => next_url = map.next_value()?,
+        _ => {
+          map.next_value::<de::IgnoredAny>()?;
+        }
+      }
+    }
+    let items = items
+      .ok_or_else(|| de::Error::missing_field("items"))?
+      .into_iter()
+      .map(|item| match item {
+        VariableItem::Simple(value) => Ok(value),
+        VariableItem::Described(_) => Err(de::Error::custom(
+          "the items of a list of items must be strings",
+        )),
       })
-      .ok()?;
-    Some(items)
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(VariableItems::List(VariableItemsList {
+      items,
+      is_incomplete,
+      preselect,
+      warning,
+      sort,
+      next_url,
+    }))
   }
 }
 
-#[cfg(test)]
-mod tests {
-  use super::*;
-  use tempfile::TempDir;
+/// Deserializes a list of variable items, keeping at most `max_items` of them.
+struct ItemsSeed {
+  max_items: Option<usize>,
+}
 
-  #[test]
-  fn test_validate_registry_configuration() {
-    assert!(validate_config(&RegistryConfigurationJson {
-      version: 3,
-      registries: vec![],
-    })
-    .is_err());
+impl<'de> de::DeserializeSeed<'de> for ItemsSeed {
+  type Value = Vec<VariableItem>;
 
-    let cfg = RegistryConfigurationJson {
-      version: 1,
-      registries: vec![RegistryConfiguration {
-        schema: "/:module@:version/:path*".to_string(),
-        variables: vec![
-          RegistryConfigurationVariable {
-            key: "module".to_string(),
+  fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+  where
+    D: de::Deserializer<'de>,
+  {
+    deserializer.deserialize_seq(self)
+  }
+}
+
+impl<'de> de::Visitor<'de> for ItemsSeed {
+  type Value = Vec<VariableItem>;
+
+  fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str("a list of items")
+  }
+
+  fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+  where
+    A: de::SeqAccess<'de>,
+  {
+    let max_items = self.max_items.unwrap_or(usize::MAX);
+    let mut items = Vec::new();
+    while items.len() < max_items {
+      match seq.next_element()? {
+        Some(item) => items.push(item),
+        None => return Ok(items),
+      }
+    }
+    while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+    Ok(items)
+  }
+}
+
+impl VariableItems {
+  /// The URL of the next page of items, if any. Only a list of items can be
+  /// followed by another page.
+  fn get_next_url(&self) -> Option<&str> {
+    match self {
+      Self::List(list) => list.next_url.as_deref(),
+      _ => None,
+    }
+  }
+
+  /// The number of items.
+  fn len(&self) -> usize {
+    match self {
+      Self::Simple(items) => items.len(),
+      Self::Described(items) => items.len(),
+      Self::List(list) => list.items.len(),
+    }
+  }
+
+  /// Append the items of the next page to a list of items, where what is
+  /// preselected, if the items are incomplete and the URL of the page after
+  /// are taken from the next page. Returns `None` if the next page has
+  /// described items, which can't be merged into a list.
+  fn append_page(self, next: VariableItems) -> Option<VariableItems> {
+    let mut list = match self {
+      Self::List(list) => list,
+      _ => return None,
+    };
+    match next {
+      Self::Simple(items) => {
+        list.items.extend(items);
+        list.preselect = None;
+        list.is_incomplete = false;
+        list.next_url = None;
+      }
+      Self::List(next) => {
+        list.items.extend(next.items);
+        list.preselect = next.preselect;
+        list.is_incomplete = next.is_incomplete;
+        list.next_url = next.next_url;
+        list.warning = list.warning.or(next.warning);
+      }
+      Self::Described(_) => return None,
+    }
+    Some(Self::List(list))
+  }
+
+  /// The values of the items which the registry has flagged as deprecated.
+  /// Only described items can be flagged.
+  fn get_deprecated(&self) -> HashSet<String> {
+    match self {
+      Self::Described(items) => items
+        .iter()
+        .filter(|i| i.deprecated)
+        .map(|i| i.value.clone())
+        .collect(),
+      _ => HashSet::new(),
+    }
+  }
+
+  /// The notice from the registry about the items, if any. Only a list of
+  /// items can include a notice.
+  fn get_warning(&self) -> Option<&str> {
+    match self {
+      Self::List(list) => list.warning.as_deref(),
+      _ => None,
+    }
+  }
+
+  /// Split the items into their values paired with any description, the item
+  /// that should be preselected, and if the items are incomplete. The items of
+  /// a list are ordered as the list requests.
+  fn into_parts(self) -> (Vec<ItemWithDescription>, Option<String>, bool) {
+    match self {
+      Self::Simple(items) => {
+        (items.into_iter().map(|i| (i, None)).collect(), None, false)
+      }
+      Self::Described(items) => (
+        items
+          .into_iter()
+          .map(|i| (i.value, i.description))
+          .collect(),
+        None,
+        false,
+      ),
+      Self::List(list) => {
+        let preselect = list.get_preselect();
+        (
+          sort_items(list.items, list.sort.unwrap_or(ItemsSort::None))
+            .into_iter()
+            .map(|i| (i, None))
+            .collect(),
+          preselect,
+          list.is_incomplete,
+        )
+      }
+    }
+  }
+}
+
+/// Tracks the generation of the latest completion request, so that a request
+/// which is still in progress can be superseded by a newer one.
+#[derive(Debug, Clone)]
+struct CompletionGeneration {
+  current: Arc<Mutex<u64>>,
+  sender: Arc<watch::Sender<u64>>,
+  receiver: watch::Receiver<u64>,
+}
+
+impl Default for CompletionGeneration {
+  fn default() -> Self {
+    let (sender, receiver) = watch::channel(0);
+    Self {
+      current: Default::default(),
+      sender: Arc::new(sender),
+      receiver,
+    }
+  }
+}
+
+impl CompletionGeneration {
+  /// Start a new generation, superseding the previous one.
+  fn next(&self) -> u64 {
+    let mut current = self.current.lock();
+    *current += 1;
+    // sending only fails when there are no receivers, and one is always held
+    let _ = self.sender.send(*current);
+    *current
+  }
+
+  /// Resolves once the generation has been superseded by a newer one.
+  async fn superseded(&self, generation: u64) {
+    let mut receiver = self.receiver.clone();
+    while *receiver.borrow() == generation {
+      if receiver.changed().await.is_err() {
+        future::pending::<()>().await;
+      }
+    }
+  }
+}
+
+#[derive(Debug)]
+struct MemoryCacheEntry<V> {
+  inserted: Instant,
+  /// The use count of the cache when the entry was last used, which orders
+  /// the entries from least to most recently used.
+  last_used: usize,
+  value: V,
+}
+
+#[derive(Debug)]
+struct MemoryCacheEntries<K, V> {
+  entries: HashMap<K, MemoryCacheEntry<V>>,
+  uses: usize,
+}
+
+impl<K, V> Default for MemoryCacheEntries<K, V> {
+  fn default() -> Self {
+    Self {
+      entries: HashMap::new(),
+      uses: 0,
+    }
+  }
+}
+
+/// An in-memory cache of parsed responses from a registry. Entries expire
+/// once they are older than the time to live, and once the cache is at
+/// capacity the least recently used entry is evicted.
+#[derive(Debug, Clone)]
+struct MemoryCache<K, V> {
+  entries: Arc<Mutex<MemoryCacheEntries<K, V>>>,
+  ttl: Duration,
+  capacity: usize,
+}
+
+/// The endpoint that variable items were requested from, along with the body
+/// that was posted to it, if any.
+type ItemsCacheKey = (ModuleSpecifier, Option<String>);
+
+/// Parsed variable items keyed by the request they were fetched with, so that
+/// rapid completion requests can reuse a recently parsed response.
+type ItemsCache = MemoryCache<ItemsCacheKey, VariableItems>;
+
+/// Parsed documentation keyed by the URL it was fetched from, which is reused
+/// when the same completion item is resolved again.
+type DocumentationCache = MemoryCache<ModuleSpecifier, lsp::Documentation>;
+
+impl<K, V> MemoryCache<K, V>
+where
+  K: Clone + Eq + std::hash::Hash,
+  V: Clone,
+{
+  fn new(ttl: Duration) -> Self {
+    Self {
+      entries: Default::default(),
+      ttl,
+      capacity: DEFAULT_ITEMS_CACHE_CAPACITY,
+    }
+  }
+
+  fn get(&self, key: &K) -> Option<V> {
+    let mut cache = self.entries.lock();
+    cache.uses += 1;
+    let uses = cache.uses;
+    let entry = cache.entries.get_mut(key)?;
+    if entry.inserted.elapsed() < self.ttl {
+      entry.last_used = uses;
+      Some(entry.value.clone())
+    } else {
+      cache.entries.remove(key);
+      None
+    }
+  }
+
+  fn insert(&self, key: K, value: V) {
+    let mut cache = self.entries.lock();
+    cache.uses += 1;
+    let last_used = cache.uses;
+    cache.entries.insert(
+      key,
+      MemoryCacheEntry {
+        inserted: Instant::now(),
+        last_used,
+        value,
+      },
+    );
+    while cache.entries.len() > self.capacity {
+      let maybe_lru = cache
+        .entries
+        .iter()
+        .min_by_key(|(_, entry)| entry.last_used)
+        .map(|(key, _)| key.clone());
+      match maybe_lru {
+        Some(key) => cache.entries.remove(&key),
+        None => break,
+      };
+    }
+  }
+
+  fn clear(&self) {
+    self.entries.lock().entries.clear();
+  }
+}
+
+/// An in-memory record of the requests for variable items which recently
+/// failed, so that an endpoint which is missing or returns an error isn't
+/// requested again on every keystroke. Entries expire once they are older than
+/// the time to live, after which the request is attempted again.
+#[derive(Debug, Clone)]
+struct FailedItemsCache {
+  entries: Arc<Mutex<HashMap<ItemsCacheKey, Instant>>>,
+  ttl: Duration,
+}
+
+impl FailedItemsCache {
+  fn new(ttl: Duration) -> Self {
+    Self {
+      entries: Default::default(),
+      ttl,
+    }
+  }
+
+  fn contains(&self, key: &ItemsCacheKey) -> bool {
+    let mut entries = self.entries.lock();
+    match entries.get(key) {
+      Some(failed) if failed.elapsed() < self.ttl => true,
+      Some(_) => {
+        entries.remove(key);
+        false
+      }
+      None => false,
+    }
+  }
+
+  fn insert(&self, key: ItemsCacheKey) {
+    let mut entries = self.entries.lock();
+    // expired entries are pruned here, as they are otherwise only removed when
+    // the same request is made again
+    let ttl = self.ttl;
+    entries.retain(|_, failed| failed.elapsed() < ttl);
+    entries.insert(key, Instant::now());
+  }
+
+  fn clear(&self) {
+    self.entries.lock().clear();
+  }
+}
+
+/// Determines when completion items include a command to cache the module
+/// that they resolve to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrefetchPolicy {
+  /// Only items that complete the last key of a schema are cached.
+  LastKeyOnly,
+  /// Items that complete any key of a schema are cached, which allows
+  /// registries with predictable intermediate URLs to be prefetched.
+  AnyCompleteSpecifier,
+}
+
+// a `#[default]` variant can't be derived with the toolchain the project is
+// built with
+#[allow(clippy::derivable_impls)]
+impl Default for PrefetchPolicy {
+  fn default() -> Self {
+    Self::LastKeyOnly
+  }
+}
+
+/// A pattern of origins, like `https://*.corp.example`, whose registries are
+/// enabled as completions are requested for them. A `*` in the host matches
+/// any characters, including dots, so the pattern matches subdomains at any
+/// depth. Without a scheme, both `http` and `https` are matched, and without a
+/// port, only origins using the default port of their scheme are matched.
+#[derive(Debug, Clone)]
+struct OriginPattern {
+  pattern: String,
+  scheme: Option<String>,
+  host: Regex,
+  port: Option<u16>,
+}
+
+impl OriginPattern {
+  fn parse(pattern: &str) -> Result<Self, AnyError> {
+    let invalid =
+      || anyhow!("Invalid registry origin pattern \"{}\".", pattern);
+    let (scheme, rest) = match pattern.split_once("://") {
+      Some((scheme, rest)) => (Some(scheme.to_ascii_lowercase()), rest),
+      None => (None, pattern),
+    };
+    if !matches!(scheme.as_deref(), None | Some("http") | Some("https")) {
+      return Err(invalid());
+    }
+    let rest = rest.trim_end_matches('/');
+    let (host, port) = match rest.rsplit_once(':') {
+      Some((host, port)) => {
+        (host, Some(port.parse::<u16>().map_err(|_| invalid())?))
+      }
+      None => (rest, None),
+    };
+    if host.is_empty()
+      || host.contains('/')
+      || host.split('.').any(|label| label.is_empty())
+    {
+      return Err(invalid());
+    }
+    let host = Regex::new(&format!(
+      "(?i)^{}$",
+      regex::escape(host).replace("\\*", ".*")
+    ))?;
+    Ok(Self {
+      pattern: pattern.to_string(),
+      scheme,
+      host,
+      port,
+    })
+  }
+
+  fn matches(&self, url: &Url) -> bool {
+    let scheme_matches = match &self.scheme {
+      Some(scheme) => url.scheme() == scheme,
+      None => matches!(url.scheme(), "http" | "https"),
+    };
+    let port_matches = match self.port {
+      Some(port) => url.port_or_known_default() == Some(port),
+      None => url.port().is_none(),
+    };
+    match url.host_str() {
+      Some(host) => scheme_matches && port_matches && self.host.is_match(host),
+      None => false,
+    }
+  }
+}
+
+/// A proxy which requests to registries are sent through, overriding the
+/// proxy configured by the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY`
+/// environment variables, which are otherwise respected.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxyConfig {
+  /// The URL of the proxy.
+  pub url: String,
+  /// The hosts which are requested directly instead of through the proxy, in
+  /// the same form as `NO_PROXY`. An entry matches the host and all of its
+  /// subdomains, and only the given port if it includes one, while `*`
+  /// matches every host.
+  pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+  /// Determine if a request to the URL should bypass the proxy.
+  fn bypasses(&self, url: &Url) -> bool {
+    let host = match url.host_str() {
+      Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+      None => return false,
+    };
+    let port = url.port_or_known_default();
+    self.no_proxy.iter().any(|entry| {
+      let entry = entry.trim();
+      if entry == "*" {
+        return true;
+      }
+      let (entry_host, entry_port) = match entry.rsplit_once(':') {
+        Some((h, p)) if !h.ends_with(':') => match p.parse::<u16>() {
+          Ok(p) => (h, Some(p)),
+          Err(_) => (entry, None),
+        },
+        _ => (entry, None),
+      };
+      let entry_host = entry_host
+        .trim_start_matches('.')
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+      let host_matches = !entry_host.is_empty()
+        && (host.eq_ignore_ascii_case(entry_host)
+          || host
+            .to_ascii_lowercase()
+            .ends_with(&format!(".{}", entry_host.to_ascii_lowercase())));
+      host_matches && (entry_port.is_none() || entry_port == port)
+    })
+  }
+}
+
+/// Create an HTTP client like the one the file fetcher uses, but which sends
+/// requests through the proxy unless the proxy is bypassed for their URL.
+fn create_proxied_http_client(
+  proxy: ProxyConfig,
+) -> Result<reqwest::Client, AnyError> {
+  let proxy_url = reqwest::Url::parse(&proxy.url)?;
+  let mut tls_config = create_client_config(None, vec![], None, None)?;
+  tls_config.alpn_protocols = vec!["h2".into(), "http/1.1".into()];
+  let mut headers = reqwest::header::HeaderMap::new();
+  headers.insert(reqwest::header::USER_AGENT, get_user_agent().parse()?);
+  let client = reqwest::Client::builder()
+    .redirect(reqwest::redirect::Policy::none())
+    .default_headers(headers)
+    .use_preconfigured_tls(tls_config)
+    .proxy(reqwest::Proxy::custom(move |url| {
+      if proxy.bypasses(url) {
+        None
+      } else {
+        Some(proxy_url.clone())
+      }
+    }))
+    .build()?;
+  Ok(client)
+}
+
+/// The kind of resource a registry fetch event is about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FetchKind {
+  /// The configuration of a registry.
+  Config,
+  /// The variable items from an endpoint of a registry.
+  Endpoint,
+  /// The documentation for a completion item.
+  Documentation,
+}
+
+/// The outcome reported by a registry fetch event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FetchOutcome {
+  Started,
+  Succeeded { elapsed: Duration },
+  Failed { elapsed: Duration, error: String },
+}
+
+/// An event which is sent to the event listener of a module registry when it
+/// starts fetching a resource and once the fetch completes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistryEvent {
+  pub kind: FetchKind,
+  pub specifier: ModuleSpecifier,
+  pub outcome: FetchOutcome,
+}
+
+/// A snapshot of the counters of a module registry, which are shared by its
+/// clones and count from when the registry was created.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RegistryStats {
+  /// The number of requests made for configurations, variable items and
+  /// documentation, including those answered by the cache on disk.
+  pub fetches: u64,
+  /// The number of variable items and documentation found in memory.
+  pub cache_hits: u64,
+  /// The number of variable items and documentation not found in memory.
+  pub cache_misses: u64,
+  /// The number of fetched configurations which were invalid.
+  pub validation_failures: u64,
+  /// The number of completion requests.
+  pub completion_requests: u64,
+  /// The number of variable items which were skipped, because the schema of
+  /// their registry didn't resolve them to a valid specifier.
+  pub invalid_items: u64,
+}
+
+#[derive(Debug, Default)]
+struct RegistryCounters {
+  fetches: AtomicU64,
+  cache_hits: AtomicU64,
+  cache_misses: AtomicU64,
+  validation_failures: AtomicU64,
+  completion_requests: AtomicU64,
+  invalid_items: AtomicU64,
+}
+
+impl RegistryCounters {
+  fn increment(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+  }
+
+  fn record_cache(&self, hit: bool) {
+    if hit {
+      Self::increment(&self.cache_hits);
+    } else {
+      Self::increment(&self.cache_misses);
+    }
+  }
+
+  fn snapshot(&self) -> RegistryStats {
+    RegistryStats {
+      fetches: self.fetches.load(Ordering::Relaxed),
+      cache_hits: self.cache_hits.load(Ordering::Relaxed),
+      cache_misses: self.cache_misses.load(Ordering::Relaxed),
+      validation_failures: self.validation_failures.load(Ordering::Relaxed),
+      completion_requests: self.completion_requests.load(Ordering::Relaxed),
+      invalid_items: self.invalid_items.load(Ordering::Relaxed),
+    }
+  }
+}
+
+/// Watches the files of the enabled `file:` registry configurations, recording
+/// which of them have changed until they are reloaded. The directory of each
+/// file is watched, so that a file which is replaced when it is saved is still
+/// watched afterwards.
+#[derive(Clone)]
+struct ConfigWatcher {
+  watcher: Arc<Mutex<RecommendedWatcher>>,
+  /// The canonical paths of the watched configuration files.
+  paths: Arc<Mutex<HashSet<PathBuf>>>,
+  /// The canonical paths of the watched configuration files which have
+  /// changed since they were last reloaded.
+  changed: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl fmt::Debug for ConfigWatcher {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ConfigWatcher")
+      .field("paths", &self.paths)
+      .field("changed", &self.changed)
+      .finish()
+  }
+}
+
+impl ConfigWatcher {
+  fn new() -> Result<Self, AnyError> {
+    let paths: Arc<Mutex<HashSet<PathBuf>>> = Default::default();
+    let changed: Arc<Mutex<HashSet<PathBuf>>> = Default::default();
+    let watcher = {
+      let paths = paths.clone();
+      let changed = changed.clone();
+      RecommendedWatcher::new(move |res: Result<NotifyEvent, NotifyError>| {
+        let event = match res {
+          Ok(event) => event,
+          Err(_) => return,
+        };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+          return;
+        }
+        let paths = paths.lock();
+        for path in event.paths {
+          if let Ok(path) = canonicalize_path(&path) {
+            if paths.contains(&path) {
+              changed.lock().insert(path);
+            }
+          }
+        }
+      })?
+    };
+    Ok(Self {
+      watcher: Arc::new(Mutex::new(watcher)),
+      paths,
+      changed,
+    })
+  }
+
+  /// Watch the configuration file of a specifier, if it is a local file.
+  fn watch(&self, specifier: &ModuleSpecifier) -> Result<(), AnyError> {
+    if !is_local(specifier) {
+      return Ok(());
+    }
+    let path = specifier
+      .to_file_path()
+      .map_err(|_| anyhow!("Invalid file path \"{}\".", specifier))?;
+    let path = canonicalize_path(&path)?;
+    if let Some(dir) = path.parent() {
+      if self.paths.lock().insert(path.clone()) {
+        self
+          .watcher
+          .lock()
+          .watch(dir, RecursiveMode::NonRecursive)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Take the paths of the configuration files which have changed.
+  fn take_changed(&self) -> HashSet<PathBuf> {
+    std::mem::take(&mut *self.changed.lock())
+  }
+}
+
+/// A structure which holds the information about currently configured module
+/// registries and can provide completion information for URLs that match
+/// one of the enabled registries.
+#[derive(Debug, Clone)]
+pub struct ModuleRegistry {
+  /// The enabled registries, keyed by their base, which is their origin
+  /// along with any path prefix they are served from.
+  origins: HashMap<String, Vec<RegistryConfiguration>>,
+  /// The specifiers of the configurations of the enabled registries, keyed by
+  /// their base.
+  config_specifiers: HashMap<String, ModuleSpecifier>,
+  /// Additional headers to send with requests, keyed by the origin they are
+  /// scoped to.
+  headers: HashMap<String, HeadersMap>,
+  file_fetcher: FileFetcher,
+  items_cache: ItemsCache,
+  failed_items_cache: FailedItemsCache,
+  documentation_cache: DocumentationCache,
+  max_completion_items: usize,
+  /// If `true`, variable items are filtered and sorted on the server by how
+  /// well they fuzzy match the partial segment being completed.
+  fuzzy: bool,
+  fetch_timeout: Duration,
+  prefetch_policy: PrefetchPolicy,
+  /// If `true`, resources are only loaded from the cache and the network is
+  /// never accessed.
+  offline: bool,
+  /// How the cache is used once the registry is no longer offline.
+  online_cache_setting: CacheSetting,
+  config_max_age: Duration,
+  /// When the configuration of each base was loaded, along with how long the
+  /// cached response it was loaded from was fresh for at the time.
+  config_loaded: HashMap<String, (Instant, Duration)>,
+  /// The paths, relative to the base of a registry, that its configuration is
+  /// looked for at, in order.
+  config_paths: Vec<String>,
+  /// If `true`, the client supports snippets in completion items, and a
+  /// snippet laying out the whole schema is offered when completing the
+  /// start of a registry path.
+  snippet_support: bool,
+  /// If `true`, variable completions include the characters that follow the
+  /// variable in the schema as commit characters.
+  commit_characters: bool,
+  /// If `true`, the client supports label details in completion items, and
+  /// variable completions include them.
+  label_details_support: bool,
+  /// If `true`, variable completions only replace the segment of the
+  /// specifier for the key being completed, instead of the whole specifier.
+  segment_edits: bool,
+  /// The last error encountered when fetching or validating the registry
+  /// configuration of an origin, keyed by the origin.
+  errors: Arc<Mutex<HashMap<String, String>>>,
+  max_retries: u32,
+  retry_delay: Duration,
+  /// The warnings from registries which have already been logged, so that
+  /// each warning is only logged once.
+  logged_warnings: Arc<Mutex<HashSet<String>>>,
+  /// Validated registry configurations bundled with the binary, keyed by
+  /// origin, which are used until the configuration of the origin has been
+  /// fetched.
+  bundled_configs: HashMap<String, RegistryConfigurationJson>,
+  /// The patterns of origins whose registries are enabled as they are
+  /// discovered.
+  patterns: Vec<OriginPattern>,
+  /// The configurations of origins which matched a pattern, keyed by origin,
+  /// where `None` records that the origin has no valid configuration.
+  discovered: Arc<Mutex<HashMap<String, Option<RegistryConfigurationJson>>>>,
+  completion_generation: CompletionGeneration,
+  min_fetch_interval: Duration,
+  /// The time of the latest request for variable items which has been
+  /// scheduled for each origin, keyed by the origin.
+  last_fetches: Arc<Mutex<HashMap<String, Instant>>>,
+  /// The time until which a registry asked for no more requests to be made,
+  /// keyed by origin.
+  backoffs: Arc<Mutex<HashMap<String, Instant>>>,
+  /// Sends fetch events to the thread which calls the event listener, if a
+  /// listener has been set.
+  event_sender: Option<mpsc::UnboundedSender<RegistryEvent>>,
+  counters: Arc<RegistryCounters>,
+  /// The permits for requests to registries, shared by the clones of the
+  /// registry, which bound how many requests are in flight at once.
+  fetch_permits: Arc<Semaphore>,
+  /// Watches the enabled `file:` configurations when they are reloaded as they
+  /// change.
+  config_watcher: Option<ConfigWatcher>,
+}
+
+impl Default for ModuleRegistry {
+  fn default() -> Self {
+    ModuleRegistryBuilder::new().build()
+  }
+}
+
+/// Configures a `ModuleRegistry` before it is created, so that options can be
+/// added without changing how a registry is created. Options which aren't set
+/// keep their defaults, and can still be changed once the registry has been
+/// created.
+#[derive(Debug, Clone)]
+pub struct ModuleRegistryBuilder {
+  location: Option<PathBuf>,
+  cache_setting: CacheSetting,
+  fetch_timeout: Duration,
+  max_completion_items: usize,
+  cache_capacity: Option<usize>,
+  offline: bool,
+  max_concurrent_fetches: usize,
+  fuzzy: Option<bool>,
+  http_client: Option<reqwest::Client>,
+  headers: HashMap<String, HeadersMap>,
+  retries: Option<(u32, Duration)>,
+  prefetch_policy: Option<PrefetchPolicy>,
+  items_cache_ttl: Option<Duration>,
+  failed_items_ttl: Option<Duration>,
+  min_fetch_interval: Option<Duration>,
+  config_paths: Option<Vec<String>>,
+  config_max_age: Option<Duration>,
+  snippet_support: Option<bool>,
+  commit_characters: Option<bool>,
+  label_details_support: Option<bool>,
+  segment_edits: Option<bool>,
+}
+
+impl Default for ModuleRegistryBuilder {
+  fn default() -> Self {
+    Self {
+      location: None,
+      cache_setting: CacheSetting::RespectHeaders,
+      fetch_timeout: DEFAULT_FETCH_TIMEOUT,
+      max_completion_items: DEFAULT_MAX_COMPLETION_ITEMS,
+      cache_capacity: None,
+      offline: false,
+      max_concurrent_fetches: DEFAULT_MAX_CONCURRENT_FETCHES,
+      fuzzy: None,
+      http_client: None,
+      headers: HashMap::new(),
+      retries: None,
+      prefetch_policy: None,
+      items_cache_ttl: None,
+      failed_items_ttl: None,
+      min_fetch_interval: None,
+      config_paths: None,
+      config_max_age: None,
+      snippet_support: None,
+      commit_characters: None,
+      label_details_support: None,
+      segment_edits: None,
+    }
+  }
+}
+
+impl ModuleRegistryBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Set the directory the registry caches configurations and responses in.
+  /// When it isn't set, the `registries` directory of the default `DENO_DIR`
+  /// is used.
+  pub fn location(mut self, location: &Path) -> Self {
+    self.location = Some(location.to_path_buf());
+    self
+  }
+
+  /// Set how the cache of configurations and responses is used, which is
+  /// ignored when the registry is offline.
+  pub fn cache_setting(mut self, cache_setting: CacheSetting) -> Self {
+    self.cache_setting = cache_setting;
+    self
+  }
+
+  /// Set how long to wait for a registry to respond to a request.
+  pub fn fetch_timeout(mut self, fetch_timeout: Duration) -> Self {
+    self.fetch_timeout = fetch_timeout;
+    self
+  }
+
+  /// Set the maximum number of completion items returned for a single
+  /// completion request.
+  pub fn max_completion_items(mut self, max_completion_items: usize) -> Self {
+    self.max_completion_items = max_completion_items;
+    self
+  }
+
+  /// Set the maximum number of parsed endpoint responses, and separately of
+  /// parsed documentation, kept in memory.
+  pub fn cache_capacity(mut self, cache_capacity: usize) -> Self {
+    self.cache_capacity = Some(cache_capacity);
+    self
+  }
+
+  /// Set if the registry should only use cached configurations and items,
+  /// never accessing the network.
+  pub fn offline(mut self, offline: bool) -> Self {
+    self.offline = offline;
+    self
+  }
+
+  /// Set the maximum number of requests to registries which are in flight at
+  /// once.
+  pub fn max_concurrent_fetches(
+    mut self,
+    max_concurrent_fetches: usize,
+  ) -> Self {
+    self.max_concurrent_fetches = max_concurrent_fetches;
+    self
+  }
+
+  /// Set if variable completions should be fuzzy matched against the partial
+  /// segment being completed.
+  pub fn fuzzy(mut self, fuzzy: bool) -> Self {
+    self.fuzzy = Some(fuzzy);
+    self
+  }
+
+  /// Send the requests made to registries through an explicit proxy, instead
+  /// of the proxy configured by the environment. Errors if a client can't be
+  /// created for the proxy.
+  pub fn proxy(mut self, proxy: ProxyConfig) -> Result<Self, AnyError> {
+    self.http_client = Some(create_proxied_http_client(proxy)?);
+    Ok(self)
+  }
+
+  /// Set headers to send with every request made to the origin of `origin`,
+  /// once a registry is enabled for it.
+  pub fn headers(mut self, origin: &Url, headers: HeadersMap) -> Self {
+    self.headers.insert(base_url(origin), headers);
+    self
+  }
+
+  /// Set how many times a request to a registry is retried after a transient
+  /// error, and how long to wait before the first retry.
+  pub fn retries(mut self, max_retries: u32, retry_delay: Duration) -> Self {
+    self.retries = Some((max_retries, retry_delay));
+    self
+  }
+
+  /// Set when completion items should include a command to cache the module
+  /// they resolve to.
+  pub fn prefetch_policy(mut self, prefetch_policy: PrefetchPolicy) -> Self {
+    self.prefetch_policy = Some(prefetch_policy);
+    self
+  }
+
+  /// Set how long parsed variable items are kept in memory.
+  pub fn items_cache_ttl(mut self, ttl: Duration) -> Self {
+    self.items_cache_ttl = Some(ttl);
+    self
+  }
+
+  /// Set how long a request for variable items which failed is not attempted
+  /// again for.
+  pub fn failed_items_ttl(mut self, ttl: Duration) -> Self {
+    self.failed_items_ttl = Some(ttl);
+    self
+  }
+
+  /// Set the minimum length of time between requests for variable items to the
+  /// same origin.
+  pub fn min_fetch_interval(mut self, interval: Duration) -> Self {
+    self.min_fetch_interval = Some(interval);
+    self
+  }
+
+  /// Set the paths, relative to the base of a registry, that its configuration
+  /// is looked for at, in order.
+  pub fn config_paths(mut self, paths: Vec<String>) -> Self {
+    self.config_paths = Some(paths);
+    self
+  }
+
+  /// Set how long a registry configuration that was served without any
+  /// caching headers is cached for.
+  pub fn config_max_age(mut self, config_max_age: Duration) -> Self {
+    self.config_max_age = Some(config_max_age);
+    self
+  }
+
+  /// Set if the client supports snippets in completion items.
+  pub fn snippet_support(mut self, snippet_support: bool) -> Self {
+    self.snippet_support = Some(snippet_support);
+    self
+  }
+
+  /// Set if variable completions should be accepted by typing the character
+  /// that follows the variable in the schema.
+  pub fn commit_characters(mut self, commit_characters: bool) -> Self {
+    self.commit_characters = Some(commit_characters);
+    self
+  }
+
+  /// Set if the client supports label details in completion items.
+  pub fn label_details_support(mut self, label_details_support: bool) -> Self {
+    self.label_details_support = Some(label_details_support);
+    self
+  }
+
+  /// Set if variable completions should only replace the segment of the
+  /// specifier for the key being completed.
+  pub fn segment_edits(mut self, segment_edits: bool) -> Self {
+    self.segment_edits = Some(segment_edits);
+    self
+  }
+
+  pub fn build(self) -> ModuleRegistry {
+    let location = match self.location {
+      Some(location) => location,
+      None => {
+        // This only gets used when creating the tsc runtime and for testing,
+        // and so it shouldn't ever actually access the DenoDir, so it doesn't
+        // support a custom root.
+        let dir = deno_dir::DenoDir::new(None).unwrap();
+        dir.root.join("registries")
+      }
+    };
+    let mut registry = ModuleRegistry::create(&location, self.cache_setting);
+    registry.set_fetch_timeout(self.fetch_timeout);
+    registry.set_max_completion_items(self.max_completion_items);
+    if let Some(cache_capacity) = self.cache_capacity {
+      registry.set_cache_capacity(cache_capacity);
+    }
+    if self.offline {
+      registry.set_offline(true);
+    }
+    registry.set_max_concurrent_fetches(self.max_concurrent_fetches);
+    if let Some(fuzzy) = self.fuzzy {
+      registry.set_fuzzy(fuzzy);
+    }
+    if let Some(http_client) = self.http_client {
+      registry.file_fetcher.http_client = http_client;
+    }
+    registry.headers = self.headers;
+    if let Some((max_retries, retry_delay)) = self.retries {
+      registry.set_retries(max_retries, retry_delay);
+    }
+    if let Some(prefetch_policy) = self.prefetch_policy {
+      registry.set_prefetch_policy(prefetch_policy);
+    }
+    if let Some(ttl) = self.items_cache_ttl {
+      registry.set_items_cache_ttl(ttl);
+    }
+    if let Some(ttl) = self.failed_items_ttl {
+      registry.set_failed_items_ttl(ttl);
+    }
+    if let Some(interval) = self.min_fetch_interval {
+      registry.set_min_fetch_interval(interval);
+    }
+    if let Some(paths) = self.config_paths {
+      registry.set_config_paths(paths);
+    }
+    if let Some(config_max_age) = self.config_max_age {
+      registry.set_config_max_age(config_max_age);
+    }
+    if let Some(snippet_support) = self.snippet_support {
+      registry.set_snippet_support(snippet_support);
+    }
+    if let Some(commit_characters) = self.commit_characters {
+      registry.set_commit_characters(commit_characters);
+    }
+    if let Some(label_details_support) = self.label_details_support {
+      registry.set_label_details_support(label_details_support);
+    }
+    if let Some(segment_edits) = self.segment_edits {
+      registry.set_segment_edits(segment_edits);
+    }
+    registry
+  }
+}
+
+impl ModuleRegistry {
+  pub fn new(location: &Path) -> Self {
+    ModuleRegistryBuilder::new().location(location).build()
+  }
+
+  fn create(location: &Path, cache_setting: CacheSetting) -> Self {
+    let http_cache = HttpCache::new(location);
+    let mut file_fetcher = FileFetcher::new(
+      http_cache,
+      cache_setting,
+      true,
+      None,
+      BlobStore::default(),
+      None,
+    )
+    .context("Error creating file fetcher in module registry.")
+    .unwrap();
+    file_fetcher.set_download_log_level(super::logging::lsp_log_level());
+    Self::from_file_fetcher(file_fetcher)
+  }
+
+  /// Create a registry which fetches with the HTTP client of an existing file
+  /// fetcher, so that its connection pool is shared with the other users of
+  /// the fetcher. The cache setting of the fetcher is used as it is. The
+  /// responses for the registries are cached on disk in `location`, and in
+  /// memory separately from the fetcher, so clearing the cache of the
+  /// registries doesn't clear the cache of the fetcher.
+  pub fn with_file_fetcher(location: &Path, file_fetcher: FileFetcher) -> Self {
+    Self::from_file_fetcher(
+      file_fetcher.with_http_cache(HttpCache::new(location)),
+    )
+  }
+
+  fn from_file_fetcher(file_fetcher: FileFetcher) -> Self {
+    let online_cache_setting = file_fetcher.cache_setting().clone();
+    Self {
+      origins: HashMap::new(),
+      config_specifiers: HashMap::new(),
+      headers: HashMap::new(),
+      file_fetcher,
+      items_cache: ItemsCache::new(DEFAULT_ITEMS_CACHE_TTL),
+      failed_items_cache: FailedItemsCache::new(DEFAULT_FAILED_ITEMS_TTL),
+      // documentation for a module is effectively immutable, and so it
+      // doesn't expire
+      documentation_cache: DocumentationCache::new(Duration::MAX),
+      max_completion_items: DEFAULT_MAX_COMPLETION_ITEMS,
+      fuzzy: false,
+      fetch_timeout: DEFAULT_FETCH_TIMEOUT,
+      prefetch_policy: PrefetchPolicy::default(),
+      offline: false,
+      online_cache_setting,
+      config_max_age: DEFAULT_CONFIG_MAX_AGE,
+      config_loaded: HashMap::new(),
+      config_paths: vec![CONFIG_PATH.to_string()],
+      snippet_support: false,
+      commit_characters: true,
+      label_details_support: false,
+      segment_edits: false,
+      errors: Default::default(),
+      max_retries: DEFAULT_MAX_RETRIES,
+      retry_delay: DEFAULT_RETRY_DELAY,
+      logged_warnings: Default::default(),
+      bundled_configs: HashMap::new(),
+      patterns: Vec::new(),
+      discovered: Default::default(),
+      completion_generation: Default::default(),
+      min_fetch_interval: DEFAULT_MIN_FETCH_INTERVAL,
+      last_fetches: Default::default(),
+      backoffs: Default::default(),
+      event_sender: None,
+      counters: Default::default(),
+      fetch_permits: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_FETCHES)),
+      config_watcher: None,
+    }
+  }
+
+  /// Set the maximum number of requests to registries which are in flight at
+  /// once, across the registry and its clones, so that completing doesn't
+  /// exhaust the connections available to the rest of the language server.
+  /// Requests beyond the limit wait for one in flight to finish. At least one
+  /// request is always allowed.
+  pub fn set_max_concurrent_fetches(&mut self, max_concurrent_fetches: usize) {
+    self.fetch_permits =
+      Arc::new(Semaphore::new(max_concurrent_fetches.max(1)));
+  }
+
+  /// Set if the configurations of the enabled `file:` registries are watched,
+  /// so that they are reloaded by `reload_changed_configs()` once they change,
+  /// which allows a registry configuration to be iterated on without
+  /// restarting the language server. Registries served over http aren't
+  /// affected.
+  pub fn set_reload_on_config_change(
+    &mut self,
+    reload_on_config_change: bool,
+  ) -> Result<(), AnyError> {
+    if !reload_on_config_change {
+      self.config_watcher = None;
+      return Ok(());
+    }
+    if self.config_watcher.is_none() {
+      let config_watcher = ConfigWatcher::new()?;
+      for specifier in self.config_specifiers.values() {
+        config_watcher.watch(specifier)?;
+      }
+      self.config_watcher = Some(config_watcher);
+    }
+    Ok(())
+  }
+
+  /// Reload the configurations of the `file:` registries which have changed
+  /// since they were loaded, when configurations are reloaded as they change,
+  /// returning `true` if any configuration was reloaded. A configuration which
+  /// has become invalid is left as it was, and the error is recorded as the
+  /// last error of the registry.
+  pub async fn reload_changed_configs(&mut self) -> bool {
+    let changed = match &self.config_watcher {
+      Some(config_watcher) => config_watcher.take_changed(),
+      None => return false,
+    };
+    if changed.is_empty() {
+      return false;
+    }
+    let reloads: Vec<(String, ModuleSpecifier)> = self
+      .config_specifiers
+      .iter()
+      .filter(|(_, specifier)| {
+        is_local(specifier)
+          && specifier
+            .to_file_path()
+            .ok()
+            .and_then(|path| canonicalize_path(&path).ok())
+            .map(|path| changed.contains(&path))
+            .unwrap_or(false)
+      })
+      .map(|(base, specifier)| (base.clone(), specifier.clone()))
+      .collect();
+    let mut reloaded = false;
+    for (base, specifier) in reloads {
+      let origin = Url::parse(&base).map_or(base.clone(), |u| base_url(&u));
+      match self.fetch_config(&specifier).await {
+        Ok(config) => {
+          info!("Reloaded the registry configuration \"{}\".", specifier);
+          self.errors.lock().remove(&origin);
+          self.insert_config(base, config);
+          reloaded = true;
+        }
+        Err(err) => {
+          error!(
+            "Unable to reload the registry configuration \"{}\". {}",
+            specifier, err
+          );
+          self.set_error(&origin, &err);
+        }
+      }
+    }
+    reloaded
+  }
+
+  /// Set how long parsed variable items are kept in memory and reused by
+  /// subsequent completion requests.
+  pub fn set_items_cache_ttl(&mut self, ttl: Duration) {
+    self.items_cache.ttl = ttl;
+  }
+
+  /// Set how long a request for variable items which failed is not attempted
+  /// again for, during which completions for it return nothing.
+  pub fn set_failed_items_ttl(&mut self, ttl: Duration) {
+    self.failed_items_cache.ttl = ttl;
+  }
+
+  /// Set a listener which is called when the registry starts fetching a
+  /// configuration or variable items, and with the outcome of the fetch once
+  /// it completes. The listener is called in order on a thread of its own, so
+  /// fetches never wait for it.
+  pub fn set_event_listener<F>(&mut self, listener: F)
+  where
+    F: Fn(RegistryEvent) + Send + 'static,
+  {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    // the thread finishes once every clone of the registry using this
+    // listener has been dropped
+    std::thread::spawn(move || {
+      while let Some(event) = receiver.blocking_recv() {
+        listener(event);
+      }
+    });
+    self.event_sender = Some(sender);
+  }
+
+  /// Enable the registries of every origin which matches a pattern, like
+  /// `https://*.corp.example`. The configuration of a matching origin is only
+  /// fetched once completions are first requested for it, and only origins
+  /// which match an enabled pattern are ever probed.
+  pub fn enable_pattern(&mut self, pattern: &str) -> Result<(), AnyError> {
+    if !self.patterns.iter().any(|p| p.pattern == pattern) {
+      self.patterns.push(OriginPattern::parse(pattern)?);
+    }
+    Ok(())
+  }
+
+  /// Disable a pattern of origins, forgetting the registries discovered for
+  /// the origins which only matched that pattern.
+  pub fn disable_pattern(&mut self, pattern: &str) {
+    self.patterns.retain(|p| p.pattern != pattern);
+    let patterns = &self.patterns;
+    self
+      .discovered
+      .lock()
+      .retain(|origin, _| match Url::parse(origin) {
+        Ok(url) => patterns.iter().any(|pattern| pattern.matches(&url)),
+        Err(_) => false,
+      });
+  }
+
+  /// Determine if an origin matches an enabled pattern of origins.
+  pub fn matches_pattern(&self, origin: &str) -> bool {
+    match Url::parse(origin) {
+      Ok(url) => self.patterns.iter().any(|pattern| pattern.matches(&url)),
+      Err(_) => false,
+    }
+  }
+
+  /// The configuration discovered for an origin which matched a pattern, if
+  /// it has been fetched and is valid.
+  fn get_discovered(&self, origin: &str) -> Option<RegistryConfigurationJson> {
+    self.discovered.lock().get(origin).cloned().flatten()
+  }
+
+  /// Find the registries of the origin of a specifier which isn't enabled but
+  /// matches an enabled pattern, fetching its configuration the first time the
+  /// origin is seen.
+  async fn discover(
+    &self,
+    specifier: &Url,
+  ) -> Option<(String, Vec<RegistryConfiguration>)> {
+    if !self
+      .patterns
+      .iter()
+      .any(|pattern| pattern.matches(specifier))
+    {
+      return None;
+    }
+    let origin = base_url(specifier);
+    let maybe_discovered = self.discovered.lock().get(&origin).cloned();
+    let config = match maybe_discovered {
+      Some(maybe_config) => maybe_config?,
+      None => {
+        let config_specifiers = self.get_config_specifiers(&origin).ok()?;
+        let maybe_config = self
+          .fetch_first_config(&config_specifiers)
+          .await
+          .map(|(_, config)| config)
+          .map_err(|err| {
+            self.set_error(&origin, &err);
+            info!(
+              "Unable to discover a registry configuration for \"{}\". {}",
+              origin, err
+            );
+          })
+          .ok();
+        self
+          .discovered
+          .lock()
+          .insert(origin.clone(), maybe_config.clone());
+        maybe_config?
+      }
+    };
+    Some((origin, config.registries))
+  }
+
+  /// Send the requests made to registries through an explicit proxy, or with
+  /// `None`, go back to using the proxy configured by the environment, if any.
+  pub fn set_proxy(
+    &mut self,
+    maybe_proxy: Option<ProxyConfig>,
+  ) -> Result<(), AnyError> {
+    self.file_fetcher.http_client = match maybe_proxy {
+      Some(proxy) => create_proxied_http_client(proxy)?,
+      None => {
+        create_http_client(get_user_agent(), None, vec![], None, None, None)?
+      }
+    };
+    Ok(())
+  }
+
+  /// Set the minimum length of time between requests for variable items to the
+  /// same origin. A request which would be made sooner waits until the
+  /// interval has passed, and is dropped if it is superseded while waiting, so
+  /// that rapid typing doesn't exceed the rate limits of a registry.
+  pub fn set_min_fetch_interval(&mut self, interval: Duration) {
+    self.min_fetch_interval = interval;
+  }
+
+  /// Set the maximum number of parsed endpoint responses, and separately of
+  /// parsed documentation, kept in memory. Once the limit is reached, the
+  /// least recently used response is evicted.
+  pub fn set_cache_capacity(&mut self, capacity: usize) {
+    self.items_cache.capacity = capacity;
+    self.documentation_cache.capacity = capacity;
+  }
+
+  /// Set the maximum number of completion items returned for a single
+  /// completion request. When the items from a registry exceed this, the
+  /// returned list is truncated and marked as incomplete.
+  pub fn set_max_completion_items(&mut self, max_completion_items: usize) {
+    self.max_completion_items = max_completion_items;
+  }
+
+  /// The number of variable items that are used from a response, beyond which
+  /// the completions are incomplete.
+  fn get_max_parsed_items(&self) -> usize {
+    if self.fuzzy {
+      self.max_completion_items.saturating_mul(FUZZY_ITEMS_FACTOR)
+    } else {
+      self.max_completion_items
+    }
+  }
+
+  /// Set if variable completions should be fuzzy matched against the partial
+  /// segment being completed, instead of leaving the filtering to the client.
+  pub fn set_fuzzy(&mut self, fuzzy: bool) {
+    self.fuzzy = fuzzy;
+  }
+
+  /// Set how long to wait for a registry to respond to a request for its
+  /// configuration, variable items or documentation before giving up.
+  pub fn set_fetch_timeout(&mut self, fetch_timeout: Duration) {
+    self.fetch_timeout = fetch_timeout;
+  }
+
+  /// Set how many times a request to a registry is retried after a transient
+  /// error, like a connection error or a server error, and how long to wait
+  /// before the first retry. The delay doubles for each subsequent retry, and
+  /// all of the retries are bounded by the fetch timeout.
+  pub fn set_retries(&mut self, max_retries: u32, retry_delay: Duration) {
+    self.max_retries = max_retries;
+    self.retry_delay = retry_delay;
+  }
+
+  /// Set when completion items should include a command to cache the module
+  /// they resolve to.
+  pub fn set_prefetch_policy(&mut self, prefetch_policy: PrefetchPolicy) {
+    self.prefetch_policy = prefetch_policy;
+  }
+
+  /// Set if the client supports snippets in completion items, which mirrors
+  /// the `completionItem.snippetSupport` client capability.
+  pub fn set_snippet_support(&mut self, snippet_support: bool) {
+    self.snippet_support = snippet_support;
+  }
+
+  /// Set if variable completions should be accepted by typing the character
+  /// that follows the variable in the schema, like the `@` which separates a
+  /// module from its version.
+  pub fn set_commit_characters(&mut self, commit_characters: bool) {
+    self.commit_characters = commit_characters;
+  }
+
+  /// Set if the client supports label details in completion items. When it
+  /// does, variable completions show the description of the item, or the name
+  /// of the key they complete, alongside their label.
+  pub fn set_label_details_support(&mut self, label_details_support: bool) {
+    self.label_details_support = label_details_support;
+  }
+
+  /// Set if variable completions should only replace the segment of the
+  /// specifier for the key being completed, which suits clients that place
+  /// the cursor in the middle of a specifier, instead of replacing the whole
+  /// specifier, which is the default.
+  pub fn set_segment_edits(&mut self, segment_edits: bool) {
+    self.segment_edits = segment_edits;
+  }
+
+  /// Set the paths, relative to the base of a registry, that its configuration
+  /// is looked for at. The paths are tried in order and the first
+  /// configuration which can be fetched and is valid is used, which lets
+  /// registries on hosts that can't serve `/.well-known` provide one
+  /// elsewhere. An empty list restores the default of just
+  /// `/.well-known/deno-import-intellisense.json`.
+  pub fn set_config_paths(&mut self, paths: Vec<String>) {
+    self.config_paths = if paths.is_empty() {
+      vec![CONFIG_PATH.to_string()]
+    } else {
+      paths
+    };
+  }
+
+  /// Set how long a registry configuration that was served without any
+  /// caching headers is cached for.
+  pub fn set_config_max_age(&mut self, config_max_age: Duration) {
+    self.config_max_age = config_max_age;
+  }
+
+  /// Set if the registries should only use cached configurations and items,
+  /// never accessing the network. Resources that are not cached are treated
+  /// as unavailable without logging an error. Going back online restores how
+  /// the cache was used before the registries went offline.
+  pub fn set_offline(&mut self, offline: bool) {
+    if offline && !self.offline {
+      self.online_cache_setting = self.file_fetcher.cache_setting().clone();
+    }
+    self.offline = offline;
+    self.file_fetcher.set_cache_setting(if offline {
+      CacheSetting::Only
+    } else {
+      self.online_cache_setting.clone()
+    });
+  }
+
+  /// Set the registry configurations bundled with the binary, keyed by origin.
+  /// When an origin with a bundled configuration is enabled, the bundled
+  /// configuration is used straight away and when the configuration of the
+  /// origin can't be fetched, for example when offline. A configuration that
+  /// is successfully fetched replaces the bundled one.
+  pub(crate) fn set_bundled_configs(
+    &mut self,
+    configs: HashMap<String, RegistryConfigurationJson>,
+  ) -> Result<(), AnyError> {
+    let mut bundled_configs = HashMap::new();
+    for (origin, config) in configs {
+      let origin = base_url(&parse_registry_url(&origin)?);
+      let config = compile_config(config).map_err(|err| {
+        anyhow!(
+          "Invalid bundled registry configuration for \"{}\". {}",
+          origin,
+          err
+        )
+      })?;
+      bundled_configs.insert(origin, config);
+    }
+    self.bundled_configs = bundled_configs;
+    Ok(())
+  }
+
+  /// Determine if completion items for a key should include a command to
+  /// cache the module they resolve to, based on the prefetch policy.
+  fn should_prefetch(&self, key: &Key, last_key_name: &StringOrNumber) -> bool {
+    match self.prefetch_policy {
+      PrefetchPolicy::LastKeyOnly => key.name == *last_key_name,
+      PrefetchPolicy::AnyCompleteSpecifier => true,
+    }
+  }
+
+  /// Offer a literal segment of a schema as a completion. The whole specifier
+  /// is replaced, but the completion is filtered by the literal alone, so that
+  /// alternative literals offered by different registries are filtered against
+  /// what has been typed of them.
+  fn complete_literal(
+    &self,
+    s: String,
+    completions: &mut HashMap<String, lsp::CompletionItem>,
+    current_specifier: &str,
+    byte_offset: usize,
+    range: &lsp::Range,
+  ) {
+    let label = if s.starts_with('/') {
+      s[0..].to_string()
+    } else {
+      s.to_string()
+    };
+    let full_text = format!(
+      "{}{}{}",
+      &current_specifier[..byte_offset],
+      s,
+      &current_specifier[byte_offset..]
+    );
+    let text_edit = Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+      range: *range,
+      new_text: full_text,
+    }));
+    let filter_text = Some(s.clone());
+    completions.insert(
+      s,
+      lsp::CompletionItem {
+        label,
+        kind: Some(lsp::CompletionItemKind::FOLDER),
+        filter_text,
+        sort_text: Some("1".to_string()),
+        text_edit,
+        ..Default::default()
+      },
+    );
+  }
+
+  /// Disable a registry, removing its configuration, if any, from memory.
+  pub async fn disable(&mut self, origin: &str) -> Result<(), AnyError> {
+    let url = Url::parse(origin)?;
+    let base = self.resolve_base(&url);
+    self.origins.remove(&base);
+    self.config_specifiers.remove(&base);
+    self.config_loaded.remove(&base);
+    let origin = base_url(&url);
+    if self.get_registries_for_origin(&origin).next().is_none() {
+      self.headers.remove(&origin);
+    }
+    self.items_cache.clear();
+    self.failed_items_cache.clear();
+    self.documentation_cache.clear();
+    Ok(())
+  }
+
+  /// Disable all of the registries, including those enabled by a pattern,
+  /// removing their configurations from memory. Unlike `clear_cache()`, the
+  /// cache on disk is left in place, so enabling a registry again doesn't
+  /// need to access the network.
+  pub async fn disable_all(&mut self) {
+    self.origins.clear();
+    self.config_specifiers.clear();
+    self.config_loaded.clear();
+    self.headers.clear();
+    self.patterns.clear();
+    self.discovered.lock().clear();
+    self.items_cache.clear();
+    self.failed_items_cache.clear();
+    self.documentation_cache.clear();
+  }
+
+  /// The counters of the registry, like how many completions have been
+  /// requested and how many fetches have been made.
+  pub fn stats(&self) -> RegistryStats {
+    self.counters.snapshot()
+  }
+
+  /// The file fetcher of the registry, which can be cloned to share its
+  /// connection pool with other users.
+  pub fn file_fetcher(&self) -> &FileFetcher {
+    &self.file_fetcher
+  }
+
+  /// The bases of the enabled registries, in order.
+  pub fn origins(&self) -> Vec<String> {
+    let mut origins: Vec<String> = self.origins.keys().cloned().collect();
+    origins.sort();
+    origins
+  }
+
+  /// Check to see if the given origin has a registry configuration.
+  pub(crate) async fn check_origin(
+    &self,
+    origin: &str,
+  ) -> Result<(), AnyError> {
+    let origin_url = parse_registry_url(origin)?;
+    let specifiers = self.get_config_specifiers(&base_url(&origin_url))?;
+    if let Err(err) = self.fetch_first_config(&specifiers).await {
+      self.set_error(&base_url(&origin_url), &err);
+      return Err(err);
+    }
+    Ok(())
+  }
+
+  /// The last error encountered when fetching or validating the registry
+  /// configuration of an origin, if any. The error is cleared once a
+  /// registry for the origin is successfully enabled.
+  pub fn last_error(&self, origin: &str) -> Option<String> {
+    let origin = base_url(&Url::parse(origin).ok()?);
+    self.errors.lock().get(&origin).cloned()
+  }
+
+  /// The schemas of the enabled registries of an origin, which is empty when
+  /// the origin has no enabled registries.
+  pub fn schemas(&self, origin: &str) -> Vec<String> {
+    let origin = Url::parse(origin).map_or(String::new(), |u| base_url(&u));
+    self
+      .get_registries_for_origin(&origin)
+      .map(|registry| registry.schema.clone())
+      .collect()
+  }
+
+  /// The schemas of the enabled registries of an origin, along with the keys
+  /// of the variables declared for each schema.
+  pub fn variables(&self, origin: &str) -> Vec<(String, Vec<String>)> {
+    let origin = Url::parse(origin).map_or(String::new(), |u| base_url(&u));
+    self
+      .get_registries_for_origin(&origin)
+      .map(|registry| {
+        let keys = registry.variables.iter().map(|v| v.key.clone()).collect();
+        (registry.schema.clone(), keys)
+      })
+      .collect()
+  }
+
+  /// Resolve the documentation URL for a value of a key of one of the schemas
+  /// of an origin, where the schema is the one at `schema_index` of
+  /// `schemas()`. This is the URL that completion items for the value refer
+  /// to for their documentation. Returns `None` if the origin is not enabled,
+  /// the key is not part of the schema, or its variable has no documentation.
+  pub fn documentation_url(
+    &self,
+    origin: &str,
+    schema_index: usize,
+    key: &str,
+    value: &str,
+  ) -> Option<ModuleSpecifier> {
+    let origin_url = Url::parse(origin).ok()?;
+    let registry = self
+      .get_registries_for_origin(&base_url(&origin_url))
+      .nth(schema_index)?;
+    let key =
+      registry
+        .compiled
+        .as_ref()?
+        .tokens
+        .iter()
+        .find_map(|t| match t {
+          Token::Key(k)
+            if k.name == StringOrNumber::String(key.to_string()) =>
+          {
+            Some(k)
+          }
+          _ => None,
+        })?;
+    let variable = registry.get_variable_for_key(key, &HashMap::new())?;
+    let url = variable.documentation.as_deref()?;
+    get_endpoint(url, &origin_url, key, Some(value)).ok()
+  }
+
+  fn set_error(&self, origin: &str, err: &AnyError) {
+    self
+      .errors
+      .lock()
+      .insert(origin.to_string(), err.to_string());
+  }
+
+  /// Send an event to the event listener, if any.
+  fn emit(
+    &self,
+    kind: FetchKind,
+    specifier: &ModuleSpecifier,
+    outcome: FetchOutcome,
+  ) {
+    if let Some(sender) = &self.event_sender {
+      // the event is dropped if the listener has panicked
+      let _ = sender.send(RegistryEvent {
+        kind,
+        specifier: specifier.clone(),
+        outcome,
+      });
+    }
+  }
+
+  /// Await a fetch, sending events to the event listener, if any, when it
+  /// starts and with its outcome once it completes.
+  async fn observe<T>(
+    &self,
+    kind: FetchKind,
+    specifier: &ModuleSpecifier,
+    fetch: impl Future<Output = Result<T, AnyError>>,
+  ) -> Result<T, AnyError> {
+    self.emit(kind, specifier, FetchOutcome::Started);
+    let start = Instant::now();
+    let result = fetch.await;
+    let elapsed = start.elapsed();
+    let outcome = match &result {
+      Ok(_) => FetchOutcome::Succeeded { elapsed },
+      Err(err) => FetchOutcome::Failed {
+        elapsed,
+        error: err.to_string(),
+      },
+    };
+    self.emit(kind, specifier, outcome);
+    result
+  }
+
+  /// Fetch and validate the specifier to a registry configuration, resolving
+  /// with the configuration if valid.
+  async fn fetch_config(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> Result<RegistryConfigurationJson, AnyError> {
+    self
+      .observe(
+        FetchKind::Config,
+        specifier,
+        self.fetch_and_validate_config(specifier),
+      )
+      .await
+  }
+
+  /// Fetch and validate a registry configuration along with the
+  /// configurations it includes, appending the registries of the included
+  /// configurations to its own. Each configuration is only included once, so
+  /// cyclic includes are broken, and a registry with the same schema as one
+  /// that has already been included is skipped.
+  async fn fetch_and_validate_config(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> Result<RegistryConfigurationJson, AnyError> {
+    let mut config = self.fetch_config_file(specifier).await?;
+    let mut visited = HashSet::new();
+    visited.insert(specifier.clone());
+    let mut schemas: HashSet<String> =
+      config.registries.iter().map(|r| r.schema.clone()).collect();
+    let mut pending = VecDeque::new();
+    for include in &config.includes {
+      pending.push_back(specifier.join(include)?);
+    }
+    while let Some(include) = pending.pop_front() {
+      if !visited.insert(include.clone()) {
+        continue;
+      }
+      let included =
+        self.fetch_config_file(&include).await.with_context(|| {
+          format!(
+            "Unable to include the registry configuration \"{}\".",
+            include
+          )
+        })?;
+      for url in &included.includes {
+        pending.push_back(include.join(url)?);
+      }
+      for registry in included.registries {
+        if schemas.insert(registry.schema.clone()) {
+          config.registries.push(registry);
+        } else {
+          warn!(
+            "The registry schema \"{}\" included from \"{}\" is already defined, and is ignored.",
+            registry.schema, include
+          );
+        }
+      }
+      for (key, value) in included.headers {
+        config.headers.entry(key).or_insert(value);
+      }
+    }
+    Ok(config)
+  }
+
+  /// Fetch and validate a single registry configuration file.
+  async fn fetch_config_file(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> Result<RegistryConfigurationJson, AnyError> {
+    let fetch_result = self.fetch(specifier).await;
+    // if there is an error fetching, we will cache an empty file, so that
+    // subsequent requests they are just an empty doc which will error without
+    // needing to connect to the remote URL. We will cache it for 1 week. When
+    // only the cache is used, for example when offline, the configuration
+    // simply isn't cached yet, so we don't, and local configurations are never
+    // cached.
+    if is_local(specifier) {
+      return fetch_result.and_then(|file| self.parse_config_file(&file));
+    }
+    if fetch_result.is_err()
+      && *self.file_fetcher.cache_setting() != CacheSetting::Only
+    {
+      let mut headers_map = HashMap::new();
+      headers_map.insert(
+        "cache-control".to_string(),
+        "max-age=604800, immutable".to_string(),
+      );
+      self
+        .file_fetcher
+        .http_cache
+        .set(specifier, headers_map, &[])?;
+    }
+    let file = fetch_result?;
+    if let Err(err) = self.set_default_config_max_age(specifier) {
+      error!(
+        "Unable to update the cache of registry configuration \"{}\". {}",
+        specifier, err
+      );
+    }
+    self.parse_config_file(&file)
+  }
+
+  /// Parse and validate the source of a registry configuration file.
+  fn parse_config_file(
+    &self,
+    file: &File,
+  ) -> Result<RegistryConfigurationJson, AnyError> {
+    let result = parse_config(&file.source).and_then(compile_config);
+    if result.is_err() {
+      RegistryCounters::increment(&self.counters.validation_failures);
+    }
+    result
+  }
+
+  /// When a configuration was served without any caching headers, cache it for
+  /// the default max age so that it isn't fetched again every time the
+  /// language server starts. Configurations with caching headers are left
+  /// untouched.
+  fn set_default_config_max_age(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> Result<(), AnyError> {
+    let http_cache = &self.file_fetcher.http_cache;
+    let (mut file, mut headers, _) = http_cache.get(specifier)?;
+    if headers.contains_key("cache-control") || headers.contains_key("expires")
+    {
+      return Ok(());
+    }
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    headers.insert(
+      "cache-control".to_string(),
+      format!("max-age={}", self.config_max_age.as_secs()),
+    );
+    http_cache.set(specifier, headers, &content)
+  }
+
+  /// Fetch the registry configuration for an origin, and if valid, store the
+  /// configuration in memory along with any headers the configuration declares
+  /// for the origin.
+  async fn load_config(
+    &mut self,
+    base: String,
+    specifiers: &[ModuleSpecifier],
+  ) -> Result<(), AnyError> {
+    let origin = base_url(&Url::parse(&base)?);
+    let (specifier, config) = match self.fetch_first_config(specifiers).await {
+      Ok(result) => result,
+      Err(err) => {
+        self.set_error(&origin, &err);
+        return Err(err);
+      }
+    };
+    self.errors.lock().remove(&origin);
+    match self.get_config_ttl(&specifier) {
+      Some(ttl) => {
+        self
+          .config_loaded
+          .insert(base.clone(), (Instant::now(), ttl));
+      }
+      None => {
+        self.config_loaded.remove(&base);
+      }
+    }
+    if let Some(config_watcher) = &self.config_watcher {
+      if let Err(err) = config_watcher.watch(&specifier) {
+        error!(
+          "Unable to watch the registry configuration \"{}\". {}",
+          specifier, err
+        );
+      }
+    }
+    self.config_specifiers.insert(base.clone(), specifier);
+    self.insert_config(base, config);
+    Ok(())
+  }
+
+  /// The specifiers that the configuration of a base is looked for at, in
+  /// the order they are tried.
+  fn get_config_specifiers(
+    &self,
+    base: &str,
+  ) -> Result<Vec<ModuleSpecifier>, AnyError> {
+    self
+      .config_paths
+      .iter()
+      .map(|path| Ok(Url::parse(&join_base_str(base, path))?))
+      .collect()
+  }
+
+  /// Fetch the first of the candidate configurations which can be fetched and
+  /// is valid, along with the specifier it was fetched from. When none of
+  /// them are, the error for the first candidate is returned.
+  async fn fetch_first_config(
+    &self,
+    specifiers: &[ModuleSpecifier],
+  ) -> Result<(ModuleSpecifier, RegistryConfigurationJson), AnyError> {
+    let mut maybe_err = None;
+    for specifier in specifiers {
+      match self.fetch_config(specifier).await {
+        Ok(config) => return Ok((specifier.clone(), config)),
+        Err(err) => {
+          maybe_err.get_or_insert(err);
+        }
+      }
+    }
+    Err(maybe_err.unwrap_or_else(|| {
+      anyhow!("There are no paths to look for a registry configuration at.")
+    }))
+  }
+
+  /// How much longer the cached response for a registry configuration is fresh
+  /// for, if the configuration is cached.
+  fn get_config_ttl(&self, specifier: &ModuleSpecifier) -> Option<Duration> {
+    let (_, headers, cached) =
+      self.file_fetcher.http_cache.get(specifier).ok()?;
+    let cache_semantics =
+      CacheSemantics::new(headers, cached, SystemTime::now());
+    Some(cache_semantics.time_to_live())
+  }
+
+  /// Determine if the cached response that the configuration of a base was
+  /// loaded from has become stale since it was loaded.
+  fn is_config_expired(&self, base: &str) -> bool {
+    match self.config_loaded.get(base) {
+      Some((loaded_at, ttl)) => loaded_at.elapsed() >= *ttl,
+      None => false,
+    }
+  }
+
+  /// Store a validated registry configuration in memory, along with any
+  /// headers the configuration declares for the origin.
+  fn insert_config(&mut self, base: String, config: RegistryConfigurationJson) {
+    let origin = match Url::parse(&base) {
+      Ok(url) => base_url(&url),
+      Err(_) => base.clone(),
+    };
+    for registry in &config.registries {
+      if let Some(name) = &registry.token_env {
+        if env::var(name).is_err() {
+          warn!(
+            "The environment variable \"{}\" for the registry \"{}\" is not set, requests will not be authenticated.",
+            name, base
+          );
+        }
+      }
+    }
+    if !config.headers.is_empty() {
+      let headers = self.headers.entry(origin).or_default();
+      for (key, value) in config.headers {
+        headers.entry(key).or_insert(value);
+      }
+    }
+    self.origins.insert(base, config.registries);
+  }
+
+  /// Enable a registry by attempting to retrieve its configuration and
+  /// validating it. A `file:` URL enables the registry kept in that directory,
+  /// which completes the specifiers within the directory.
+  pub async fn enable(&mut self, origin: &str) -> Result<(), AnyError> {
+    let origin = enabled_base(&parse_registry_url(origin)?);
+    #[allow(clippy::map_entry)]
+    // we can't use entry().or_insert_with() because we can't use async closures
+    if !self.origins.contains_key(&origin) {
+      let specifiers = self.get_config_specifiers(&origin)?;
+      match self.bundled_configs.get(&origin).cloned() {
+        Some(config) => {
+          self.insert_config(origin.clone(), config);
+          if let Err(err) = self.load_config(origin.clone(), &specifiers).await
+          {
+            info!(
+              "Using the bundled registry configuration for \"{}\". {}",
+              origin, err
+            );
+          }
+        }
+        None => self.load_config(origin, &specifiers).await?,
+      }
+    } else if !self.offline && self.is_config_expired(&origin) {
+      // the configuration is loaded again once the response it was loaded
+      // from is stale, so that a registry which has changed or recovered is
+      // picked up without having to refresh it
+      if let Some(specifier) = self.config_specifiers.get(&origin).cloned() {
+        match self
+          .load_config(origin.clone(), std::slice::from_ref(&specifier))
+          .await
+        {
+          Ok(_) => {
+            self.items_cache.clear();
+            self.failed_items_cache.clear();
+            self.documentation_cache.clear();
+          }
+          Err(err) => {
+            // the failure is cached, so it isn't attempted again
+            self.config_loaded.remove(&origin);
+            info!(
+              "Unable to reload the registry configuration for \"{}\", the previous configuration is still used. {}",
+              origin, err
+            );
+          }
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Enable a registry, sending the supplied headers with every request made
+  /// to the origin, including the request for its configuration.
+  pub async fn enable_with_headers(
+    &mut self,
+    origin: &str,
+    headers: HeadersMap,
+  ) -> Result<(), AnyError> {
+    let origin_url = parse_registry_url(origin)?;
+    self.headers.insert(base_url(&origin_url), headers);
+    self.enable(origin).await
+  }
+
+  /// Force a re-fetch of the registry configuration for an origin, bypassing
+  /// any cached copy of it. If the re-fetch fails, the previously enabled
+  /// configuration for the origin, if any, is left in place.
+  pub async fn refresh(&mut self, origin: &str) -> Result<(), AnyError> {
+    let origin_url = parse_registry_url(origin)?;
+    let base = self.resolve_base(&origin_url);
+    let specifiers = match self.config_specifiers.get(&base) {
+      Some(specifier) => vec![specifier.clone()],
+      None => self.get_config_specifiers(&base_url(&origin_url))?,
+    };
+    for specifier in &specifiers {
+      if !is_local(specifier) {
+        self.file_fetcher.http_cache.remove(specifier)?;
+      }
+    }
+    self.load_config(base, &specifiers).await?;
+    self.items_cache.clear();
+    self.failed_items_cache.clear();
+    self.documentation_cache.clear();
+    Ok(())
+  }
+
+  /// Enable a registry if it isn't already, and fetch the items for the first
+  /// key of each of its schemas into memory, so that the first completions for
+  /// the registry don't have to wait on the network. Fetching the items is
+  /// best effort, failures are logged and are tried again when completing.
+  pub async fn warm(&mut self, origin: &str) -> Result<(), AnyError> {
+    self.enable(origin).await?;
+    let base = enabled_base(&parse_registry_url(origin)?);
+    let mut paths = Vec::new();
+    for registry in self.origins.get(&base).into_iter().flatten() {
+      let maybe_path = registry
+        .compiled
+        .as_ref()
+        .and_then(|schema| get_first_key_path(&schema.tokens));
+      if let Some(path) = maybe_path {
+        if !paths.contains(&path) {
+          paths.push(path);
+        }
+      }
+    }
+    for path in paths {
+      // the items are fetched by completing the path, so that they are
+      // cached exactly as a completion would request them
+      let specifier = match join_base(&base, &path) {
+        Ok(specifier) => specifier,
+        Err(err) => {
+          info!("Unable to warm the registry \"{}\". {}", base, err);
+          continue;
+        }
+      };
+      let current_specifier = specifier.to_string();
+      let offset = current_specifier.chars().count();
+      let outcome = self
+        .complete_specifier(
+          &current_specifier,
+          Some(&specifier),
+          offset,
+          &lsp::Range::default(),
+          |_| false,
+        )
+        .await;
+      if !matches!(outcome, CompletionOutcome::Matched(_)) {
+        info!(
+          "Unable to warm the registry \"{}\" for \"{}\".",
+          base, specifier
+        );
+      }
+    }
+    Ok(())
+  }
+
+  /// Remove all of the cached registry configurations and endpoint responses,
+  /// both from memory and from the registries location on disk. All of the
+  /// registries are disabled and need to be enabled again to be used.
+  pub fn clear_cache(&mut self) -> Result<(), AnyError> {
+    self.origins.clear();
+    self.config_specifiers.clear();
+    self.config_loaded.clear();
+    self.headers.clear();
+    self.items_cache.clear();
+    self.failed_items_cache.clear();
+    self.documentation_cache.clear();
+    self.discovered.lock().clear();
+    self.errors.lock().clear();
+    self.file_fetcher.http_cache.clear()
+  }
+
+  /// Enable a registry using an explicit URL for its configuration, instead of
+  /// the well known path at the root of its origin. The base may include a
+  /// path prefix, which allows registries that are served from a path of an
+  /// origin, for example behind a reverse proxy. Schemas of the registry are
+  /// then matched against the part of a specifier after the base.
+  pub async fn enable_with_config_url(
+    &mut self,
+    base: &str,
+    config_url: &str,
+  ) -> Result<(), AnyError> {
+    let base = registry_base(&parse_registry_url(base)?);
+    let specifier = Url::parse(config_url)?;
+    if !self.origins.contains_key(&base) {
+      self
+        .load_config(base, std::slice::from_ref(&specifier))
+        .await?;
+    }
+
+    Ok(())
+  }
+
+  /// Enable a registry using a configuration read from a local file, as if it
+  /// had been fetched from the origin. This allows a registry configuration to
+  /// be tried out before it is deployed. Any configuration already enabled for
+  /// the origin is replaced, so the file can be enabled again after editing it.
+  pub fn enable_from_file(
+    &mut self,
+    origin: &str,
+    path: &Path,
+  ) -> Result<(), AnyError> {
+    let origin = enabled_base(&parse_registry_url(origin)?);
+    let result = std::fs::read_to_string(path)
+      .with_context(|| {
+        format!(
+          "Unable to read the registry configuration \"{}\".",
+          path.display()
+        )
+      })
+      .and_then(|source| parse_config(&source).and_then(compile_config));
+    let config = match result {
+      Ok(config) => config,
+      Err(err) => {
+        self.set_error(&origin, &err);
+        return Err(err);
+      }
+    };
+    self.errors.lock().remove(&origin);
+    self.config_specifiers.remove(&origin);
+    self.insert_config(origin, config);
+    Ok(())
+  }
+
+  /// Find the enabled registries that a specifier belongs to along with their
+  /// base, preferring the longest base when several bases share an origin.
+  fn find_registries(
+    &self,
+    specifier: &Url,
+  ) -> Option<(&String, &Vec<RegistryConfiguration>)> {
+    let s = specifier.as_str();
+    self
+      .origins
+      .iter()
+      .filter(|(base, _)| {
+        s.starts_with(base.as_str())
+          && matches!(s[base.len()..].chars().next(), None | Some('/'))
+      })
+      .max_by_key(|(base, _)| base.len())
+  }
+
+  /// Iterate over the registries that are served from an origin, including
+  /// those served from a path prefix of the origin.
+  fn get_registries_for_origin<'a>(
+    &'a self,
+    origin: &str,
+  ) -> impl Iterator<Item = &'a RegistryConfiguration> + 'a {
+    let origin = origin.to_string();
+    self
+      .origins
+      .iter()
+      .filter(move |(base, _)| {
+        Url::parse(base).map(|url| base_url(&url)).ok() == Some(origin.clone())
+      })
+      .flat_map(|(_, registries)| registries.iter())
+  }
+
+  /// Resolve the base that an origin or base passed to the public API refers
+  /// to, falling back to the plain origin for registries that were enabled
+  /// using `enable`.
+  fn resolve_base(&self, url: &Url) -> String {
+    let base = registry_base(url);
+    if self.origins.contains_key(&base) {
+      base
+    } else {
+      base_url(url)
+    }
+  }
+
+  /// For a string specifier from the client, provide a set of completions, if
+  /// any, for the specifier. The `offset` of the cursor within the specifier
+  /// is in UTF-16 code units.
+  ///
+  /// Each request supersedes any request which is still in progress, including
+  /// those made to clones of the registry. A superseded request drops any of
+  /// its pending fetches and resolves with `None` straight away, so that stale
+  /// completions are never returned out of order.
+  pub(crate) async fn get_completions(
+    &self,
+    current_specifier: &str,
+    offset: usize,
+    range: &lsp::Range,
+    specifier_exists: impl Fn(&ModuleSpecifier) -> bool,
+  ) -> Option<lsp::CompletionList> {
+    // a host typed in Unicode is completed in its ASCII form, which is the
+    // form that the bases are stored in
+    let maybe_ascii_host = AsciiHost::parse(current_specifier);
+    let (current_specifier, offset) =
+      AsciiHost::to_ascii(&maybe_ascii_host, current_specifier, offset);
+    let maybe_specifier = parse_registry_url(current_specifier).ok();
+    let maybe_list = self
+      .complete(
+        current_specifier,
+        maybe_specifier.as_ref(),
+        offset,
+        range,
+        specifier_exists,
+      )
+      .await;
+    match maybe_ascii_host {
+      Some(ascii_host) => {
+        maybe_list.map(|list| ascii_host.to_typed_list(list, range))
+      }
+      None => maybe_list,
+    }
+  }
+
+  /// For a specifier which has already been parsed, provide a set of
+  /// completions, if any, for the specifier. This is the same as
+  /// `get_completions()` without parsing the specifier again, where the
+  /// `offset` is within the serialization of the specifier.
+  pub(crate) async fn get_completions_for(
+    &self,
+    specifier: &ModuleSpecifier,
+    offset: usize,
+    range: &lsp::Range,
+    specifier_exists: impl Fn(&ModuleSpecifier) -> bool,
+  ) -> Option<lsp::CompletionList> {
+    let maybe_specifier = if is_registry_url(specifier) {
+      Some(specifier)
+    } else {
+      None
+    };
+    self
+      .complete(
+        specifier.as_str(),
+        maybe_specifier,
+        offset,
+        range,
+        specifier_exists,
+      )
+      .await
+  }
+
+  async fn complete(
+    &self,
+    current_specifier: &str,
+    maybe_specifier: Option<&ModuleSpecifier>,
+    offset: usize,
+    range: &lsp::Range,
+    specifier_exists: impl Fn(&ModuleSpecifier) -> bool,
+  ) -> Option<lsp::CompletionList> {
+    RegistryCounters::increment(&self.counters.completion_requests);
+    let generation = self.completion_generation.next();
+    if !self.could_match(current_specifier) {
+      return None;
+    }
+    tokio::select! {
+      biased;
+      _ = self.completion_generation.superseded(generation) => None,
+      maybe_list = self.complete_list(
+        current_specifier,
+        maybe_specifier,
+        offset,
+        range,
+        specifier_exists,
+      ) => maybe_list,
+    }
+  }
+
+  /// Complete a string specifier against the enabled registries, offering the
+  /// enabled origins when the specifier doesn't belong to one of them.
+  async fn complete_list(
+    &self,
+    current_specifier: &str,
+    maybe_specifier: Option<&ModuleSpecifier>,
+    offset: usize,
+    range: &lsp::Range,
+    specifier_exists: impl Fn(&ModuleSpecifier) -> bool,
+  ) -> Option<lsp::CompletionList> {
+    let outcome = self
+      .complete_specifier(
+        current_specifier,
+        maybe_specifier,
+        offset,
+        range,
+        specifier_exists,
+      )
+      .await;
+    match outcome {
+      CompletionOutcome::NotAnOrigin | CompletionOutcome::BeforePath => {
+        self.get_origin_completions(current_specifier, range)
+      }
+      CompletionOutcome::NoMatch => None,
+      CompletionOutcome::Matched(list) => Some(list),
+    }
+  }
+
+  /// Complete a string specifier like `get_completions()`, resolving with just
+  /// the specifiers that the completions would insert, so that the registries
+  /// can be used outside of the language server. Unlike `get_completions()`,
+  /// this doesn't supersede any completion request which is in progress.
+  pub async fn complete_specifiers(
+    &self,
+    current_specifier: &str,
+    offset: usize,
+  ) -> Vec<ModuleSpecifier> {
+    // segment edits only insert the segment being completed, and so the
+    // completions are made as if they were disabled
+    let registry;
+    let registry = if self.segment_edits {
+      registry = Self {
+        segment_edits: false,
+        ..self.clone()
+      };
+      &registry
+    } else {
+      self
+    };
+    let maybe_ascii_host = AsciiHost::parse(current_specifier);
+    let (current_specifier, offset) =
+      AsciiHost::to_ascii(&maybe_ascii_host, current_specifier, offset);
+    if !registry.could_match(current_specifier) {
+      return Vec::new();
+    }
+    let maybe_specifier = parse_registry_url(current_specifier).ok();
+    let maybe_list = registry
+      .complete_list(
+        current_specifier,
+        maybe_specifier.as_ref(),
+        offset,
+        &lsp::Range::default(),
+        |_| false,
+      )
+      .await;
+    let mut specifiers = Vec::new();
+    for item in maybe_list.map(|list| list.items).unwrap_or_default() {
+      if let Some(specifier) = get_completion_specifier(&item) {
+        if !specifiers.contains(&specifier) {
+          specifiers.push(specifier);
+        }
+      }
+    }
+    specifiers
+  }
+
+  /// Cheaply determine if a string specifier from the client could be
+  /// completed by any of the enabled registries without parsing it, so that
+  /// text which can't belong to a registry is passed over on every keystroke.
+  /// The specifier could match when it is a prefix of an enabled base, or of
+  /// its host when no scheme has been typed yet, or when it starts with an
+  /// enabled base. While any patterns are enabled, any specifier could match.
+  fn could_match(&self, current_specifier: &str) -> bool {
+    if !self.patterns.is_empty() {
+      return true;
+    }
+    let has_scheme = current_specifier.contains("://");
+    self.origins.keys().any(|base| {
+      let host = base
+        .split_once("://")
+        .map_or(base.as_str(), |(_, host)| host);
+      starts_with_ignore_case(base, current_specifier)
+        || starts_with_ignore_case(current_specifier, base)
+        || (!has_scheme && host.starts_with(current_specifier))
+    })
+  }
+
+  /// For a string specifier from the client, complete the specifier against
+  /// the enabled registries, describing why no completions were provided when
+  /// that is the case. Unlike `get_completions()`, the enabled origins are
+  /// not offered when the specifier does not belong to one of them.
+  pub(crate) async fn get_completions_detailed(
+    &self,
+    current_specifier: &str,
+    offset: usize,
+    range: &lsp::Range,
+    specifier_exists: impl Fn(&ModuleSpecifier) -> bool,
+  ) -> CompletionOutcome {
+    let maybe_ascii_host = AsciiHost::parse(current_specifier);
+    let (current_specifier, offset) =
+      AsciiHost::to_ascii(&maybe_ascii_host, current_specifier, offset);
+    let maybe_specifier = parse_registry_url(current_specifier).ok();
+    let outcome = self
+      .complete_specifier(
+        current_specifier,
+        maybe_specifier.as_ref(),
+        offset,
+        range,
+        specifier_exists,
+      )
+      .await;
+    match (outcome, maybe_ascii_host) {
+      (CompletionOutcome::Matched(list), Some(ascii_host)) => {
+        CompletionOutcome::Matched(ascii_host.to_typed_list(list, range))
+      }
+      (outcome, _) => outcome,
+    }
+  }
+
+  /// Complete the string specifier from the client against the enabled
+  /// registries, where `maybe_specifier` is the specifier once parsed, which
+  /// is `None` when it can't belong to a registry.
+  async fn complete_specifier(
+    &self,
+    current_specifier: &str,
+    maybe_specifier: Option<&ModuleSpecifier>,
+    offset: usize,
+    range: &lsp::Range,
+    specifier_exists: impl Fn(&ModuleSpecifier) -> bool,
+  ) -> CompletionOutcome {
+    if let Some(specifier) = maybe_specifier {
+      let discovered;
+      let found = match self.find_registries(specifier) {
+        Some((origin, registries)) => {
+          Some((origin.as_str(), registries.as_slice()))
+        }
+        None => {
+          discovered = self.discover(specifier).await;
+          discovered.as_ref().map(|(origin, registries)| {
+            (origin.as_str(), registries.as_slice())
+          })
+        }
+      };
+      if let Some((origin, registries)) = found {
+        // the path is decoded before it is matched, which keeps encoded
+        // characters from being encoded again when completed, and so the
+        // offset is measured in characters of the decoded path as well
+        let byte_offset = to_byte_index(current_specifier, offset);
+        if byte_offset >= origin.len() {
+          let path = decode_path(&specifier.as_str()[origin.len()..]);
+          // the query and the fragment are only matched by the registries
+          // which opt into them, otherwise they are stripped, so that a
+          // pasted specifier with a fragment still matches its path
+          let (path_with_query, maybe_fragment) = match path.split_once('#') {
+            Some((path, fragment)) => (path, Some(fragment)),
+            None => (path.as_str(), None),
+          };
+          let path_without_query =
+            path_with_query.split('?').next().unwrap_or_default();
+          let path_offset = current_specifier
+            .get(origin.len()..byte_offset)
+            .map_or(0, |p| decode_path(p).chars().count());
+          let mut completions = HashMap::<String, lsp::CompletionItem>::new();
+          let mut is_incomplete = false;
+          let mut did_match = false;
+          // Work out which items each registry needs, so that all of the
+          // fetches for the origin can be made at the same time.
+          let mut pending = Vec::new();
+          for registry in registries {
+            let schema = match &registry.compiled {
+              Some(schema) => schema,
+              None => {
+                // a single broken registry shouldn't keep the other registries
+                // of the origin from providing completions
+                error!(
+                  "Registry schema \"{}\" for origin \"{}\" has not been compiled.",
+                  registry.schema, origin
+                );
+                continue;
+              }
+            };
+            let tokens = &schema.tokens;
+            let path = if registry.match_query {
+              path_with_query
+            } else {
+              path_without_query
+            };
+            let path = match maybe_fragment {
+              Some(fragment) if registry.match_fragment => {
+                Cow::Owned(format!("{}#{}", path, fragment))
+              }
+              _ => Cow::Borrowed(path),
+            };
+            let path = path.as_ref();
+            let mut i = tokens.len();
+            let last_key_name =
+              StringOrNumber::String(tokens.iter().last().map_or_else(
+                || "".to_string(),
+                |t| {
+                  if let Token::Key(key) = t {
+                    if let StringOrNumber::String(s) = &key.name {
+                      return s.clone();
+                    }
+                  }
+                  "".to_string()
+                },
+              ));
+            loop {
+              let matcher = &schema.matchers[i];
+              if let Some(match_result) = matcher.matches(path) {
+                did_match = true;
+                let completor_type =
+                  get_completor_type(path_offset, tokens, &match_result);
+                match completor_type {
+                  Some(CompletorType::Literal(s)) => self.complete_literal(
+                    s,
+                    &mut completions,
+                    current_specifier,
+                    byte_offset,
+                    range,
+                  ),
+                  Some(CompletorType::Key { key, prefix, index }) => {
+                    let typed = match &key.name {
+                      StringOrNumber::String(name) => match_result
+                        .get(name)
+                        .map(|s| s.to_string(Some(&key)))
+                        .unwrap_or_default(),
+                      _ => "".to_string(),
+                    };
+                    let maybe_variable =
+                      registry.get_variable_for_key(&key, &match_result.params);
+                    if let Some(min_query_length) = maybe_variable
+                      .and_then(|v| get_min_query_length(v, &typed))
+                    {
+                      // the items aren't requested until enough of the value
+                      // is typed, which the client asks for again as it is
+                      is_incomplete = true;
+                      completions.insert(
+                        format!("{}\0{}", key.name, min_query_length),
+                        get_min_query_placeholder(
+                          &key,
+                          min_query_length,
+                          current_specifier,
+                          range,
+                        ),
+                      );
+                    } else if let Some(variable) = maybe_variable {
+                      let template_match =
+                        registry.get_template_match(&key, &match_result);
+                      pending.push(PendingItems::Key {
+                        registry,
+                        tokens,
+                        last_key_name,
+                        match_result,
+                        template_match,
+                        key,
+                        prefix,
+                        index,
+                        variable,
+                      });
+                    }
+                  }
+                  None => (),
+                }
+                break;
+              }
+              i -= 1;
+              // If we have fallen though to the first token, and we still
+              // didn't get a match
+              if i == 0 {
+                match &tokens[i] {
+                  // so if the first token is a string literal, we will return
+                  // that as a suggestion
+                  Token::String(s) => {
+                    let is_unanchored =
+                      registry.anchored && path.trim_matches('/').is_empty();
+                    if s.starts_with(path) && !is_unanchored {
+                      let label = s.to_string();
+                      let kind = Some(lsp::CompletionItemKind::FOLDER);
+                      let url = match join_base(origin, s) {
+                        Ok(url) => url,
+                        Err(err) => {
+                          error!(
+                            "Invalid registry schema \"{}\" for origin \"{}\": {}",
+                            registry.schema, origin, err
+                          );
+                          break;
+                        }
+                      };
+                      let full_text = url.as_str();
+                      let text_edit =
+                        Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+                          range: *range,
+                          new_text: full_text.to_string(),
+                        }));
+                      // like other literals, only the literal is filtered on
+                      let filter_text = Some(s.to_string());
+                      completions.insert(
+                        s.to_string(),
+                        lsp::CompletionItem {
+                          label,
+                          kind,
+                          filter_text,
+                          sort_text: Some("1".to_string()),
+                          text_edit,
+                          preselect: Some(true),
+                          ..Default::default()
+                        },
+                      );
+                      // offer a snippet which lays out the rest of the schema
+                      // with a tabstop for each variable
+                      if self.snippet_support
+                        && tokens.iter().any(|t| matches!(t, Token::Key(_)))
+                      {
+                        let snippet = get_snippet(tokens);
+                        let text_edit =
+                          Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+                            range: *range,
+                            new_text: join_base_str(origin, &snippet),
+                          }));
+                        completions.insert(
+                          snippet,
+                          lsp::CompletionItem {
+                            label: registry.schema.clone(),
+                            kind: Some(lsp::CompletionItemKind::SNIPPET),
+                            detail: Some("(registry schema)".to_string()),
+                            filter_text: Some(full_text.to_string()),
+                            sort_text: Some("2".to_string()),
+                            text_edit,
+                            insert_text_format: Some(
+                              lsp::InsertTextFormat::SNIPPET,
+                            ),
+                            ..Default::default()
+                          },
+                        );
+                      }
+                    }
+                  }
+                  // if the token though is a key, and the key has a prefix, and
+                  // the path matches the prefix, we will go and get the items
+                  // for that first key and return them.
+                  Token::Key(k) => {
+                    if let Some(prefix) = &k.prefix {
+                      let maybe_variable =
+                        registry.get_variable_for_key(k, &HashMap::new());
+                      if let Some(min_query_length) =
+                        maybe_variable.and_then(|v| get_min_query_length(v, ""))
+                      {
+                        is_incomplete = true;
+                        completions.insert(
+                          format!("{}\0{}", k.name, min_query_length),
+                          get_min_query_placeholder(
+                            k,
+                            min_query_length,
+                            current_specifier,
+                            range,
+                          ),
+                        );
+                      } else if let Some(variable) = maybe_variable {
+                        pending.push(PendingItems::FirstKey {
+                          registry,
+                          tokens,
+                          last_key_name,
+                          key: k,
+                          prefix,
+                          variable,
+                        });
+                      }
+                    }
+                  }
+                }
+                break;
+              }
+            }
+          }
+          let results = future::join_all(pending.iter().map(|p| match p {
+            PendingItems::Key {
+              tokens,
+              template_match,
+              key,
+              variable,
+              ..
+            } => future::Either::Left(self.get_variable_items(
+              key,
+              variable,
+              specifier,
+              tokens,
+              template_match,
+            )),
+            PendingItems::FirstKey { key, variable, .. } => {
+              let maybe_body = variable
+                .get_body()
+                .map(|body| replace_variable(body, key, None));
+              future::Either::Right(self.get_items(&variable.url, maybe_body))
+            }
+          }))
+          .await;
+          // The results are merged in the order of the registries and then the
+          // order of the items, so the completions are deterministic.
+          for (pending, maybe_items) in pending.iter().zip(results) {
+            let items = match maybe_items {
+              Some(items) => items,
+              None => continue,
+            };
+            match pending {
+              PendingItems::Key {
+                registry,
+                tokens,
+                last_key_name,
+                match_result,
+                key,
+                prefix,
+                index,
+                variable,
+                ..
+              } => {
+                let compiler = registry.get_compiler(&tokens[..=*index]);
+                let warning = items.get_warning().map(String::from);
+                let deprecated = items.get_deprecated();
+                let (items, preselect, incomplete) = items.into_parts();
+                if incomplete || items.len() > self.get_max_parsed_items() {
+                  // not all of the items have been scored against what has
+                  // been typed
+                  is_incomplete = true;
+                }
+                if items.is_empty() {
+                  insert_example_items(
+                    &mut completions,
+                    variable,
+                    key,
+                    current_specifier,
+                    range,
+                  );
+                }
+                if registry.suggest_latest
+                  && key.modifier.as_deref() == Some("?")
+                {
+                  let compiler =
+                    registry.get_compiler(&get_latest_tokens(tokens, *index));
+                  let mut params = match_result.params.clone();
+                  params.remove(&key.name);
+                  let path = compiler.to_path(&params).unwrap_or_default();
+                  if let Ok(item_specifier) = join_base(origin, &path) {
+                    let full_text = item_specifier.as_str();
+                    completions.entry(full_text.to_string()).or_insert(
+                      lsp::CompletionItem {
+                        label: "latest".to_string(),
+                        kind: Some(lsp::CompletionItemKind::FOLDER),
+                        detail: Some(format!("({})", key.name)),
+                        sort_text: Some(format!("{:0>10}", 0)),
+                        filter_text: Some(current_specifier.to_string()),
+                        text_edit: Some(lsp::CompletionTextEdit::Edit(
+                          lsp::TextEdit {
+                            range: *range,
+                            new_text: full_text.to_string(),
+                          },
+                        )),
+                        ..Default::default()
+                      },
+                    );
+                  }
+                }
+                let partial = if prefix.is_none() {
+                  match &key.name {
+                    StringOrNumber::String(name) => match_result
+                      .get(name)
+                      .map(|s| s.to_string(Some(key)))
+                      .unwrap_or_default(),
+                    _ => "".to_string(),
+                  }
+                } else {
+                  "".to_string()
+                };
+                let key_items = KeyItems {
+                  registry,
+                  tokens,
+                  index: *index,
+                  last_key_name,
+                  key,
+                  variable,
+                  specifier,
+                  range,
+                  warning,
+                  deprecated,
+                  preselect: get_typed_preselect(&items, &partial, preselect),
+                };
+                let maybe_segment_range = if self.segment_edits {
+                  Some(get_segment_range(
+                    current_specifier,
+                    origin.len(),
+                    range,
+                    tokens,
+                    *index,
+                    match_result,
+                    path_offset,
+                  ))
+                } else {
+                  None
+                };
+                // all of the parsed items are scored and ordered before any are
+                // dropped for exceeding the maximum, so that the best matches
+                // are kept instead of the first ones that match
+                let mut items: Vec<(String, String, Option<String>)> = items
+                  .into_iter()
+                  .take(self.get_max_parsed_items())
+                  .enumerate()
+                  .filter_map(|(idx, (item, description))| {
+                    let sort_text = if self.fuzzy && !partial.is_empty() {
+                      let score = get_fuzzy_score(&partial, &item)?;
+                      format!("{:0>10}{:0>10}", score, idx + 1)
+                    } else {
+                      format!("{:0>10}", idx + 1)
+                    };
+                    Some((sort_text, item, description))
+                  })
+                  .collect();
+                items.sort_by(|a, b| a.0.cmp(&b.0));
+                for (sort_text, item, description) in items {
+                  if completions.len() >= self.max_completion_items {
+                    is_incomplete = true;
+                    break;
+                  }
+                  let sort_text = Some(sort_text);
+                  let label = if let Some(p) = prefix {
+                    format!("{}{}", p, item)
+                  } else {
+                    item.clone()
+                  };
+                  let kind = if key.name == *last_key_name {
+                    lsp::CompletionItemKind::FILE
+                  } else {
+                    lsp::CompletionItemKind::FOLDER
+                  };
+                  let kind = Some(variable.kind.map_or(kind, Into::into));
+                  let mut params = match_result.params.clone();
+                  params.insert(
+                    key.name.clone(),
+                    StringOrVec::from_str(&item, key),
+                  );
+                  let item_specifier = match compiler
+                    .to_path(&params)
+                    .and_then(|path| Ok(join_base(origin, &path)?))
+                  {
+                    Ok(url) => url,
+                    Err(err) => {
+                      self.skip_invalid_item(origin, &item, err);
+                      continue;
+                    }
+                  };
+                  let full_text = item_specifier.as_str();
+                  let (text_edit, filter_text) = match maybe_segment_range {
+                    Some(segment_range) => (
+                      lsp::TextEdit {
+                        range: segment_range,
+                        new_text: label.clone(),
+                      },
+                      label.clone(),
+                    ),
+                    None => (
+                      lsp::TextEdit {
+                        range: *range,
+                        new_text: full_text.to_string(),
+                      },
+                      full_text.to_string(),
+                    ),
+                  };
+                  // items are keyed by the specifier they resolve to, so the
+                  // first item for a specifier wins
+                  completions.entry(full_text.to_string()).or_insert(
+                    lsp::CompletionItem {
+                      label,
+                      kind,
+                      sort_text,
+                      filter_text: Some(filter_text),
+                      text_edit: Some(lsp::CompletionTextEdit::Edit(text_edit)),
+                      ..self.get_key_item(
+                        &key_items,
+                        &item,
+                        description,
+                        &item,
+                        &item_specifier,
+                        &specifier_exists,
+                      )
+                    },
+                  );
+                }
+              }
+              PendingItems::FirstKey {
+                registry,
+                tokens,
+                last_key_name,
+                key: k,
+                prefix,
+                variable,
+              } => {
+                let warning = items.get_warning().map(String::from);
+                let deprecated = items.get_deprecated();
+                let (items, preselect, incomplete) = items.into_parts();
+                if incomplete {
+                  is_incomplete = true;
+                }
+                if items.is_empty() {
+                  insert_example_items(
+                    &mut completions,
+                    variable,
+                    k,
+                    current_specifier,
+                    range,
+                  );
+                }
+                let key_items = KeyItems {
+                  registry,
+                  tokens,
+                  index: 0,
+                  last_key_name,
+                  key: k,
+                  variable,
+                  specifier,
+                  range,
+                  warning,
+                  deprecated,
+                  preselect,
+                };
+                for (idx, (item, description)) in items.into_iter().enumerate()
+                {
+                  if completions.len() >= self.max_completion_items {
+                    is_incomplete = true;
+                    break;
+                  }
+                  let path = format!("{}{}", prefix, item);
+                  let kind = Some(
+                    variable
+                      .kind
+                      .map_or(lsp::CompletionItemKind::FOLDER, Into::into),
+                  );
+                  let item_specifier = match join_base(origin, &path) {
+                    Ok(url) => url,
+                    Err(err) => {
+                      self.skip_invalid_item(origin, &item, err);
+                      continue;
+                    }
+                  };
+                  let full_text = item_specifier.as_str();
+                  completions.entry(full_text.to_string()).or_insert(
+                    lsp::CompletionItem {
+                      label: item.clone(),
+                      kind,
+                      sort_text: Some(format!("{:0>10}", idx + 1)),
+                      filter_text: Some(full_text.to_string()),
+                      text_edit: Some(lsp::CompletionTextEdit::Edit(
+                        lsp::TextEdit {
+                          range: *range,
+                          new_text: full_text.to_string(),
+                        },
+                      )),
+                      ..self.get_key_item(
+                        &key_items,
+                        &item,
+                        description,
+                        &path,
+                        &item_specifier,
+                        &specifier_exists,
+                      )
+                    },
+                  );
+                }
+              }
+            }
+          }
+          // If we return None, other sources of completions will be looked for
+          // but if we did at least match part of a registry, we should send an
+          // empty vector so that no-completions will be sent back to the client
+          return if completions.is_empty() && !did_match {
+            CompletionOutcome::NoMatch
+          } else {
+            let mut items: Vec<lsp::CompletionItem> =
+              completions.into_values().collect();
+            sort_completions(&mut items);
+            CompletionOutcome::Matched(lsp::CompletionList {
+              items,
+              is_incomplete,
+            })
+          };
+        }
+        return CompletionOutcome::BeforePath;
+      }
+    }
+
+    CompletionOutcome::NotAnOrigin
+  }
+
+  /// Note a variable item which is skipped because it doesn't resolve to a
+  /// valid specifier, so that the authors of a registry can see that their
+  /// schema produces bad URLs for it.
+  fn skip_invalid_item(
+    &self,
+    origin: &str,
+    item: &str,
+    err: impl fmt::Display,
+  ) {
+    debug!(
+      "Skipping the item \"{}\" of the registry \"{}\" as it does not resolve to a valid specifier. {}",
+      item, origin, err
+    );
+    RegistryCounters::increment(&self.counters.invalid_items);
+  }
+
+  /// Build the completion item for an item of the variable of a key, which
+  /// resolves to `item_specifier`, where `value` is the value of the key that
+  /// the documentation of the item is requested for. The label, kind, sort and
+  /// filter text and the edit of the item depend on how it is completed, and
+  /// are left for the caller to fill in.
+  fn get_key_item(
+    &self,
+    key_items: &KeyItems,
+    item: &str,
+    description: Option<String>,
+    value: &str,
+    item_specifier: &ModuleSpecifier,
+    specifier_exists: &impl Fn(&ModuleSpecifier) -> bool,
+  ) -> lsp::CompletionItem {
+    let KeyItems {
+      registry,
+      tokens,
+      index,
+      last_key_name,
+      key,
+      variable,
+      specifier,
+      range,
+      ..
+    } = key_items;
+    let command = if self.should_prefetch(key, last_key_name)
+      && !specifier_exists(item_specifier)
+    {
+      Some(lsp::Command {
+        title: "".to_string(),
+        command: "deno.cache".to_string(),
+        arguments: Some(registry.get_cache_arguments(item_specifier)),
+      })
+    } else {
+      None
+    };
+    let label_details = if self.label_details_support {
+      Some(get_label_details(description.as_deref(), key))
+    } else {
+      None
+    };
+    let commit_characters = if self.commit_characters {
+      get_commit_characters(tokens, *index)
+    } else {
+      None
+    };
+    lsp::CompletionItem {
+      label_details,
+      documentation: get_documentation(description.as_deref()),
+      detail: get_detail(description, key, key_items.warning.as_deref()),
+      command,
+      preselect: get_preselect(item.to_string(), key_items.preselect.clone()),
+      data: get_data(variable, specifier, key, value),
+      tags: get_tags(item, &key_items.deprecated),
+      commit_characters,
+      additional_text_edits: registry
+        .get_assertion_edits(item_specifier, range),
+      ..Default::default()
+    }
+  }
+
+  /// Resolve the details of a registry completion item from the `data` that
+  /// was attached to it, fetching the documentation that it refers to. Returns
+  /// `None` if the data does not refer to any documentation.
+  pub async fn get_completion_details(
+    &self,
+    data: &Value,
+  ) -> Option<lsp::CompletionItem> {
+    let url = data.get("documentation")?.as_str()?;
+    let documentation = self.get_documentation(url).await;
+    Some(lsp::CompletionItem {
+      documentation,
+      ..Default::default()
+    })
+  }
+
+  /// Fetch the documentation for a completion item from a registry. The
+  /// response is either an LSP `Documentation` as JSON, or when the registry
+  /// declares a documentation format, the documentation itself in that format.
+  /// Parsed documentation is kept in memory, so resolving the same item again
+  /// doesn't fetch it again.
+  pub async fn get_documentation(
+    &self,
+    url: &str,
+  ) -> Option<lsp::Documentation> {
+    let specifier = Url::parse(url).ok()?;
+    let maybe_documentation = self.documentation_cache.get(&specifier);
+    self.counters.record_cache(maybe_documentation.is_some());
+    if let Some(documentation) = maybe_documentation {
+      return Some(documentation);
+    }
+    let documentation = self.fetch_documentation(&specifier).await?;
+    self
+      .documentation_cache
+      .insert(specifier, documentation.clone());
+    Some(documentation)
+  }
+
+  async fn fetch_documentation(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> Option<lsp::Documentation> {
+    let file = self
+      .observe(
+        FetchKind::Documentation,
+        specifier,
+        self.fetch_accepting(specifier, Some(DOCUMENTATION_ACCEPT)),
+      )
+      .await
+      .ok()?;
+    // documentation that is JSON is used as it is, otherwise the format is the
+    // one the registry declares, falling back to the content type of the
+    // response
+    let maybe_format = self.get_documentation_format(specifier).or_else(|| {
+      file
+        .maybe_headers
+        .as_ref()
+        .and_then(|headers| headers.get("content-type"))
+        .and_then(|content_type| get_content_type_format(content_type))
+    });
+    let documentation = match serde_json::from_str(&file.source) {
+      Ok(documentation) => documentation,
+      Err(_) if maybe_format.is_some() => {
+        lsp::Documentation::String(file.source.to_string())
+      }
+      Err(_) => return None,
+    };
+    match documentation {
+      lsp::Documentation::String(value) => {
+        if let Some(format) = maybe_format {
+          let value = if format == DocumentationFormat::Html {
+            html_to_markdown(&value)
+          } else {
+            value
+          };
+          Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+            kind: format.into(),
+            value,
+          }))
+        } else {
+          Some(lsp::Documentation::String(value))
+        }
+      }
+      documentation => Some(documentation),
+    }
+  }
+
+  /// Determine the documentation format declared by the registries of the
+  /// origin that a documentation URL belongs to, if any.
+  fn get_documentation_format(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> Option<DocumentationFormat> {
+    let origin = base_url(specifier);
+    let discovered = self.get_discovered(&origin);
+    let maybe_format = self
+      .get_registries_for_origin(&origin)
+      .chain(discovered.iter().flat_map(|c| c.registries.iter()))
+      .find_map(|r| r.documentation_format);
+    maybe_format
+  }
+
+  /// Provide completions for the enabled registries whose base starts with
+  /// the current specifier. When the current specifier has no scheme, it is
+  /// also matched against the host of the bases, completing to the full base,
+  /// so that typing `deno.land` offers `https://deno.land`.
+  pub fn get_origin_completions(
+    &self,
+    current_specifier: &str,
+    range: &lsp::Range,
+  ) -> Option<lsp::CompletionList> {
+    let has_scheme = current_specifier.contains("://");
+    let mut items = self
+      .origins
+      .keys()
+      .filter_map(|k| {
+        // the bases are normalized without a trailing slash when enabled
+        let origin = k.to_string();
+        let host = origin
+          .split_once("://")
+          .map_or(origin.as_str(), |(_, host)| host);
+        let filter_text = if origin.starts_with(current_specifier) {
+          None
+        } else if !has_scheme && host.starts_with(current_specifier) {
+          // the client filters items against what has been typed, and so the
+          // item needs to be filtered on the host instead of the label
+          Some(host.to_string())
+        } else {
+          return None;
+        };
+        let text_edit = Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+          range: *range,
+          new_text: origin.clone(),
+        }));
+        Some(lsp::CompletionItem {
+          label: origin,
+          kind: Some(lsp::CompletionItemKind::FOLDER),
+          detail: Some("(registry)".to_string()),
+          sort_text: Some("2".to_string()),
+          filter_text,
+          text_edit,
+          ..Default::default()
+        })
+      })
+      .collect::<Vec<lsp::CompletionItem>>();
+    sort_completions(&mut items);
+    if !items.is_empty() {
+      Some(lsp::CompletionList {
+        items,
+        is_incomplete: false,
+      })
+    } else {
+      None
+    }
+  }
+
+  async fn get_items(
+    &self,
+    url: &str,
+    maybe_body: Option<String>,
+  ) -> Option<VariableItems> {
+    let specifier = ModuleSpecifier::parse(url).ok()?;
+    self.fetch_items_pages(&specifier, maybe_body).await
+  }
+
+  async fn get_variable_items(
+    &self,
+    key: &Key,
+    variable: &RegistryConfigurationVariable,
+    base: &Url,
+    tokens: &[Token],
+    match_result: &MatchResult,
+  ) -> Option<VariableItems> {
+    let url = &variable.url;
+    let specifier =
+      get_endpoint_with_match(key, url, base, tokens, match_result, None)
+        .map_err(|err| {
+          error!("Internal error mapping endpoint \"{}\". {}", url, err);
+        })
+        .ok()?;
+    let maybe_body = variable
+      .get_body()
+      .map(|body| fill_template(key, body, tokens, match_result, None));
+    self.fetch_items_pages(&specifier, maybe_body).await
+  }
+
+  /// Fetch the variable items from an endpoint, following the `nextUrl` of
+  /// each page of items, up to a maximum number of pages or until there are
+  /// more items than are parsed for completions, merging the pages. The next pages are
+  /// always requested with a `GET`. If a next page can't be fetched, the items
+  /// fetched so far are used.
+  async fn fetch_items_pages(
+    &self,
+    specifier: &ModuleSpecifier,
+    maybe_body: Option<String>,
+  ) -> Option<VariableItems> {
+    let mut items = self.fetch_items(specifier, maybe_body).await?;
+    let mut page_specifier = specifier.clone();
+    let mut visited = HashSet::new();
+    visited.insert(page_specifier.clone());
+    for _ in 1..MAX_ITEMS_PAGES {
+      if items.len() > self.get_max_parsed_items() {
+        break;
+      }
+      let next_specifier = match items
+        .get_next_url()
+        .map(|url| page_specifier.join(url))
+      {
+        Some(Ok(next_specifier)) if visited.insert(next_specifier.clone()) => {
+          next_specifier
+        }
+        _ => break,
+      };
+      let next = match self.fetch_items(&next_specifier, None).await {
+        Some(next) => next,
+        None => break,
+      };
+      items = match items.clone().append_page(next) {
+        Some(items) => items,
+        None => break,
+      };
+      page_specifier = next_specifier;
+    }
+    Some(items)
+  }
+
+  /// The additional headers to send with a request to a registry, which are
+  /// the headers scoped to the origin of the resource along with any bearer
+  /// token for the origin.
+  fn get_headers(&self, specifier: &ModuleSpecifier) -> Option<HeadersMap> {
+    let origin = base_url(specifier);
+    let mut maybe_headers = self.headers.get(&origin).cloned().or_else(|| {
+      self
+        .get_discovered(&origin)
+        .map(|config| config.headers)
+        .filter(|headers| !headers.is_empty())
+    });
+    if let Some(token) = self.get_auth_token(&origin) {
+      maybe_headers
+        .get_or_insert_with(HashMap::new)
+        .insert("authorization".to_string(), format!("Bearer {}", token));
+    }
+    maybe_headers
+  }
+
+  /// Fetch a resource for a registry, sending any additional headers that are
+  /// scoped to the origin of the resource.
+  async fn fetch(&self, specifier: &ModuleSpecifier) -> Result<File, AnyError> {
+    self.fetch_accepting(specifier, None).await
+  }
+
+  /// Fetch a resource for a registry like `fetch()`, sending an `Accept`
+  /// header as well, unless the headers for the origin already include one.
+  async fn fetch_accepting(
+    &self,
+    specifier: &ModuleSpecifier,
+    maybe_accept: Option<&str>,
+  ) -> Result<File, AnyError> {
+    RegistryCounters::increment(&self.counters.fetches);
+    let mut maybe_headers = self.get_headers(specifier);
+    if let Some(accept) = maybe_accept {
+      let headers = maybe_headers.get_or_insert_with(HashMap::new);
+      if !headers.keys().any(|key| key.eq_ignore_ascii_case("accept")) {
+        headers.insert("accept".to_string(), accept.to_string());
+      }
+    }
+    self
+      .send_with_retries(specifier, || {
+        let maybe_headers = maybe_headers.clone();
+        async move {
+          let mut permissions = Permissions::allow_all();
+          self
+            .file_fetcher
+            .fetch_uncached(specifier, &mut permissions, maybe_headers)
+            .await
+        }
+      })
+      .await
+  }
+
+  /// Post a body to a registry endpoint, resolving with the body of the
+  /// response. Unlike fetched resources, responses to a post are never cached
+  /// on disk.
+  async fn post(
+    &self,
+    specifier: &ModuleSpecifier,
+    body: String,
+  ) -> Result<(String, Option<String>), AnyError> {
+    if self.offline {
+      return Err(anyhow!(
+        "Unable to post to \"{}\" while offline.",
+        specifier
+      ));
+    }
+    RegistryCounters::increment(&self.counters.fetches);
+    let headers = self.get_headers(specifier).unwrap_or_default();
+    self
+      .send_with_retries(specifier, || {
+        let mut request = self
+          .file_fetcher
+          .http_client
+          .post(specifier.clone())
+          .body(body.clone());
+        for (key, value) in &headers {
+          request = request.header(key.as_str(), value.as_str());
+        }
+        async move {
+          let response = request.send().await?.error_for_status()?;
+          let maybe_content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+          Ok((response.text().await?, maybe_content_type))
+        }
+      })
+      .await
+  }
+
+  /// Send a request to a registry, retrying it after a transient error, until
+  /// it succeeds or the fetch timeout elapses.
+  async fn send_with_retries<T, F, Fut>(
+    &self,
+    specifier: &ModuleSpecifier,
+    send: F,
+  ) -> Result<T, AnyError>
+  where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, AnyError>>,
+  {
+    let fut = async {
+      let mut retries = 0;
+      loop {
+        // the permit is only held while a request is in flight, and not while
+        // waiting to retry it
+        let result = {
+          let _permit = self.fetch_permits.acquire().await?;
+          send().await
+        };
+        match result {
+          Err(err)
+            if retries < self.max_retries && is_transient_error(&err) =>
+          {
+            tokio::time::sleep(self.retry_delay * 2_u32.pow(retries)).await;
+            retries += 1;
+          }
+          result => return result,
+        }
+      }
+    };
+    match tokio::time::timeout(self.fetch_timeout, fut).await {
+      Ok(result) => result,
+      Err(_) => {
+        let message = format!(
+          "Timed out fetching \"{}\" after {}ms.",
+          specifier,
+          self.fetch_timeout.as_millis()
+        );
+        error!("{}", message);
+        Err(anyhow!(message))
+      }
+    }
+  }
+
+  /// Resolve a bearer token for an origin from the environment variable named
+  /// by the `tokenEnv` of one of its registries, if any is set.
+  fn get_auth_token(&self, origin: &str) -> Option<String> {
+    let discovered = self.get_discovered(origin);
+    let maybe_token = self
+      .get_registries_for_origin(origin)
+      .chain(discovered.iter().flat_map(|c| c.registries.iter()))
+      .filter_map(|r| r.token_env.as_ref())
+      .find_map(|name| env::var(name).ok());
+    maybe_token
+  }
+
+  /// Wait until a request can be made to the origin of the specifier without
+  /// making requests to the origin more often than the minimum fetch interval,
+  /// returning `true` if the request had to wait.
+  async fn throttle(&self, specifier: &ModuleSpecifier) -> bool {
+    // reading a local file doesn't put any load on a server
+    if is_local(specifier) {
+      return false;
+    }
+    let delay = {
+      let mut last_fetches = self.last_fetches.lock();
+      let now = Instant::now();
+      let scheduled = match last_fetches.get(&base_url(specifier)) {
+        Some(last) => now.max(*last + self.min_fetch_interval),
+        None => now,
+      };
+      last_fetches.insert(base_url(specifier), scheduled);
+      scheduled - now
+    };
+    if delay.is_zero() {
+      false
+    } else {
+      tokio::time::sleep(delay).await;
+      true
+    }
+  }
+
+  /// Hold off requests to the origin of the specifier for the delay a
+  /// registry asked for.
+  fn back_off(&self, specifier: &ModuleSpecifier, delay: Duration) {
+    let origin = base_url(specifier);
+    let delay = delay.min(MAX_RETRY_AFTER);
+    let until = match Instant::now().checked_add(delay) {
+      Some(until) => until,
+      None => return,
+    };
+    let mut backoffs = self.backoffs.lock();
+    let is_backing_off = matches!(
+      backoffs.get(&origin),
+      Some(current) if *current > Instant::now()
+    );
+    if !is_backing_off {
+      info!(
+        "Registry \"{}\" is rate limiting requests, holding off requests to it for {}s.",
+        origin,
+        delay.as_secs()
+      );
+    }
+    backoffs.insert(origin, until);
+  }
+
+  /// Determine if requests to the origin of the specifier are being held off,
+  /// because the registry asked for that.
+  fn is_backing_off(&self, specifier: &ModuleSpecifier) -> bool {
+    let origin = base_url(specifier);
+    let mut backoffs = self.backoffs.lock();
+    match backoffs.get(&origin) {
+      Some(until) if *until > Instant::now() => true,
+      Some(_) => {
+        backoffs.remove(&origin);
+        false
+      }
+      None => false,
+    }
+  }
+
+  /// Read a response from the cache on disk, however stale it is.
+  fn get_cached_source(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> Option<(String, Option<String>)> {
+    let (mut file, headers, _) =
+      self.file_fetcher.http_cache.get(specifier).ok()?;
+    let mut source = String::new();
+    file.read_to_string(&mut source).ok()?;
+    if source.is_empty() {
+      None
+    } else {
+      Some((source, headers.get("content-type").cloned()))
+    }
+  }
+
+  /// Fetch and parse the variable items from an endpoint, reusing a recently
+  /// parsed response for the same endpoint if there is one. If the same
+  /// request recently failed, it is not attempted again and `None` is
+  /// returned. While the registry has asked for requests to be held off, only
+  /// a response from the cache is used.
+  async fn fetch_items(
+    &self,
+    specifier: &ModuleSpecifier,
+    maybe_body: Option<String>,
+  ) -> Option<VariableItems> {
+    let cache_key = (specifier.clone(), maybe_body.clone());
+    let maybe_items = self.items_cache.get(&cache_key);
+    self.counters.record_cache(maybe_items.is_some());
+    if let Some(items) = maybe_items {
+      return Some(items);
+    }
+    let (source, maybe_content_type) =
+      if !self.offline && self.is_backing_off(specifier) {
+        match maybe_body {
+          Some(_) => return None,
+          None => {
+            let (source, maybe_content_type) =
+              self.get_cached_source(specifier)?;
+            (Arc::new(source), maybe_content_type)
+          }
+        }
+      } else {
+        if self.failed_items_cache.contains(&cache_key) {
+          return None;
+        }
+        if !self.offline && self.throttle(specifier).await {
+          // the same items may have been fetched while waiting
+          if let Some(items) = self.items_cache.get(&cache_key) {
+            return Some(items);
+          }
+        }
+        let result = self
+          .observe(FetchKind::Endpoint, specifier, async {
+            match maybe_body {
+              Some(body) => self.post(specifier, body).await.map(
+                |(source, maybe_content_type)| {
+                  (Arc::new(source), maybe_content_type)
+                },
+              ),
+              None => self.fetch(specifier).await.map(|file| {
+                let maybe_content_type = file
+                  .maybe_headers
+                  .as_ref()
+                  .and_then(|headers| headers.get("content-type").cloned());
+                (file.source, maybe_content_type)
+              }),
+            }
+          })
+          .await;
+        result
+          .map_err(|err| {
+            // when offline, the failure only means the endpoint hasn't been
+            // cached, and so it is attempted again once back online
+            if !self.offline {
+              error!(
+                "Internal error fetching endpoint \"{}\". {}",
+                specifier, err
+              );
+              self.failed_items_cache.insert(cache_key.clone());
+              if let Some(delay) = get_retry_after(&err) {
+                self.back_off(specifier, delay);
+              }
+            }
+          })
+          .ok()?
+      };
+    // only the items up to the maximum are used, and one more than the
+    // maximum shows that the completions are incomplete, where fuzzy matched
+    // items are scored from a multiple of the maximum
+    let max_items = Some(self.get_max_parsed_items() + 1);
+    let result = if is_ndjson(maybe_content_type.as_deref()) {
+      parse_ndjson_items(&source, max_items)
+    } else {
+      parse_variable_items(&source, max_items)
+    };
+    let items = result
+      .map_err(|err| {
+        error!(
+          "Error parsing response from endpoint \"{}\". {}",
+          specifier, err
+        );
+        self.failed_items_cache.insert(cache_key.clone());
+      })
+      .ok()?;
+    if let Some(warning) = items.get_warning() {
+      if self.logged_warnings.lock().insert(warning.to_string()) {
+        warn!("Registry endpoint \"{}\" warns: {}", specifier, warning);
+      }
+    }
+    self.items_cache.insert(cache_key, items.clone());
+    Some(items)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  /// Enable a registry for the test server whose modules are the items of
+  /// `endpoint`, requested with a token of the test's own, so that the state
+  /// the server keeps for the endpoint isn't shared with other tests. Returns
+  /// the URL the items are requested from.
+  fn enable_with_token(
+    module_registry: &mut ModuleRegistry,
+    dir: &Path,
+    endpoint: &str,
+  ) -> ModuleSpecifier {
+    let url = format!(
+      "http://localhost:4545/lsp/registries/{}?token={}",
+      endpoint,
+      uuid::Uuid::new_v4()
+    );
+    let config = json!({
+      "version": 2,
+      "registries": [
+        {
+          "schema": "/x/:module([a-z0-9_]*)/:path*",
+          "variables": [
+            {
+              "key": "module",
+              "url": url
+            },
+            {
+              "key": "path",
+              "url": "/lsp/registries/${module}_latest.json"
+            }
+          ]
+        }
+      ]
+    });
+    let path = dir.join("deno-import-intellisense.json");
+    std::fs::write(&path, config.to_string()).unwrap();
+    module_registry
+      .enable_from_file("http://localhost:4545/", &path)
+      .expect("could not enable");
+    resolve_url(&url).unwrap()
+  }
+
+  #[test]
+  fn test_validate_config_str() {
+    let result = validate_config_str(
+      r#"{
+        "version": 1,
+        "registries": [
+          {
+            "schema": "/:module@:version",
+            "variables": [
+              { "key": "module", "url": "https://api.deno.land/modules" },
+              { "key": "version", "url": "https://api.deno.land/${module}" }
+            ]
+          }
+        ]
+      }"#,
+    );
+    assert!(result.is_ok());
+    let err = validate_config_str(r#"{ "version": 1, "registries": "#)
+      .unwrap_err()
+      .to_string();
+    assert!(err.contains("EOF while parsing"));
+    let err = validate_config_str(r#"{ "version": 4, "registries": [] }"#)
+      .unwrap_err()
+      .to_string();
+    assert_eq!(
+      err,
+      RegistryConfigError::UnsupportedVersion { got: 4 }.to_string()
+    );
+    let result = validate_config_str(
+      r#"{ "version": 1, "minClientVersion": 1, "registries": [] }"#,
+    );
+    assert!(result.is_ok());
+  }
+
+  #[test]
+  fn test_validate_config_str_compiles() {
+    let err = validate_config_str(
+      r#"{
+        "version": 2,
+        "registries": [
+          {
+            "schema": "/:module@:version?",
+            "variables": [
+              { "key": "module", "url": "/modules" },
+              {
+                "key": "version",
+                "url": "/recent/${module}",
+                "when": "version == \"\""
+              }
+            ]
+          }
+        ]
+      }"#,
+    )
+    .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      RegistryConfigError::UnresolvableKey {
+        schema: "/:module@:version?".to_string(),
+        key: "version".to_string(),
+        condition: "`version != \"\"`".to_string(),
+      }
+      .to_string()
+    );
+    let err = validate_config_str(
+      r#"{
+        "version": 2,
+        "registries": [
+          {
+            "schema": "/:module(",
+            "variables": [{ "key": "module", "url": "/modules" }]
+          }
+        ]
+      }"#,
+    );
+    assert!(err.is_err());
+  }
+
+  #[test]
+  fn test_validate_config_str_version() {
+    let err = validate_config_str(r#"{ "registries": [] }"#)
+      .unwrap_err()
+      .to_string();
+    assert_eq!(err, "Invalid registry configuration. The configuration is missing the required integer field \"version\".");
+    let err = validate_config_str(r#"{ "version": "1", "registries": [] }"#)
+      .unwrap_err()
+      .to_string();
+    assert_eq!(err, "Invalid registry configuration. The required field \"version\" must be an integer, but is a string.");
+    let err = validate_config_str(r#"{ "version": 1.5, "registries": [] }"#)
+      .unwrap_err()
+      .to_string();
+    assert_eq!(
+      err,
+      RegistryConfigError::MissingVersion {
+        found: Some("a non-integer number")
+      }
+      .to_string()
+    );
+  }
+
+  #[test]
+  fn test_validate_registry_configuration() {
+    assert_eq!(
+      validate_config(&RegistryConfigurationJson {
+        version: 4,
+        ..Default::default()
+      }),
+      Err(RegistryConfigError::UnsupportedVersion { got: 4 })
+    );
+
+    let cfg = RegistryConfigurationJson {
+      version: 1,
+      registries: vec![RegistryConfiguration {
+        schema: "/:module@:version/:path*".to_string(),
+        variables: vec![
+          RegistryConfigurationVariable {
+            key: "module".to_string(),
+            documentation: None,
+            url: "https://api.deno.land/modules?short".to_string(),
+            kind: None,
+            method: None,
+            body: None,
+            when: None,
+            default: None,
+            min_query_length: None,
+            examples: Vec::new(),
+          },
+          RegistryConfigurationVariable {
+            key: "version".to_string(),
+            documentation: None,
+            url: "https://deno.land/_vsc1/module/${module}".to_string(),
+            kind: None,
+            method: None,
+            body: None,
+            when: None,
+            default: None,
+            min_query_length: None,
+            examples: Vec::new(),
+          },
+        ],
+        ..Default::default()
+      }],
+      ..Default::default()
+    };
+    assert!(validate_config(&cfg).is_err());
+
+    let cfg = RegistryConfigurationJson {
+      version: 1,
+      registries: vec![RegistryConfiguration {
+        schema: "/:module@:version/:path*".to_string(),
+        variables: vec![
+          RegistryConfigurationVariable {
+            key: "module".to_string(),
+            documentation: None,
+            url: "https://api.deno.land/modules?short".to_string(),
+            kind: None,
+            method: None,
+            body: None,
+            when: None,
+            default: None,
+            min_query_length: None,
+            examples: Vec::new(),
+          },
+          RegistryConfigurationVariable {
+            key: "version".to_string(),
+            documentation: None,
+            url: "https://deno.land/_vsc1/module/${module}/${path}".to_string(),
+            kind: None,
+            method: None,
+            body: None,
+            when: None,
+            default: None,
+            min_query_length: None,
+            examples: Vec::new(),
+          },
+          RegistryConfigurationVariable {
+            key: "path".to_string(),
+            documentation: None,
+            url: "https://deno.land/_vsc1/module/${module}/v/${{version}}"
+              .to_string(),
+            kind: None,
+            method: None,
+            body: None,
+            when: None,
+            default: None,
+            min_query_length: None,
+            examples: Vec::new(),
+          },
+        ],
+        ..Default::default()
+      }],
+      ..Default::default()
+    };
+    assert!(validate_config(&cfg).is_err());
+
+    let cfg = RegistryConfigurationJson {
+      version: 1,
+      registries: vec![RegistryConfiguration {
+        schema: "/:module@:version/:path*".to_string(),
+        variables: vec![
+          RegistryConfigurationVariable {
+            key: "module".to_string(),
+            documentation: None,
+            url: "https://api.deno.land/modules?short".to_string(),
+            kind: None,
+            method: None,
+            body: None,
+            when: None,
+            default: None,
+            min_query_length: None,
+            examples: Vec::new(),
+          },
+          RegistryConfigurationVariable {
+            key: "version".to_string(),
+            documentation: None,
+            url: "https://deno.land/_vsc1/module/${module}/v/${{version}}"
+              .to_string(),
+            kind: None,
+            method: None,
+            body: None,
+            when: None,
+            default: None,
+            min_query_length: None,
+            examples: Vec::new(),
+          },
+          RegistryConfigurationVariable {
+            key: "path".to_string(),
+            documentation: None,
+            url: "https://deno.land/_vsc1/module/${module}/v/${{version}}"
+              .to_string(),
+            kind: None,
+            method: None,
+            body: None,
+            when: None,
+            default: None,
+            min_query_length: None,
+            examples: Vec::new(),
+          },
+        ],
+        ..Default::default()
+      }],
+      ..Default::default()
+    };
+    assert!(validate_config(&cfg).is_err());
+
+    let cfg = RegistryConfigurationJson {
+      version: 1,
+      registries: vec![RegistryConfiguration {
+        schema: "/:module@:version/:path*".to_string(),
+        variables: vec![
+          RegistryConfigurationVariable {
+            key: "module".to_string(),
             documentation: None,
             url: "https://api.deno.land/modules?short".to_string(),
+            kind: None,
+            method: None,
+            body: None,
+            when: None,
+            default: None,
+            min_query_length: None,
+            examples: Vec::new(),
+          },
+          RegistryConfigurationVariable {
+            key: "version".to_string(),
+            documentation: None,
+            url: "https://deno.land/_vsc1/module/${module}".to_string(),
+            kind: None,
+            method: None,
+            body: None,
+            when: None,
+            default: None,
+            min_query_length: None,
+            examples: Vec::new(),
+          },
+          RegistryConfigurationVariable {
+            key: "path".to_string(),
+            documentation: None,
+            url: "https://deno.land/_vsc1/module/${module}/v/${{version}}"
+              .to_string(),
+            kind: None,
+            method: None,
+            body: None,
+            when: None,
+            default: None,
+            min_query_length: None,
+            examples: Vec::new(),
+          },
+        ],
+        ..Default::default()
+      }],
+      ..Default::default()
+    };
+    assert!(validate_config(&cfg).is_ok());
+
+    let cfg: RegistryConfigurationJson = serde_json::from_value(json!({
+      "version": 2,
+      "registries": [
+        {
+          "schema": "/x/:module([a-z0-9_]+)@:version?/:path",
+          "variables": [
+            {
+              "key": "module",
+              "documentation": "/api/details/mods/${module}",
+              "url": "/api/mods/${module}"
+            },
+            {
+              "key": "version",
+              "documentation": "/api/details/mods/${module}/v/${{version}}",
+              "url": "/api/mods/${module}/v/${{version}}"
+            },
+            {
+              "key": "path",
+              "documentation": "/api/details/mods/${module}/v/${{version}}/p/${path}",
+              "url": "/api/mods/${module}/v/${{version}}/p/${path}"
+            }
+          ]
+        },
+        {
+          "schema": "/x/:module([a-z0-9_]+)/:path",
+          "variables": [
+            {
+              "key": "module",
+              "documentation": "/api/details/mods/${module}",
+              "url": "/api/mods/${module}"
+            },
+            {
+              "key": "path",
+              "documentation": "/api/details/mods/${module}/v/latest/p/${path}",
+              "url": "/api/mods/${module}/v/latest/p/${path}"
+            }
+          ]
+        }
+      ]
+    })).unwrap();
+    assert!(validate_config(&cfg).is_ok());
+
+    let cfg: RegistryConfigurationJson = serde_json::from_value(json!({
+      "version": 2,
+      "registries": [
+        {
+          "schema": "/x/:module([a-z0-9_]+)@:version?/:path",
+          "variables": [
+            {
+              "key": "module",
+              "documentation": "/api/details/mods/${module}/v/${{version}}",
+              "url": "/api/mods/${module}"
+            },
+            {
+              "key": "version",
+              "url": "/api/mods/${module}/v/${{version}}"
+            },
+            {
+              "key": "path",
+              "url": "/api/mods/${module}/v/${{version}}/p/${path}"
+            }
+          ]
+        }
+      ]
+    }))
+    .unwrap();
+    let err = validate_config(&cfg).unwrap_err();
+    assert!(err.to_string().contains("Documentation url"));
+    assert_eq!(
+      err,
+      RegistryConfigError::OutOfScopeVariable {
+        schema: "/x/:module([a-z0-9_]+)@:version?/:path".to_string(),
+        variable: "module".to_string(),
+        referenced: "version".to_string(),
+        url: "/api/details/mods/${module}/v/${{version}}".to_string(),
+        documentation: true,
+      }
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_origin_match() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 21,
+      },
+    };
+    let completions = module_registry
+      .get_completions("h", 1, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].label, "http://localhost:4545");
+    assert_eq!(
+      completions[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545".to_string()
+      }))
+    );
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 36,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost", 16, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].label, "http://localhost:4545");
+    assert_eq!(
+      completions[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545".to_string()
+      }))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_origin_host_match() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let configs = module_registry.origins["http://localhost:4545"].clone();
+    module_registry
+      .origins
+      .insert("https://deno.land".to_string(), configs);
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 29,
+      },
+    };
+    let completions = module_registry
+      .get_completions("deno.land", 9, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].label, "https://deno.land");
+    assert_eq!(completions[0].filter_text, Some("deno.land".to_string()));
+    assert_eq!(
+      completions[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "https://deno.land".to_string()
+      }))
+    );
+    let completions = module_registry
+      .get_completions("local", 5, &range, |_| false)
+      .await
+      .unwrap()
+      .items;
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].label, "http://localhost:4545");
+    // the host is only matched when the scheme is omitted
+    let completions = module_registry
+      .get_completions("http://deno.land", 16, &range, |_| false)
+      .await;
+    assert!(completions.is_none());
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 41,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545", 21, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].label, "/x");
+    assert_eq!(
+      completions[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/x".to_string()
+      }))
+    );
+    // the literal is filtered on its own, while the whole specifier is
+    // replaced
+    assert_eq!(completions[0].filter_text, Some("/x".to_string()));
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 42,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/", 22, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].label, "/x");
+    assert_eq!(
+      completions[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/x".to_string()
+      }))
+    );
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap();
+    assert_eq!(completions.items.len(), 2);
+    assert!(completions.is_incomplete);
+    // the items are ordered by their sort text
+    assert_eq!(completions.items[0].label, "a");
+    assert_eq!(completions.items[1].label, "b");
+
+    // testing for incremental searching for a module
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 45,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a", 25, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap();
+    assert_eq!(completions.items.len(), 4);
+    assert!(!completions.is_incomplete);
+    let labels: Vec<&str> =
+      completions.items.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(labels, vec!["a", "aa", "ab", "aba"]);
+    assert_eq!(
+      completions.items[0].data,
+      Some(json!({
+        "documentation": format!("http://localhost:4545/lsp/registries/doc_{}.json", completions.items[0].label),
+      }))
+    );
+
+    // testing getting the documentation
+    let documentation = module_registry
+      .get_documentation("http://localhost:4545/lsp/registries/doc_a.json")
+      .await;
+    assert_eq!(
+      documentation,
+      Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+        kind: lsp::MarkupKind::Markdown,
+        value: "**a**".to_string(),
+      }))
+    );
+
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a@", 26, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 3);
+    let labels: Vec<&str> =
+      completions.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(labels, vec!["v1.0.0", "v1.0.1", "v2.0.0"]);
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 53,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a@v1.0.0/", 33, &range, |_| {
+        false
+      })
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 2);
+    assert_eq!(completions[0].detail, Some("(path)".to_string()));
+    assert_eq!(completions[0].kind, Some(lsp::CompletionItemKind::FILE));
+    assert!(completions[0].command.is_some());
+    assert_eq!(completions[1].detail, Some("(path)".to_string()));
+    assert_eq!(completions[0].kind, Some(lsp::CompletionItemKind::FILE));
+    assert!(completions[1].command.is_some());
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_segment_edits() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry.set_segment_edits(true);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 54,
+      },
+    };
+    // the cursor is within the module of the specifier, and so only the
+    // module is replaced, leaving the version and the path after it alone
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/ab@v1.0.0/", 25, &range, |_| {
+        false
+      })
+      .await
+      .expect("no completions");
+    assert!(!completions.items.is_empty());
+    for item in &completions.items {
+      assert_eq!(
+        item.text_edit,
+        Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+          range: lsp::Range {
+            start: lsp::Position {
+              line: 0,
+              character: 44,
+            },
+            end: lsp::Position {
+              line: 0,
+              character: 46,
+            },
+          },
+          new_text: item.label.clone(),
+        }))
+      );
+      assert_eq!(item.filter_text, Some(item.label.clone()));
+    }
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a@", 26, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert_eq!(completions.items.len(), 3);
+    assert_eq!(
+      completions.items[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range: lsp::Range {
+          start: lsp::Position {
+            line: 0,
+            character: 46,
+          },
+          end: lsp::Position {
+            line: 0,
+            character: 46,
+          },
+        },
+        new_text: "v1.0.0".to_string(),
+      }))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_detailed() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 43,
+      },
+    };
+    let outcome = module_registry
+      .get_completions_detailed("http://127.0.0.1:4545/", 22, &range, |_| false)
+      .await;
+    assert_eq!(outcome, CompletionOutcome::NotAnOrigin);
+    let outcome = module_registry
+      .get_completions_detailed("http://localhost:4545", 5, &range, |_| false)
+      .await;
+    assert_eq!(outcome, CompletionOutcome::BeforePath);
+    // the origin is offered instead when the cursor is before the path
+    let completions = module_registry
+      .get_completions("http://localhost:4545", 5, &range, |_| false)
+      .await
+      .expect("expected origin completions");
+    assert_eq!(completions.items.len(), 1);
+    assert_eq!(completions.items[0].label, "http://localhost:4545");
+    let outcome = module_registry
+      .get_completions_detailed("http://localhost:4545/y", 23, &range, |_| {
+        false
+      })
+      .await;
+    assert_eq!(outcome, CompletionOutcome::NoMatch);
+    assert!(module_registry
+      .get_completions("http://localhost:4545/y", 23, &range, |_| false)
+      .await
+      .is_none());
+    let outcome = module_registry
+      .get_completions_detailed("http://localhost:4545/", 22, &range, |_| false)
+      .await;
+    match outcome {
+      CompletionOutcome::Matched(list) => {
+        assert_eq!(list.items.len(), 1);
+        assert_eq!(list.items[0].label, "/x");
+      }
+      outcome => panic!("unexpected outcome: {:?}", outcome),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_registry_stats() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    assert_eq!(module_registry.stats(), RegistryStats::default());
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-defaults.json",
+      )
+      .await
+      .expect("could not enable");
+    let stats = module_registry.stats();
+    assert_eq!(stats.fetches, 1);
+    assert_eq!(stats.validation_failures, 0);
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    for _ in 0..3 {
+      let completions = module_registry
+        .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+        .await;
+      assert!(completions.is_some());
+    }
+    let stats = module_registry.stats();
+    assert_eq!(stats.completion_requests, 3);
+    // the items are only fetched for the first completion, and found in
+    // memory for the others
+    assert_eq!(stats.fetches, 2);
+    assert_eq!(stats.cache_misses, 1);
+    assert_eq!(stats.cache_hits, 2);
+    // the counters are shared by clones of the registry
+    let clone = module_registry.clone();
+    clone
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await;
+    assert_eq!(module_registry.stats().completion_requests, 4);
+    let result = module_registry
+      .enable_with_config_url(
+        "http://127.0.0.1:4545/",
+        "http://localhost:4545/lsp/registries/doc_a.json",
+      )
+      .await;
+    assert!(result.is_err());
+    assert_eq!(module_registry.stats().validation_failures, 1);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_max_items() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry.set_max_completion_items(2);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 45,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a", 25, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap();
+    assert_eq!(completions.items.len(), 2);
+    assert!(completions.is_incomplete);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_key_first() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url("http://localhost:4545/", "http://localhost:4545/lsp/registries/deno-import-intellisense-key-first.json")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 42,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/", 22, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 3);
+    for completion in completions {
+      assert!(completion.text_edit.is_some());
+      if let lsp::CompletionTextEdit::Edit(edit) = completion.text_edit.unwrap()
+      {
+        assert_eq!(
+          edit.new_text,
+          format!("http://localhost:4545/{}", completion.label)
+        );
+      } else {
+        unreachable!("unexpected text edit");
+      }
+    }
+
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/cde@", 26, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 2);
+    for completion in completions {
+      assert!(completion.text_edit.is_some());
+      if let lsp::CompletionTextEdit::Edit(edit) = completion.text_edit.unwrap()
+      {
+        assert_eq!(
+          edit.new_text,
+          format!("http://localhost:4545/cde@{}", completion.label)
+        );
+      } else {
+        unreachable!("unexpected text edit");
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_registry_documentation_format() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-v3.json",
+      )
+      .await
+      .expect("could not enable");
+    let documentation = module_registry
+      .get_documentation(
+        "http://localhost:4545/lsp/registries/doc_plaintext_a.json",
+      )
+      .await;
+    assert_eq!(
+      documentation,
+      Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+        kind: lsp::MarkupKind::PlainText,
+        value: "**a**".to_string(),
+      }))
+    );
+    let documentation = module_registry
+      .get_documentation("http://localhost:4545/lsp/registries/doc_a.json")
+      .await;
+    assert_eq!(
+      documentation,
+      Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+        kind: lsp::MarkupKind::Markdown,
+        value: "**a**".to_string(),
+      }))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_documentation_raw() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    let enable = |module_registry: &mut ModuleRegistry, config: &str| {
+      let config_url = format!(
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-{}.json",
+        config
+      );
+      let mut module_registry = module_registry.clone();
+      async move {
+        module_registry
+          .disable("http://localhost:4545/")
+          .await
+          .expect("could not disable");
+        module_registry
+          .enable_with_config_url("http://localhost:4545/", &config_url)
+          .await
+          .expect("could not enable");
+        module_registry
+      }
+    };
+    module_registry = enable(&mut module_registry, "v3").await;
+    let documentation = module_registry
+      .get_documentation("http://localhost:4545/lsp/registries/doc_a.txt")
+      .await;
+    assert_eq!(
+      documentation,
+      Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+        kind: lsp::MarkupKind::PlainText,
+        value: "The *a* module.\n".to_string(),
+      }))
+    );
+    module_registry = enable(&mut module_registry, "markdown").await;
+    let documentation = module_registry
+      .get_documentation("http://localhost:4545/lsp/registries/doc_a.md")
+      .await;
+    assert_eq!(
+      documentation,
+      Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+        kind: lsp::MarkupKind::Markdown,
+        value: "# a\n\nThe **a** module.\n".to_string(),
+      }))
+    );
+    module_registry = enable(&mut module_registry, "html").await;
+    let documentation = module_registry
+      .get_documentation("http://localhost:4545/lsp/registries/doc_a.html")
+      .await;
+    assert_eq!(
+      documentation,
+      Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+        kind: lsp::MarkupKind::Markdown,
+        value: "# a\n\nThe **a** module, see [the docs](https://deno.land/x/a).\n\n```\nimport * as a from \"a\";\n```".to_string(),
+      }))
+    );
+  }
+
+  #[test]
+  fn test_get_content_type_format() {
+    assert_eq!(
+      get_content_type_format("text/markdown; charset=utf-8"),
+      Some(DocumentationFormat::Markdown)
+    );
+    assert_eq!(
+      get_content_type_format("Text/Plain"),
+      Some(DocumentationFormat::Plaintext)
+    );
+    assert_eq!(
+      get_content_type_format("text/html"),
+      Some(DocumentationFormat::Html)
+    );
+    assert_eq!(get_content_type_format("application/octet-stream"), None);
+  }
+
+  #[tokio::test]
+  async fn test_registry_documentation_content_type() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    // the endpoint serves markdown, as it is accepted
+    let documentation = module_registry
+      .get_documentation("http://localhost:4545/lsp/registries/doc_negotiated")
+      .await;
+    assert_eq!(
+      documentation,
+      Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+        kind: lsp::MarkupKind::Markdown,
+        value: "# a\n\nThe **a** module.\n".to_string(),
+      }))
+    );
+    let documentation = module_registry
+      .get_documentation("http://localhost:4545/lsp/registries/doc_plaintext")
+      .await;
+    assert_eq!(
+      documentation,
+      Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+        kind: lsp::MarkupKind::PlainText,
+        value: "The a module.\n".to_string(),
+      }))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_documentation_cache() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    let fetches = Arc::new(Mutex::new(0));
+    let listener_fetches = fetches.clone();
+    module_registry.set_event_listener(move |event| {
+      if event.kind == FetchKind::Documentation
+        && event.outcome == FetchOutcome::Started
+      {
+        *listener_fetches.lock() += 1;
+      }
+    });
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let url = "http://localhost:4545/lsp/registries/doc_a.json";
+    let documentation = module_registry.get_documentation(url).await;
+    assert!(documentation.is_some());
+    assert_eq!(module_registry.get_documentation(url).await, documentation);
+    let specifier = resolve_url(url).unwrap();
+    assert!(module_registry
+      .documentation_cache
+      .get(&specifier)
+      .is_some());
+    module_registry
+      .clear_cache()
+      .expect("could not clear cache");
+    assert!(module_registry
+      .documentation_cache
+      .get(&specifier)
+      .is_none());
+    assert_eq!(module_registry.get_documentation(url).await, documentation);
+    // the listener is called on another thread
+    for _ in 0..100 {
+      if *fetches.lock() >= 2 {
+        break;
+      }
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(*fetches.lock(), 2);
+  }
+
+  #[test]
+  fn test_html_to_markdown() {
+    assert_eq!(
+      html_to_markdown("<p>Hello <em>world</em>!</p>\n<p>Bye</p>"),
+      "Hello _world_!\n\nBye"
+    );
+    assert_eq!(
+      html_to_markdown("<ul><li>one</li><li><code>a &lt; b</code></li></ul>"),
+      "- one\n- `a < b`"
+    );
+    assert_eq!(
+      html_to_markdown("<style>p { color: red; }</style><a>plain</a>"),
+      "plain"
+    );
+    assert_eq!(
+      html_to_markdown("<img src=x onerror=alert(1)>text<br/>more"),
+      "text\nmore"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_completion_details() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let details = module_registry
+      .get_completion_details(&json!({
+        "documentation": "http://localhost:4545/lsp/registries/doc_a.json"
+      }))
+      .await
+      .unwrap();
+    assert_eq!(
+      details.documentation,
+      Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+        kind: lsp::MarkupKind::Markdown,
+        value: "**a**".to_string(),
+      }))
+    );
+    assert!(module_registry
+      .get_completion_details(&json!({ "documentation": 1 }))
+      .await
+      .is_none());
+    assert!(module_registry
+      .get_completion_details(&json!(["documentation"]))
+      .await
+      .is_none());
+  }
+
+  #[tokio::test]
+  async fn test_registry_config_max_age() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry.set_config_max_age(Duration::from_secs(120));
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let specifier = resolve_url(
+      "http://localhost:4545/.well-known/deno-import-intellisense.json",
+    )
+    .unwrap();
+    let (_, headers, _) = module_registry
+      .file_fetcher
+      .http_cache
+      .get(&specifier)
+      .unwrap();
+    assert_eq!(
+      headers.get("cache-control"),
+      Some(&"max-age=120".to_string())
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_enable_expired() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let fetches = module_registry.stats().fetches;
+    // while the cached configuration is fresh, it isn't fetched again
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    assert_eq!(module_registry.stats().fetches, fetches);
+    assert!(!module_registry.is_config_expired("http://localhost:4545"));
+    // once it is stale, enabling the registry again fetches it again
+    module_registry.config_loaded.insert(
+      "http://localhost:4545".to_string(),
+      (Instant::now(), Duration::ZERO),
+    );
+    assert!(module_registry.is_config_expired("http://localhost:4545"));
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    assert_eq!(module_registry.stats().fetches, fetches + 1);
+    assert!(!module_registry.is_config_expired("http://localhost:4545"));
+    assert_eq!(module_registry.origins(), vec!["http://localhost:4545"]);
+    // a configuration served with a max age of zero is stale straight away
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry.set_config_max_age(Duration::ZERO);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let fetches = module_registry.stats().fetches;
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    assert_eq!(module_registry.stats().fetches, fetches + 1);
+  }
+
+  #[tokio::test]
+  async fn test_registry_headers() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url("http://localhost:4545/", "http://localhost:4545/lsp/registries/deno-import-intellisense-headers.json")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 50,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/private/", 30, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].label, "private");
+  }
+
+  #[tokio::test]
+  async fn test_registry_token_env() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url("http://localhost:4545/", "http://localhost:4545/lsp/registries/deno-import-intellisense-token.json")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 49,
+      },
+    };
+    env::set_var("DENO_TEST_REGISTRY_TOKEN", "secret");
+    let completions = module_registry
+      .get_completions("http://localhost:4545/bearer/", 29, &range, |_| false)
+      .await;
+    env::remove_var("DENO_TEST_REGISTRY_TOKEN");
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].label, "bearer");
+  }
+
+  #[test]
+  fn test_is_transient_error() {
+    use deno_core::error::custom_error;
+    use deno_core::error::generic_error;
+    use reqwest::StatusCode;
+
+    let err = |status: StatusCode| {
+      let message =
+        format!("Import 'http://localhost:4545/a.json' failed: {}", status);
+      generic_error(message.clone()).context(ErrorStatus::new(message, status))
+    };
+    assert!(is_transient_error(&err(StatusCode::SERVICE_UNAVAILABLE)));
+    assert!(!is_transient_error(&err(StatusCode::UNAUTHORIZED)));
+    // only the status decides, not the message
+    assert!(!is_transient_error(&generic_error(
+      "Import 'http://localhost:4545/a.json' failed: 503 Service Unavailable"
+    )));
+    assert!(!is_transient_error(&custom_error(
+      "NotFound",
+      "Import 'http://localhost:4545/a.json' failed, not found."
+    )));
+  }
+
+  #[test]
+  fn test_decode_path() {
+    assert_eq!(decode_path("/x/my%20mod@1.0.0/"), "/x/my mod@1.0.0/");
+    assert_eq!(decode_path("/x/%C3%B1"), "/x/ñ");
+    assert_eq!(decode_path("/x/a%2Fb%40c%2"), "/x/a%2Fb%40c%2");
+    assert_eq!(decode_path("/x/100%"), "/x/100%");
+  }
+
+  #[test]
+  fn test_to_byte_index() {
+    assert_eq!(to_byte_index("/x/a", 2), 2);
+    assert_eq!(to_byte_index("/ñ/a", 2), 3);
+    assert_eq!(to_byte_index("/😀/a", 3), 5);
+    assert_eq!(to_byte_index("/ñ/a", 10), 5);
+  }
+
+  #[test]
+  fn test_get_fuzzy_score() {
+    assert_eq!(get_fuzzy_score("rct", "react"), Some(2));
+    assert_eq!(get_fuzzy_score("React", "react"), Some(0));
+    assert_eq!(get_fuzzy_score("", "react"), Some(0));
+    assert_eq!(get_fuzzy_score("rx", "react"), None);
+    assert!(get_fuzzy_score("std", "std") < get_fuzzy_score("std", "s_t_d"));
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_fuzzy() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    module_registry.set_fuzzy(true);
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/ab", 26, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let mut completions = completions.unwrap().items;
+    completions.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+    let labels: Vec<&str> =
+      completions.iter().map(|c| c.label.as_str()).collect();
+    assert_eq!(labels, vec!["ab", "aba", "axb", "cab"]);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_fuzzy_max_items() {
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    let workspace = temp_dir.path().join("workspace");
+    std::fs::create_dir_all(workspace.join(".well-known")).unwrap();
+    let mut items: Vec<String> = (0..19).map(|i| format!("m{}", i)).collect();
+    items.push("zz".to_string());
+    std::fs::write(workspace.join("modules.json"), json!(items).to_string())
+      .unwrap();
+    let base = Url::from_directory_path(&workspace).unwrap();
+    let config = json!({
+      "version": 2,
+      "registries": [
+        {
+          "schema": "/x/:module([a-z0-9_]*)",
+          "variables": [
+            {
+              "key": "module",
+              "url": format!("{}modules.json", base)
+            }
+          ]
+        }
+      ]
+    });
+    std::fs::write(
+      workspace.join(".well-known/deno-import-intellisense.json"),
+      config.to_string(),
+    )
+    .unwrap();
+    module_registry
+      .enable(base.as_str())
+      .await
+      .expect("could not enable");
+    module_registry.set_fuzzy(true);
+    module_registry.set_max_completion_items(1);
+    let specifier = format!("{}x/zz", base);
+    let offset = specifier.encode_utf16().count();
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 20 + offset as u32,
+      },
+    };
+    // only ten times the maximum of the items are scored, which doesn't reach
+    // the item that matches
+    let completions = module_registry
+      .get_completions(&specifier, offset, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert!(completions.items.is_empty());
+    assert!(completions.is_incomplete);
+    module_registry.items_cache.clear();
+    module_registry.set_max_completion_items(2);
+    let completions = module_registry
+      .get_completions(&specifier, offset, &range, |_| false)
+      .await
+      .expect("no completions");
+    let labels: Vec<&str> =
+      completions.items.iter().map(|c| c.label.as_str()).collect();
+    assert_eq!(labels, vec!["zz"]);
+    assert!(!completions.is_incomplete);
+    // the best match is kept even when a worse match comes before it
+    std::fs::write(
+      workspace.join("modules.json"),
+      json!(["zaz", "zz"]).to_string(),
+    )
+    .unwrap();
+    module_registry.items_cache.clear();
+    module_registry.set_max_completion_items(1);
+    let completions = module_registry
+      .get_completions(&specifier, offset, &range, |_| false)
+      .await
+      .expect("no completions");
+    let labels: Vec<&str> =
+      completions.items.iter().map(|c| c.label.as_str()).collect();
+    assert_eq!(labels, vec!["zz"]);
+    assert!(completions.is_incomplete);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_typed_preselect() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/ab", 26, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let preselected: Vec<&str> = completions
+      .as_ref()
+      .unwrap()
+      .items
+      .iter()
+      .filter(|c| c.preselect == Some(true))
+      .map(|c| c.label.as_str())
+      .collect();
+    assert_eq!(preselected, vec!["ab"]);
+  }
+
+  #[test]
+  fn test_get_typed_preselect() {
+    let items = vec![
+      ("a".to_string(), None),
+      ("ab".to_string(), None),
+      ("abc".to_string(), None),
+    ];
+    assert_eq!(
+      get_typed_preselect(&items, "ab", Some("a".to_string())),
+      Some("ab".to_string())
+    );
+    assert_eq!(
+      get_typed_preselect(&items, "abd", Some("a".to_string())),
+      Some("a".to_string())
+    );
+    assert_eq!(
+      get_typed_preselect(&items, "", Some("a".to_string())),
+      Some("a".to_string())
+    );
+    assert_eq!(get_typed_preselect(&items, "b", None), None);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_dedupe() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url("http://localhost:4545/", "http://localhost:4545/lsp/registries/deno-import-intellisense-overlap.json")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 50,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/overlap/", 30, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let mut completions = completions.unwrap().items;
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    let labels: Vec<&str> =
+      completions.iter().map(|c| c.label.as_str()).collect();
+    assert_eq!(labels, vec!["a", "b", "c"]);
+    assert_eq!(completions[1].sort_text, Some("0000000002".to_string()));
+    assert_eq!(
+      completions[1].data,
+      Some(json!({
+        "documentation": "http://localhost:4545/lsp/registries/doc_b.json"
+      }))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_fetch_timeout() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry.set_fetch_timeout(Duration::from_millis(100));
+    let items = module_registry
+      .get_items(
+        "http://localhost:4545/lsp/registries/slow_modules.json",
+        None,
+      )
+      .await;
+    assert!(items.is_none());
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_prefetch_policy() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a", 25, &range, |_| false)
+      .await
+      .unwrap()
+      .items;
+    assert!(completions.iter().all(|c| c.command.is_none()));
+
+    module_registry.set_prefetch_policy(PrefetchPolicy::AnyCompleteSpecifier);
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a", 25, &range, |_| false)
+      .await
+      .unwrap()
+      .items;
+    assert_eq!(completions.len(), 4);
+    assert!(completions.iter().all(|c| c.command.is_some()));
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a", 25, &range, |_| true)
+      .await
+      .unwrap()
+      .items;
+    assert!(completions.iter().all(|c| c.command.is_none()));
+  }
+
+  #[test]
+  fn test_sort_items() {
+    let items: Vec<String> = [
+      "v1.2.0",
+      "main",
+      "v1.10.0",
+      "1.2.0-rc.1",
+      "v0.9.1",
+      "1.2.0-beta",
+      "latest",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    assert_eq!(sort_items(items.clone(), ItemsSort::None), items);
+    assert_eq!(
+      sort_items(items.clone(), ItemsSort::SemverDesc),
+      vec![
+        "v1.10.0",
+        "v1.2.0",
+        "1.2.0-rc.1",
+        "1.2.0-beta",
+        "v0.9.1",
+        "main",
+        "latest"
+      ]
+    );
+    assert_eq!(
+      sort_items(items, ItemsSort::SemverAsc),
+      vec![
+        "v0.9.1",
+        "1.2.0-beta",
+        "1.2.0-rc.1",
+        "v1.2.0",
+        "v1.10.0",
+        "main",
+        "latest"
+      ]
+    );
+    let items = parse_variable_items(
+      r#"{
+        "items": ["v1.0.0", "v2.0.0", "v1.0.1"],
+        "preselect": ["v1.0.0", "v2.0.0"],
+        "sort": "semver-desc"
+      }"#,
+      None,
+    )
+    .unwrap();
+    assert_eq!(
+      items.into_parts(),
+      (
+        vec![
+          ("v2.0.0".to_string(), None),
+          ("v1.0.1".to_string(), None),
+          ("v1.0.0".to_string(), None)
+        ],
+        Some("v1.0.0".to_string()),
+        false
+      )
+    );
+    assert!(parse_variable_items(
+      r#"{ "items": ["a"], "sort": "alphabetical" }"#,
+      None
+    )
+    .is_err());
+  }
+
+  #[test]
+  fn test_parse_variable_items() {
+    let items = parse_variable_items(r#"["a", "b", "c"]"#, Some(2)).unwrap();
+    assert_eq!(
+      items.into_parts(),
+      (
+        vec![("a".to_string(), None), ("b".to_string(), None)],
+        None,
+        false
+      )
+    );
+    let items = parse_variable_items(
+      r#"[{ "value": "a", "description": "first" }, "b", { "value": "c" }]"#,
+      None,
+    )
+    .unwrap();
+    assert_eq!(
+      items.into_parts().0,
+      vec![
+        ("a".to_string(), Some("first".to_string())),
+        ("b".to_string(), None),
+        ("c".to_string(), None)
+      ]
+    );
+    let items = parse_variable_items(
+      r#"{
+        "items": ["a", "b", "c"],
+        "isIncomplete": true,
+        "preselect": "b",
+        "warning": "deprecated",
+        "unknown": { "nested": [1, 2] }
+      }"#,
+      Some(2),
+    )
+    .unwrap();
+    assert_eq!(items.get_warning(), Some("deprecated"));
+    assert_eq!(
+      items.into_parts(),
+      (
+        vec![("a".to_string(), None), ("b".to_string(), None)],
+        Some("b".to_string()),
+        true
+      )
+    );
+    assert!(
+      parse_variable_items(r#"{ "items": [{ "value": "a" }] }"#, None).is_err()
+    );
+    assert!(parse_variable_items(r#"{ "isIncomplete": true }"#, None).is_err());
+    assert!(parse_variable_items(r#"["a"] ["b"]"#, None).is_err());
+    assert!(parse_variable_items(r#""a""#, None).is_err());
+  }
+
+  #[test]
+  fn test_parse_ndjson_items() {
+    let items = parse_ndjson_items(
+      "\"a\"\n\n{ \"value\": \"b\", \"description\": \"second\" }\r\n\"c\"\n",
+      None,
+    )
+    .unwrap();
+    assert_eq!(
+      items.into_parts(),
+      (
+        vec![
+          ("a".to_string(), None),
+          ("b".to_string(), Some("second".to_string())),
+          ("c".to_string(), None)
+        ],
+        None,
+        false
+      )
+    );
+    // lines past the maximum are never parsed
+    let items =
+      parse_ndjson_items("\"a\"\n\"b\"\nnot json\n", Some(2)).unwrap();
+    assert_eq!(
+      items.into_parts().0,
+      vec![("a".to_string(), None), ("b".to_string(), None)]
+    );
+    assert!(parse_ndjson_items("\"a\"\nnot json\n", None).is_err());
+    assert!(is_ndjson(Some("application/x-ndjson; charset=utf-8")));
+    assert!(!is_ndjson(Some("application/json")));
+    assert!(!is_ndjson(None));
+  }
+
+  #[test]
+  fn test_variable_items_list_preselect() {
+    let list: VariableItemsList = serde_json::from_value(json!({
+      "items": ["2.0.0-rc.1", "1.1.0", "1.0.0"],
+      "preselect": "1.0.0"
+    }))
+    .unwrap();
+    assert_eq!(list.get_preselect(), Some("1.0.0".to_string()));
+    let list: VariableItemsList = serde_json::from_value(json!({
+      "items": ["2.0.0-rc.1", "1.1.0", "1.0.0"],
+      "preselect": ["1.0.0", "1.1.0"]
+    }))
+    .unwrap();
+    assert_eq!(list.get_preselect(), Some("1.1.0".to_string()));
+    let list: VariableItemsList = serde_json::from_value(json!({
+      "items": ["2.0.0-rc.1"],
+      "preselect": ["1.0.0"]
+    }))
+    .unwrap();
+    assert_eq!(list.get_preselect(), None);
+  }
+
+  #[tokio::test]
+  async fn test_registry_enable_from_file() {
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    let path = test_util::testdata_path()
+      .join("lsp/registries/deno-import-intellisense.json");
+    module_registry
+      .enable_from_file("http://localhost:4545/x/", &path)
+      .expect("could not enable");
+    assert_eq!(module_registry.origins.len(), 1);
+    assert!(module_registry
+      .origins
+      .contains_key("http://localhost:4545"));
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 41,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545", 21, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].label, "/x");
+    let path = temp_dir.path().join("invalid.json");
+    std::fs::write(&path, r#"{ "version": 2, "registries": [{ "schema": "/:module", "variables": [] }] }"#).unwrap();
+    assert!(module_registry
+      .enable_from_file("http://localhost:4546", &path)
+      .is_err());
+    assert!(module_registry
+      .last_error("http://localhost:4546")
+      .is_some());
+    assert!(module_registry
+      .enable_from_file("http://localhost:4546", &temp_dir.path().join("none"))
+      .is_err());
+    assert_eq!(module_registry.origins.len(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_registry_enable_local() {
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    let workspace = temp_dir.path().join("workspace");
+    std::fs::create_dir_all(workspace.join(".well-known")).unwrap();
+    std::fs::write(workspace.join("modules.json"), r#"["a", "b"]"#).unwrap();
+    let base = Url::from_directory_path(&workspace).unwrap();
+    let config = json!({
+      "version": 2,
+      "registries": [
+        {
+          "schema": "/x/:module([a-z0-9_]*)/:path*",
+          "variables": [
+            {
+              "key": "module",
+              "url": format!("{}modules.json", base)
+            },
+            {
+              "key": "path",
+              "url": format!("{}${{module}}.json", base)
+            }
+          ]
+        }
+      ]
+    });
+    std::fs::write(
+      workspace.join(".well-known/deno-import-intellisense.json"),
+      config.to_string(),
+    )
+    .unwrap();
+    module_registry
+      .enable(base.as_str())
+      .await
+      .expect("could not enable");
+    assert!(module_registry
+      .origins
+      .contains_key(base.as_str().trim_end_matches('/')));
+    let specifier = format!("{}x/", base);
+    let offset = specifier.encode_utf16().count();
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 20 + offset as u32,
+      },
+    };
+    let completions = module_registry
+      .get_completions(&specifier, offset, &range, |_| false)
+      .await
+      .expect("no completions");
+    let mut completions = completions.items;
+    assert_eq!(completions.len(), 2);
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(completions[0].label, "a");
+    assert_eq!(
+      completions[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: format!("{}x/a", base),
+      }))
+    );
+    assert_eq!(completions[1].label, "b");
+    // specifiers outside of the directory of the registry aren't completed
+    let specifier = Url::from_directory_path(temp_dir.path())
+      .unwrap()
+      .join("x/")
+      .unwrap();
+    assert!(module_registry
+      .get_completions_for(&specifier, 0, &range, |_| false)
+      .await
+      .is_none());
+  }
+
+  #[tokio::test]
+  async fn test_registry_reload_on_config_change() {
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    let workspace = temp_dir.path().join("workspace");
+    std::fs::create_dir_all(workspace.join(".well-known")).unwrap();
+    std::fs::write(workspace.join("modules.json"), r#"["a", "b"]"#).unwrap();
+    let base = Url::from_directory_path(&workspace).unwrap();
+    let config_path =
+      workspace.join(".well-known/deno-import-intellisense.json");
+    let write_config = |schema: &str| {
+      let config = json!({
+        "version": 2,
+        "registries": [
+          {
+            "schema": schema,
+            "variables": [
+              {
+                "key": "module",
+                "url": format!("{}modules.json", base)
+              }
+            ]
+          }
+        ]
+      });
+      std::fs::write(&config_path, config.to_string()).unwrap();
+    };
+    write_config("/x/:module([a-z0-9_]*)");
+    module_registry
+      .enable(base.as_str())
+      .await
+      .expect("could not enable");
+    module_registry
+      .set_reload_on_config_change(true)
+      .expect("could not watch");
+    assert!(!module_registry.reload_changed_configs().await);
+
+    write_config("/y/:module([a-z0-9_]*)");
+    let mut reloaded = false;
+    for _ in 0..100 {
+      if module_registry.reload_changed_configs().await {
+        reloaded = true;
+        break;
+      }
+      tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(reloaded);
+    let specifier = format!("{}y/", base);
+    let offset = specifier.encode_utf16().count();
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 20 + offset as u32,
+      },
+    };
+    let completions = module_registry
+      .get_completions(&specifier, offset, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert_eq!(completions.items.len(), 2);
+
+    // an invalid edit keeps the last valid configuration
+    std::fs::write(&config_path, "{").unwrap();
+    for _ in 0..100 {
+      module_registry.reload_changed_configs().await;
+      if module_registry.last_error(base.as_str()).is_some() {
+        break;
+      }
+      tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(module_registry.last_error(base.as_str()).is_some());
+    let completions = module_registry
+      .get_completions(&specifier, offset, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert_eq!(completions.items.len(), 2);
+
+    // once the configurations are no longer watched, edits aren't picked up
+    module_registry
+      .set_reload_on_config_change(false)
+      .expect("could not stop watching");
+    assert!(module_registry.config_watcher.is_none());
+    write_config("/z/:module([a-z0-9_]*)");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!module_registry.reload_changed_configs().await);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_unicode_host() {
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    std::fs::write(temp_dir.path().join("modules.json"), r#"["a", "b"]"#)
+      .unwrap();
+    let modules_url =
+      Url::from_file_path(temp_dir.path().join("modules.json")).unwrap();
+    let config = json!({
+      "version": 2,
+      "registries": [
+        {
+          "schema": "/x/:module([a-z0-9_]*)",
+          "variables": [
+            {
+              "key": "module",
+              "url": modules_url.as_str()
+            }
+          ]
+        }
+      ]
+    });
+    let config_path = temp_dir.path().join("deno-import-intellisense.json");
+    std::fs::write(&config_path, config.to_string()).unwrap();
+    module_registry
+      .enable_from_file("http://xn--mnchen-3ya.example", &config_path)
+      .expect("could not enable");
+    assert_eq!(
+      module_registry.origins(),
+      vec!["http://xn--mnchen-3ya.example"]
+    );
+
+    let specifier = "http://m\u{fc}nchen.example/x/";
+    let offset = specifier.encode_utf16().count();
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 20 + offset as u32,
+      },
+    };
+    let completions = module_registry
+      .get_completions(specifier, offset, &range, |_| false)
+      .await
+      .expect("no completions");
+    let mut items = completions.items;
+    items.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].label, "a");
+    // the specifier is completed in the form it was typed
+    assert_eq!(
+      items[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://m\u{fc}nchen.example/x/a".to_string(),
+      }))
+    );
+    assert_eq!(items[1].label, "b");
+    assert_eq!(
+      module_registry.complete_specifiers(specifier, offset).await,
+      vec![
+        Url::parse("http://xn--mnchen-3ya.example/x/a").unwrap(),
+        Url::parse("http://xn--mnchen-3ya.example/x/b").unwrap(),
+      ]
+    );
+
+    let specifier = "http://m\u{fc}nchen.example";
+    let offset = specifier.encode_utf16().count();
+    let completions = module_registry
+      .get_completions(specifier, offset, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert_eq!(completions.items.len(), 1);
+    assert_eq!(completions.items[0].label, "/x");
+    assert_eq!(
+      completions.items[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://m\u{fc}nchen.example/x".to_string(),
+      }))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_trailing_slash() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut with_slash = ModuleRegistry::new(&location);
+    with_slash
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let mut without_slash = ModuleRegistry::new(&location);
+    without_slash
+      .enable("http://localhost:4545")
+      .await
+      .expect("could not enable");
+    assert_eq!(with_slash.origins(), vec!["http://localhost:4545"]);
+    assert_eq!(with_slash.origins(), without_slash.origins());
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    for (specifier, offset) in [
+      ("http://localhost:4545", 21),
+      ("http://localhost:4545/", 22),
+      ("http://localhost:4545/x/", 24),
+      ("http://localhost:4545/x/a@", 26),
+    ] {
+      let completions = with_slash
+        .get_completions(specifier, offset, &range, |_| false)
+        .await
+        .expect("no completions");
+      assert!(!completions.items.is_empty());
+      for item in completions.items {
+        let new_text = match item.text_edit {
+          Some(lsp::CompletionTextEdit::Edit(edit)) => edit.new_text,
+          _ => panic!("unexpected text edit"),
+        };
+        let (_, rest) = new_text.split_once("://").unwrap();
+        assert!(!rest.contains("//"), "doubled slash in \"{}\"", new_text);
+      }
+    }
+    assert_eq!(
+      join_base_str("http://localhost:4545/", "/x/a"),
+      "http://localhost:4545/x/a"
+    );
+    assert_eq!(
+      join_base_str("http://localhost:4545", "x/a"),
+      "http://localhost:4545/x/a"
+    );
+    assert_eq!(
+      join_base_str("http://localhost:4545/registry", ""),
+      "http://localhost:4545/registry"
+    );
+    assert_eq!(
+      join_base_str("http://localhost:4545/registry/", "?q=a"),
+      "http://localhost:4545/registry?q=a"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_disable_all() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    module_registry
+      .enable_with_config_url(
+        "http://127.0.0.1:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-key-first.json",
+      )
+      .await
+      .expect("could not enable");
+    module_registry
+      .enable_pattern("http://*.localhost:4545")
+      .expect("could not enable pattern");
+    assert_eq!(
+      module_registry.origins(),
+      vec!["http://127.0.0.1:4545", "http://localhost:4545"]
+    );
+    module_registry.disable_all().await;
+    assert!(module_registry.origins().is_empty());
+    assert!(!module_registry.matches_pattern("http://a.localhost:4545"));
+    // the configurations are still cached, so they are enabled without
+    // accessing the network
+    module_registry.set_offline(true);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    assert_eq!(module_registry.origins(), vec!["http://localhost:4545"]);
+  }
+
+  #[tokio::test]
+  async fn test_registry_opaque_origin() {
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    let err = module_registry
+      .enable("data:application/json,{}")
+      .await
+      .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "The URL \"data:application/json,{}\" does not have an origin which can be used as a registry."
+    );
+    assert!(module_registry.enable("file:///registry/").await.is_err());
+    assert!(module_registry
+      .check_origin("data:application/json,{}")
+      .await
+      .is_err());
+    assert!(module_registry
+      .enable_with_config_url(
+        "file:///registry/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense.json"
+      )
+      .await
+      .is_err());
+    assert!(module_registry.origins.is_empty());
+    assert!(module_registry.headers.is_empty());
+  }
+
+  #[test]
+  fn test_origin_pattern() {
+    let pattern = OriginPattern::parse("https://*.corp.example").unwrap();
+    let matches = |url: &str| pattern.matches(&Url::parse(url).unwrap());
+    assert!(matches("https://a.corp.example/"));
+    assert!(matches("https://a.b.CORP.example/"));
+    assert!(!matches("https://corp.example/"));
+    assert!(!matches("https://a.corp.example.com/"));
+    assert!(!matches("http://a.corp.example/"));
+    assert!(!matches("https://a.corp.example:8443/"));
+    let pattern = OriginPattern::parse("*.corp.example:8443").unwrap();
+    let matches = |url: &str| pattern.matches(&Url::parse(url).unwrap());
+    assert!(matches("https://a.corp.example:8443/"));
+    assert!(matches("http://a.corp.example:8443/"));
+    assert!(!matches("https://a.corp.example/"));
+    assert!(OriginPattern::parse("ftp://*.corp.example").is_err());
+    assert!(OriginPattern::parse("https://*.corp.example:port").is_err());
+    assert!(OriginPattern::parse("https://*..example").is_err());
+    assert!(OriginPattern::parse("https://*.corp.example/path").is_err());
+  }
+
+  #[tokio::test]
+  async fn test_registry_enable_pattern() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_pattern("http://127.0.0.*:4545")
+      .expect("could not enable pattern");
+    assert!(module_registry.matches_pattern("http://127.0.0.1:4545"));
+    assert!(!module_registry.matches_pattern("http://localhost:4545"));
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 41,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://127.0.0.1:4545", 21, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].label, "/x");
+    let completions = module_registry
+      .get_completions("http://localhost:4545", 21, &range, |_| false)
+      .await;
+    assert!(completions.is_none());
+    module_registry.disable_pattern("http://127.0.0.*:4545");
+    assert!(!module_registry.matches_pattern("http://127.0.0.1:4545"));
+    let completions = module_registry
+      .get_completions("http://127.0.0.1:4545", 21, &range, |_| false)
+      .await;
+    assert!(completions.is_none());
+  }
+
+  #[tokio::test]
+  async fn test_registry_builder() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistryBuilder::new()
+      .location(&location)
+      .fetch_timeout(Duration::from_secs(5))
+      .max_completion_items(1)
+      .cache_capacity(2)
+      .build();
+    assert_eq!(module_registry.fetch_timeout, Duration::from_secs(5));
+    assert_eq!(module_registry.items_cache.capacity, 2);
+    assert_eq!(module_registry.documentation_cache.capacity, 2);
+    assert!(!module_registry.offline);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert_eq!(completions.items.len(), 1);
+    assert!(completions.is_incomplete);
+    // an offline registry can only use what has already been cached
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let mut module_registry = ModuleRegistryBuilder::new()
+      .location(&temp_dir.path().join("registries"))
+      .offline(true)
+      .build();
+    assert!(module_registry.offline);
+    assert!(module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .is_err());
+    let module_registry = ModuleRegistryBuilder::new()
+      .location(&location)
+      .cache_setting(CacheSetting::Only)
+      .build();
+    assert!(!module_registry.offline);
+    assert_eq!(
+      module_registry.max_completion_items,
+      DEFAULT_MAX_COMPLETION_ITEMS
+    );
+  }
+
+  #[test]
+  fn test_registry_builder_options() {
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let origin = Url::parse("https://example.com/").unwrap();
+    let mut headers = HeadersMap::new();
+    headers.insert("x-test".to_string(), "a".to_string());
+    let module_registry = ModuleRegistryBuilder::new()
+      .location(&temp_dir.path().join("registries"))
+      .fuzzy(true)
+      .proxy(ProxyConfig {
+        url: "http://localhost:8080".to_string(),
+        no_proxy: Vec::new(),
+      })
+      .expect("could not create client")
+      .headers(&origin, headers.clone())
+      .retries(5, Duration::from_millis(1))
+      .prefetch_policy(PrefetchPolicy::AnyCompleteSpecifier)
+      .items_cache_ttl(Duration::from_secs(10))
+      .failed_items_ttl(Duration::from_secs(20))
+      .min_fetch_interval(Duration::ZERO)
+      .config_paths(vec!["/registry.json".to_string()])
+      .config_max_age(Duration::from_secs(30))
+      .snippet_support(true)
+      .commit_characters(true)
+      .label_details_support(true)
+      .segment_edits(true)
+      .build();
+    assert!(module_registry.fuzzy);
+    assert_eq!(
+      module_registry.headers.get("https://example.com"),
+      Some(&headers)
+    );
+    assert_eq!(module_registry.max_retries, 5);
+    assert_eq!(module_registry.retry_delay, Duration::from_millis(1));
+    assert_eq!(
+      module_registry.prefetch_policy,
+      PrefetchPolicy::AnyCompleteSpecifier
+    );
+    assert_eq!(module_registry.items_cache.ttl, Duration::from_secs(10));
+    assert_eq!(
+      module_registry.failed_items_cache.ttl,
+      Duration::from_secs(20)
+    );
+    assert_eq!(module_registry.min_fetch_interval, Duration::ZERO);
+    assert_eq!(module_registry.config_paths, vec!["/registry.json"]);
+    assert_eq!(module_registry.config_max_age, Duration::from_secs(30));
+    assert!(module_registry.snippet_support);
+    assert!(module_registry.commit_characters);
+    assert!(module_registry.label_details_support);
+    assert!(module_registry.segment_edits);
+    assert!(ModuleRegistryBuilder::new()
+      .proxy(ProxyConfig {
+        url: "not a url".to_string(),
+        no_proxy: Vec::new(),
+      })
+      .is_err());
+  }
+
+  #[tokio::test]
+  async fn test_registry_with_file_fetcher() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let file_fetcher = FileFetcher::new(
+      HttpCache::new(&temp_dir.path().join("deps")),
+      CacheSetting::Only,
+      true,
+      None,
+      BlobStore::default(),
+      None,
+    )
+    .expect("could not create file fetcher");
+    // the cache setting of the file fetcher is used as it is
+    let mut module_registry =
+      ModuleRegistry::with_file_fetcher(&location, file_fetcher.clone());
+    assert_eq!(module_registry.file_fetcher().http_cache.location, location);
+    assert_eq!(
+      *module_registry.file_fetcher().cache_setting(),
+      CacheSetting::Only
+    );
+    assert!(!module_registry.offline);
+    assert!(module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .is_err());
+
+    let mut file_fetcher = file_fetcher;
+    file_fetcher.set_cache_setting(CacheSetting::RespectHeaders);
+    let mut module_registry =
+      ModuleRegistry::with_file_fetcher(&location, file_fetcher);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    assert_eq!(module_registry.origins(), vec!["http://localhost:4545"]);
+    // the responses are cached in the location of the registries
+    assert!(location.exists());
+    assert!(!temp_dir.path().join("deps").exists());
+  }
+
+  #[tokio::test]
+  async fn test_registry_with_file_fetcher_cache() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let file_fetcher = FileFetcher::new(
+      HttpCache::new(&temp_dir.path().join("deps")),
+      CacheSetting::ReloadAll,
+      true,
+      None,
+      BlobStore::default(),
+      None,
+    )
+    .expect("could not create file fetcher");
+    let specifier =
+      resolve_url("http://localhost:4545/subdir/mod1.ts").unwrap();
+    file_fetcher
+      .fetch(&specifier, &mut Permissions::allow_all())
+      .await
+      .expect("could not fetch");
+    let mut module_registry =
+      ModuleRegistry::with_file_fetcher(&location, file_fetcher.clone());
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    // the in memory cache of the fetcher isn't shared with the registries
+    let config_specifier = resolve_url(
+      "http://localhost:4545/.well-known/deno-import-intellisense.json",
+    )
+    .unwrap();
+    assert!(file_fetcher.remove_cached(&config_specifier).is_none());
+    module_registry
+      .clear_cache()
+      .expect("could not clear cache");
+    assert!(file_fetcher.remove_cached(&specifier).is_some());
+    // going back online restores the cache setting of the fetcher
+    module_registry.set_offline(true);
+    assert_eq!(
+      *module_registry.file_fetcher.cache_setting(),
+      CacheSetting::Only
+    );
+    module_registry.set_offline(false);
+    assert_eq!(
+      *module_registry.file_fetcher.cache_setting(),
+      CacheSetting::ReloadAll
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_offline() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry.set_items_cache_ttl(Duration::ZERO);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a", 25, &range, |_| false)
+      .await
+      .unwrap();
+    assert_eq!(completions.items.len(), 4);
+
+    module_registry.set_offline(true);
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a", 25, &range, |_| false)
+      .await
+      .unwrap();
+    assert_eq!(completions.items.len(), 4);
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/ab", 26, &range, |_| false)
+      .await
+      .unwrap();
+    assert!(completions.items.is_empty());
+    assert!(module_registry
+      .enable("http://localhost:4546/")
+      .await
+      .is_err());
+
+    module_registry.set_offline(false);
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/ab", 26, &range, |_| false)
+      .await
+      .unwrap();
+    assert_eq!(completions.items.len(), 5);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_path_prefix() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/registry/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-v3.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 54,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/registry/x/a", 34, &range, |_| {
+        false
+      })
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 4);
+    assert!(completions.iter().any(|c| c.text_edit
+      == Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/registry/x/aa".to_string(),
+      }))));
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a", 25, &range, |_| false)
+      .await;
+    assert!(completions.is_none());
+    let completions = module_registry
+      .get_completions("http://localhost:4", 18, &range, |_| false)
+      .await;
+    assert_eq!(
+      completions.unwrap().items[0].label,
+      "http://localhost:4545/registry"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_suggest_latest() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-latest.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a@", 26, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let mut completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 4);
+    completions.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+    assert_eq!(completions[0].label, "latest");
+    assert_eq!(
+      completions[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/x/a".to_string(),
+      }))
+    );
+    assert_eq!(completions[1].label, "v1.0.0");
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_suggest_latest_separators() {
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let workspace = temp_dir.path().join("workspace");
+    std::fs::create_dir_all(workspace.join(".well-known")).unwrap();
+    std::fs::write(workspace.join("modules.json"), r#"["a"]"#).unwrap();
+    std::fs::write(workspace.join("versions.json"), r#"["1.0.0"]"#).unwrap();
+    let base = Url::from_directory_path(&workspace).unwrap();
+    let cases = [
+      ("/x/:module([a-z0-9_]*)-:version?", "x/a-"),
+      ("/x/:module([a-z0-9_]*)/:version?", "x/a/"),
+      ("/x/:module([a-z0-9_]*){~:version}?", "x/a~1"),
+    ];
+    for (schema, path) in cases {
+      let config = json!({
+        "version": 2,
+        "registries": [
+          {
+            "schema": schema,
+            "suggestLatest": true,
+            "variables": [
+              {
+                "key": "module",
+                "url": format!("{}modules.json", base)
+              },
+              {
+                "key": "version",
+                "url": format!("{}versions.json", base)
+              }
+            ]
+          }
+        ]
+      });
+      std::fs::write(
+        workspace.join(".well-known/deno-import-intellisense.json"),
+        config.to_string(),
+      )
+      .unwrap();
+      let location = temp_dir.path().join("registries");
+      let mut module_registry = ModuleRegistry::new(&location);
+      module_registry
+        .enable(base.as_str())
+        .await
+        .expect("could not enable");
+      let specifier = format!("{}{}", base, path);
+      let offset = specifier.encode_utf16().count();
+      let range = lsp::Range {
+        start: lsp::Position {
+          line: 0,
+          character: 20,
+        },
+        end: lsp::Position {
+          line: 0,
+          character: 20 + offset as u32,
+        },
+      };
+      let completions = module_registry
+        .get_completions(&specifier, offset, &range, |_| false)
+        .await
+        .unwrap_or_else(|| panic!("no completions for {}", schema));
+      let latest = completions
+        .items
+        .iter()
+        .find(|c| c.label == "latest")
+        .expect("no latest item");
+      assert_eq!(
+        latest.text_edit,
+        Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+          range,
+          new_text: format!("{}x/a", base),
+        })),
+        "{}",
+        schema
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_import_assertions() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-assertions.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 48,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/data/", 27, &range, |_| false)
+      .await
+      .unwrap();
+    let mut items = completions.items;
+    items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].label, "mod.ts");
+    assert_eq!(items[0].additional_text_edits, None);
+    assert_eq!(items[1].label, "config.json");
+    assert_eq!(
+      items[1].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/data/config.json".to_string(),
+      }))
+    );
+    assert_eq!(
+      items[1].additional_text_edits,
+      Some(vec![lsp::TextEdit {
+        range: lsp::Range {
+          start: lsp::Position {
+            line: 0,
+            character: 49,
           },
-          RegistryConfigurationVariable {
-            key: "version".to_string(),
-            documentation: None,
-            url: "https://deno.land/_vsc1/module/${module}".to_string(),
+          end: lsp::Position {
+            line: 0,
+            character: 49,
           },
-        ],
-      }],
+        },
+        new_text: " with { type: \"json\" }".to_string(),
+      }])
+    );
+    assert_eq!(
+      items[0].command.as_ref().unwrap().arguments,
+      Some(vec![json!(["http://localhost:4545/data/mod.ts"])])
+    );
+    let command = items[1].command.as_ref().unwrap();
+    assert_eq!(command.command, "deno.cache");
+    assert_eq!(
+      command.arguments,
+      Some(vec![
+        json!(["http://localhost:4545/data/config.json"]),
+        json!({ "type": "json" }),
+      ])
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_complex() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url("http://localhost:4545/", "http://localhost:4545/lsp/registries/deno-import-intellisense-complex.json")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 42,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/", 22, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 3);
+    for completion in completions {
+      assert!(completion.text_edit.is_some());
+      if let lsp::CompletionTextEdit::Edit(edit) = completion.text_edit.unwrap()
+      {
+        assert_eq!(
+          edit.new_text,
+          format!("http://localhost:4545/{}", completion.label)
+        );
+      } else {
+        unreachable!("unexpected text edit");
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_skip_broken_registry() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    // a registry which failed to compile comes before the good ones
+    module_registry
+      .origins
+      .get_mut("http://localhost:4545")
+      .expect("missing origin")
+      .insert(
+        0,
+        RegistryConfiguration {
+          schema: "/x/:module(".to_string(),
+          ..Default::default()
+        },
+      );
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    let labels: Vec<&str> =
+      completions.items.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(labels, vec!["a", "b"]);
+  }
+
+  #[tokio::test]
+  async fn test_registry_config_paths() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry.set_config_paths(vec![
+      "/.well-known/missing-intellisense.json".to_string(),
+      "/lsp/registries/deno-import-intellisense-described.json".to_string(),
+    ]);
+    module_registry
+      .check_origin("http://localhost:4545/")
+      .await
+      .expect("could not check origin");
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    assert_eq!(
+      module_registry.config_specifiers["http://localhost:4545"].as_str(),
+      "http://localhost:4545/lsp/registries/deno-import-intellisense-described.json"
+    );
+    assert_eq!(
+      module_registry.schemas("http://localhost:4545/"),
+      vec!["/x/:module([a-z0-9_]*)/:path*".to_string()]
+    );
+    assert_eq!(module_registry.last_error("http://localhost:4545/"), None);
+
+    // the registry isn't enabled when none of the paths work
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry.set_config_paths(vec![
+      "/.well-known/missing-intellisense.json".to_string(),
+      "/lsp/registries/missing-intellisense.json".to_string(),
+    ]);
+    assert!(module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .is_err());
+    assert!(module_registry.origins.is_empty());
+    assert!(module_registry
+      .last_error("http://localhost:4545/")
+      .is_some());
+
+    // an empty list restores the default path
+    module_registry.set_config_paths(Vec::new());
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    assert_eq!(module_registry.schemas("http://localhost:4545/").len(), 2);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_empty_repeated_key() {
+    let registry = RegistryConfiguration {
+      schema: "/x/:module@:version/:path*".to_string(),
+      ..Default::default()
+    };
+    let schema = CompiledSchema::new(&registry).expect("could not compile");
+    let path = "/x/a@v1.0.0/";
+    let match_result = schema.matchers[schema.tokens.len()]
+      .matches(path)
+      .expect("no match");
+    assert!(matches!(
+      match_result.get("path"),
+      Some(StringOrVec::Vec(v)) if v.is_empty()
+    ));
+    match get_completor_type(path.len(), &schema.tokens, &match_result) {
+      Some(CompletorType::Key { key, prefix, index }) => {
+        assert_eq!(key.name, StringOrNumber::String("path".to_string()));
+        assert_eq!(prefix, None);
+        assert!(
+          matches!(&schema.tokens[index], Token::Key(k) if k.name == key.name)
+        );
+      }
+      completor_type => panic!("unexpected completor {:?}", completor_type),
+    }
+
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 53,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a@v1.0.0/", 33, &range, |_| {
+        false
+      })
+      .await
+      .expect("no completions");
+    let mut labels: Vec<&str> =
+      completions.items.iter().map(|i| i.label.as_str()).collect();
+    labels.sort_unstable();
+    assert_eq!(labels, vec!["b/c.ts", "d/e.js"]);
+  }
+
+  #[test]
+  fn test_match_schema() {
+    let schema = "/x/:module([a-z0-9_]*)@:version?/:path*";
+    let params =
+      match_schema(schema, "http://localhost:4545/x/std@0.1.0/fs/mod.ts")
+        .expect("could not match")
+        .expect("no match");
+    assert_eq!(params.len(), 3);
+    assert_eq!(params.get("module").map(String::as_str), Some("std"));
+    assert_eq!(params.get("version").map(String::as_str), Some("0.1.0"));
+    assert_eq!(params.get("path").map(String::as_str), Some("fs/mod.ts"));
+    // an optional key which isn't matched is omitted, and an optional repeated
+    // key which isn't matched is empty
+    let params = match_schema("/x/:module/:version?", "/x/std")
+      .expect("could not match")
+      .expect("no match");
+    assert_eq!(params.get("module").map(String::as_str), Some("std"));
+    assert_eq!(params.get("version"), None);
+    let params = match_schema(schema, "/x/std@0.1.0/")
+      .expect("could not match")
+      .expect("no match");
+    assert_eq!(params.get("path").map(String::as_str), Some(""));
+    assert_eq!(
+      match_schema(schema, "http://localhost:4545/y/std")
+        .expect("could not match"),
+      None
+    );
+    assert_eq!(
+      match_schema("/x/:module+", "/x").expect("could not match"),
+      None
+    );
+    let params = match_schema("/x/:module+", "/x/a/b")
+      .expect("could not match")
+      .expect("no match");
+    assert_eq!(params.get("module").map(String::as_str), Some("a/b"));
+    assert!(match_schema("/x/:module(", "/x/a").is_err());
+  }
+
+  #[test]
+  fn test_get_first_key_path() {
+    let tokens = parse("/x/:module([a-z0-9_]*)@:version?/:path*", None)
+      .expect("could not parse");
+    assert_eq!(get_first_key_path(&tokens), Some("/x/".to_string()));
+    let tokens = parse("/:module", None).expect("could not parse");
+    assert_eq!(get_first_key_path(&tokens), Some("/".to_string()));
+    let tokens = parse("/std", None).expect("could not parse");
+    assert_eq!(get_first_key_path(&tokens), None);
+  }
+
+  #[tokio::test]
+  async fn test_registry_warm() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .warm("http://localhost:4545/")
+      .await
+      .expect("could not warm");
+    assert_eq!(module_registry.origins().len(), 1);
+    let fetches = module_registry.stats().fetches;
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    let labels: Vec<&str> =
+      completions.items.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(labels, vec!["a", "b"]);
+    // the items were already in memory
+    assert_eq!(module_registry.stats().fetches, fetches);
+    assert!(module_registry.warm("http://localhost:4545/").await.is_ok());
+  }
+
+  #[tokio::test]
+  async fn test_registry_schemas_compiled_on_enable() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let registries = module_registry
+      .origins
+      .get("http://localhost:4545")
+      .expect("missing origin");
+    assert_eq!(registries.len(), 2);
+    for registry in registries {
+      let schema = registry.compiled.as_ref().expect("schema not compiled");
+      assert_eq!(schema.matchers.len(), schema.tokens.len() + 1);
+    }
+    module_registry
+      .disable("http://localhost:4545/")
+      .await
+      .expect("could not disable");
+    assert!(module_registry.origins.is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_registry_refresh() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    module_registry
+      .refresh("http://localhost:4545/")
+      .await
+      .expect("could not refresh");
+    let configs = module_registry.origins["http://localhost:4545"].clone();
+    assert_eq!(configs.len(), 2);
+
+    // a failed re-fetch should leave the existing configuration in place
+    module_registry
+      .origins
+      .insert("https://deno.com".to_string(), configs);
+    let result = module_registry.refresh("https://deno.com/").await;
+    assert!(result.is_err());
+    assert_eq!(module_registry.origins["https://deno.com"].len(), 2);
+  }
+
+  fn bundled_config(schema: &str) -> RegistryConfigurationJson {
+    serde_json::from_value(json!({
+      "version": 1,
+      "registries": [
+        {
+          "schema": schema,
+          "variables": [
+            {
+              "key": "module",
+              "url": "http://localhost:4545/lsp/registries/modules.json"
+            }
+          ]
+        }
+      ]
+    }))
+    .unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_registry_bundled_configs() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    let mut configs = HashMap::new();
+    configs.insert(
+      "http://localhost:4545/".to_string(),
+      bundled_config("/bundled/:module"),
+    );
+    module_registry
+      .set_bundled_configs(configs)
+      .expect("could not set bundled configs");
+
+    // the bundled configuration is used when the configuration can't be
+    // fetched
+    module_registry.set_offline(true);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let registries = &module_registry.origins["http://localhost:4545"];
+    assert_eq!(registries.len(), 1);
+    assert_eq!(registries[0].schema, "/bundled/:module");
+    assert!(registries[0].compiled.is_some());
+
+    // and is replaced by the configuration once it is fetched
+    module_registry.set_offline(false);
+    module_registry
+      .disable("http://localhost:4545/")
+      .await
+      .expect("could not disable");
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let registries = &module_registry.origins["http://localhost:4545"];
+    assert_eq!(registries.len(), 2);
+    assert!(registries.iter().all(|r| r.schema != "/bundled/:module"));
+  }
+
+  #[test]
+  fn test_registry_bundled_configs_invalid() {
+    let mut module_registry = ModuleRegistry::default();
+    let mut configs = HashMap::new();
+    configs.insert(
+      "https://deno.land/".to_string(),
+      bundled_config("/x/:module@:version"),
+    );
+    let err = module_registry
+      .set_bundled_configs(configs)
+      .expect_err("should have failed");
+    assert!(err.to_string().starts_with(
+      "Invalid bundled registry configuration for \"https://deno.land\"."
+    ));
+    assert!(module_registry.bundled_configs.is_empty());
+  }
+
+  #[test]
+  fn test_items_cache() {
+    let specifier =
+      ModuleSpecifier::parse("http://localhost:4545/lsp/registries/a.json")
+        .unwrap();
+    let key = (specifier.clone(), None);
+    let items = VariableItems::Simple(vec!["a".to_string()]);
+    let cache = ItemsCache::new(Duration::from_secs(60));
+    assert!(cache.get(&key).is_none());
+    cache.insert(key.clone(), items.clone());
+    assert!(cache.get(&key).is_some());
+    // a request which posts a body is cached separately
+    assert!(cache.get(&(specifier, Some("a".to_string()))).is_none());
+    cache.clear();
+    assert!(cache.get(&key).is_none());
+
+    let cache = ItemsCache::new(Duration::ZERO);
+    cache.insert(key.clone(), items);
+    assert!(cache.get(&key).is_none());
+  }
+
+  #[test]
+  fn test_failed_items_cache() {
+    let key = (
+      ModuleSpecifier::parse("http://localhost:4545/lsp/registries/a.json")
+        .unwrap(),
+      None,
+    );
+    let cache = FailedItemsCache::new(Duration::from_secs(60));
+    assert!(!cache.contains(&key));
+    cache.insert(key.clone());
+    assert!(cache.contains(&key));
+    cache.clear();
+    assert!(!cache.contains(&key));
+
+    let cache = FailedItemsCache::new(Duration::ZERO);
+    cache.insert(key.clone());
+    assert!(!cache.contains(&key));
+  }
+
+  #[tokio::test]
+  async fn test_registry_failed_items() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let missing =
+      resolve_url("http://localhost:4545/lsp/registries/missing_modules.json")
+        .unwrap();
+    assert!(module_registry.fetch_items(&missing, None).await.is_none());
+    assert!(module_registry
+      .failed_items_cache
+      .contains(&(missing.clone(), None)));
+    // an endpoint which recently failed is not requested again, even if it
+    // would now succeed
+    let modules =
+      resolve_url("http://localhost:4545/lsp/registries/modules_a.json")
+        .unwrap();
+    module_registry
+      .failed_items_cache
+      .insert((modules.clone(), None));
+    assert!(module_registry.fetch_items(&modules, None).await.is_none());
+    module_registry.set_failed_items_ttl(Duration::ZERO);
+    assert!(module_registry.fetch_items(&modules, None).await.is_some());
+    module_registry.set_failed_items_ttl(DEFAULT_FAILED_ITEMS_TTL);
+    module_registry.fetch_items(&missing, None).await;
+    module_registry
+      .clear_cache()
+      .expect("could not clear cache");
+    assert!(!module_registry
+      .failed_items_cache
+      .contains(&(missing, None)));
+  }
+
+  #[tokio::test]
+  async fn test_registry_min_fetch_interval() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry.set_min_fetch_interval(Duration::from_millis(500));
+    let specifiers: Vec<ModuleSpecifier> =
+      ["modules_a.json", "modules_ab.json"]
+        .iter()
+        .map(|name| {
+          resolve_url(&format!("http://localhost:4545/lsp/registries/{}", name))
+            .unwrap()
+        })
+        .collect();
+    let start = Instant::now();
+    assert!(module_registry
+      .fetch_items(&specifiers[0], None)
+      .await
+      .is_some());
+    assert!(start.elapsed() < Duration::from_millis(500));
+    // the second request to the origin waits for the interval to pass
+    assert!(module_registry
+      .fetch_items(&specifiers[1], None)
+      .await
+      .is_some());
+    assert!(start.elapsed() >= Duration::from_millis(500));
+    // cached items are returned straight away
+    let cached = Instant::now();
+    assert!(module_registry
+      .fetch_items(&specifiers[0], None)
+      .await
+      .is_some());
+    assert!(cached.elapsed() < Duration::from_millis(500));
+    // requests to other origins are not affected
+    let other = Instant::now();
+    let specifier =
+      resolve_url("http://127.0.0.1:4545/lsp/registries/modules_a.json")
+        .unwrap();
+    assert!(module_registry
+      .fetch_items(&specifier, None)
+      .await
+      .is_some());
+    assert!(other.elapsed() < Duration::from_millis(500));
+  }
+
+  #[tokio::test]
+  async fn test_registry_event_listener() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let listener_events = events.clone();
+    module_registry.set_event_listener(move |event| {
+      listener_events.lock().push(event);
+    });
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let missing =
+      resolve_url("http://localhost:4545/lsp/registries/missing_modules.json")
+        .unwrap();
+    assert!(module_registry.fetch_items(&missing, None).await.is_none());
+    // the listener is called on another thread
+    for _ in 0..100 {
+      if events.lock().len() >= 4 {
+        break;
+      }
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let events = events.lock();
+    assert_eq!(events.len(), 4);
+    let config = resolve_url(
+      "http://localhost:4545/.well-known/deno-import-intellisense.json",
+    )
+    .unwrap();
+    assert_eq!(events[0].kind, FetchKind::Config);
+    assert_eq!(events[0].specifier, config);
+    assert_eq!(events[0].outcome, FetchOutcome::Started);
+    assert_eq!(events[1].kind, FetchKind::Config);
+    assert!(matches!(events[1].outcome, FetchOutcome::Succeeded { .. }));
+    assert_eq!(events[2].kind, FetchKind::Endpoint);
+    assert_eq!(events[2].specifier, missing);
+    assert_eq!(events[2].outcome, FetchOutcome::Started);
+    assert_eq!(events[3].kind, FetchKind::Endpoint);
+    assert!(matches!(events[3].outcome, FetchOutcome::Failed { .. }));
+  }
+
+  #[test]
+  fn test_proxy_config_bypasses() {
+    let proxy = ProxyConfig {
+      url: "http://proxy.example.com:8080".to_string(),
+      no_proxy: vec![
+        "deno.land".to_string(),
+        " .example.org".to_string(),
+        "localhost:4545".to_string(),
+        "[::1]".to_string(),
+      ],
+    };
+    let bypasses = |url: &str| proxy.bypasses(&Url::parse(url).unwrap());
+    assert!(bypasses("https://deno.land/x/"));
+    assert!(bypasses("https://cdn.deno.land/x/"));
+    assert!(!bypasses("https://notdeno.land/x/"));
+    assert!(bypasses("http://example.org/"));
+    assert!(bypasses("http://www.EXAMPLE.org/"));
+    assert!(bypasses("http://localhost:4545/"));
+    assert!(!bypasses("http://localhost:4546/"));
+    assert!(bypasses("http://[::1]:4545/"));
+    let proxy = ProxyConfig {
+      url: "http://proxy.example.com:8080".to_string(),
+      no_proxy: vec!["*".to_string()],
+    };
+    assert!(proxy.bypasses(&Url::parse("https://deno.land/").unwrap()));
+    assert!(!ProxyConfig::default()
+      .bypasses(&Url::parse("https://deno.land/").unwrap()));
+  }
+
+  #[tokio::test]
+  async fn test_registry_no_proxy() {
+    let _g = test_util::http_server();
+    let specifier =
+      resolve_url("http://localhost:4545/lsp/registries/modules_a.json")
+        .unwrap();
+    // nothing listens on the port of the proxy, so only requests which
+    // bypass it succeed
+    let proxy_url = "http://127.0.0.1:9".to_string();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let mut module_registry =
+      ModuleRegistry::new(&temp_dir.path().join("registries"));
+    module_registry.set_retries(0, Duration::ZERO);
+    module_registry
+      .set_proxy(Some(ProxyConfig {
+        url: proxy_url.clone(),
+        no_proxy: vec!["localhost:4545".to_string()],
+      }))
+      .unwrap();
+    assert!(module_registry
+      .fetch_items(&specifier, None)
+      .await
+      .is_some());
+
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let mut module_registry =
+      ModuleRegistry::new(&temp_dir.path().join("registries"));
+    module_registry.set_retries(0, Duration::ZERO);
+    module_registry
+      .set_proxy(Some(ProxyConfig {
+        url: proxy_url,
+        no_proxy: vec!["localhost:4546".to_string()],
+      }))
+      .unwrap();
+    assert!(module_registry
+      .fetch_items(&specifier, None)
+      .await
+      .is_none());
+    module_registry.set_proxy(None).unwrap();
+    module_registry.failed_items_cache.clear();
+    assert!(module_registry
+      .fetch_items(&specifier, None)
+      .await
+      .is_some());
+  }
+
+  #[test]
+  fn test_items_cache_capacity() {
+    let specifiers: Vec<ItemsCacheKey> = ["a", "b", "c"]
+      .iter()
+      .map(|name| {
+        let specifier = ModuleSpecifier::parse(&format!(
+          "http://localhost:4545/lsp/registries/{}.json",
+          name
+        ))
+        .unwrap();
+        (specifier, None)
+      })
+      .collect();
+    let items = VariableItems::Simple(vec!["a".to_string()]);
+    let mut cache = ItemsCache::new(Duration::from_secs(60));
+    cache.capacity = 2;
+    cache.insert(specifiers[0].clone(), items.clone());
+    cache.insert(specifiers[1].clone(), items.clone());
+    // using the first entry makes the second the least recently used
+    assert!(cache.get(&specifiers[0]).is_some());
+    cache.insert(specifiers[2].clone(), items);
+    assert!(cache.get(&specifiers[0]).is_some());
+    assert!(cache.get(&specifiers[1]).is_none());
+    assert!(cache.get(&specifiers[2]).is_some());
+  }
+
+  #[test]
+  fn test_parse_replacement_variables() {
+    let actual = parse_replacement_variables(
+      "https://deno.land/_vsc1/modules/${module}/v/${{version}}",
+    );
+    assert_eq!(actual.len(), 2);
+    assert!(actual.contains(&"module".to_owned()));
+    assert!(actual.contains(&"version".to_owned()));
+  }
+
+  #[tokio::test]
+  async fn test_check_origin_supported() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let module_registry = ModuleRegistry::new(&location);
+    let result = module_registry.check_origin("http://localhost:4545").await;
+    assert!(result.is_ok());
+  }
+
+  #[tokio::test]
+  async fn test_check_origin_not_supported() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let module_registry = ModuleRegistry::new(&location);
+    let result = module_registry.check_origin("https://deno.com").await;
+    assert!(result.is_err());
+    let err = result.unwrap_err().to_string();
+    assert!(err
+      .contains("https://deno.com/.well-known/deno-import-intellisense.json"));
+
+    // because we are caching an empty file when we hit an error with import
+    // detection when fetching the config file, we should have an error now that
+    // indicates trying to parse an empty file.
+    let result = module_registry.check_origin("https://deno.com").await;
+    assert!(result.is_err());
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("EOF while parsing a value at line 1 column 0"));
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_snippet() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry.set_snippet_support(true);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 41,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545", 21, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let mut completions = completions.unwrap().items;
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(completions.len(), 3);
+    assert_eq!(completions[0].label, "/x");
+    assert_eq!(completions[0].insert_text_format, None);
+    assert_eq!(completions[1].label, "/x/:module([a-z0-9_]*)/:path*");
+    assert_eq!(completions[1].sort_text, Some("2".to_string()));
+    assert_eq!(
+      completions[1].insert_text_format,
+      Some(lsp::InsertTextFormat::SNIPPET)
+    );
+    assert_eq!(
+      completions[1].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/x/${1:module}/${2:path}".to_string()
+      }))
+    );
+    assert_eq!(
+      completions[2].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/x/${1:module}@${2:version}/${3:path}"
+          .to_string()
+      }))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_case_insensitive() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/X/", 24, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 2);
+
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-case.json",
+      )
+      .await
+      .expect("could not enable");
+    let completions = module_registry
+      .get_completions("http://localhost:4545/X/", 24, &range, |_| false)
+      .await;
+    assert!(completions.is_none());
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 2);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_delimiters() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-delimiters.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a@", 26, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let mut completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 3);
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(completions[0].label, "v1.0.0");
+    assert_eq!(
+      completions[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/x/a@v1.0.0".to_string(),
+      }))
+    );
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 53,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a@v1.0.0/", 33, &range, |_| {
+        false
+      })
+      .await;
+    assert!(completions.is_some());
+    let mut completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 2);
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(
+      completions[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/x/a@v1.0.0/b/c.ts".to_string(),
+      }))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_described_items() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-described.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let mut completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 2);
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(completions[0].label, "a");
+    assert_eq!(
+      completions[0].detail,
+      Some("A module for testing.".to_string())
+    );
+    assert_eq!(completions[1].label, "b");
+    assert_eq!(completions[1].detail, Some("(module)".to_string()));
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_inline_documentation() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-described.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions")
+      .items;
+    assert_eq!(completions.len(), 2);
+    assert_eq!(completions[0].label, "a");
+    assert_eq!(
+      completions[0].documentation,
+      Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+        kind: lsp::MarkupKind::Markdown,
+        value: "A module for testing.".to_string(),
+      }))
+    );
+    // items without a description are left to be resolved
+    assert_eq!(completions[1].label, "b");
+    assert_eq!(completions[1].documentation, None);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_min_query_length() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-min-query.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 45,
+      },
+    };
+    let fetches = module_registry.stats().fetches;
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a", 25, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert!(completions.is_incomplete);
+    assert_eq!(completions.items.len(), 1);
+    assert_eq!(completions.items[0].label, "keep typing to search\u{2026}");
+    assert_eq!(
+      completions.items[0].detail,
+      Some("(module needs at least 2 characters)".to_string())
+    );
+    assert_eq!(
+      completions.items[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/x/a".to_string(),
+      }))
+    );
+    // the items weren't requested
+    assert_eq!(module_registry.stats().fetches, fetches);
+
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/ab", 26, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert!(!completions.items.is_empty());
+    assert!(completions
+      .items
+      .iter()
+      .all(|i| i.label != "keep typing to search\u{2026}"));
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_examples() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-examples.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
     };
-    assert!(validate_config(&cfg).is_err());
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    let mut items = completions.items;
+    items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].label, "oak");
+    assert_eq!(items[1].label, "std");
+    for item in &items {
+      assert_eq!(item.kind, Some(lsp::CompletionItemKind::TEXT));
+      assert_eq!(item.detail, Some("(example module)".to_string()));
+      assert_eq!(
+        item.text_edit,
+        Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+          range,
+          new_text: "http://localhost:4545/x/".to_string(),
+        }))
+      );
+    }
+    // examples aren't specifiers
+    assert!(module_registry
+      .complete_specifiers("http://localhost:4545/x/", 24)
+      .await
+      .is_empty());
+
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a/", 26, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert!(!completions.items.is_empty());
+    assert!(completions.items.iter().all(|i| i.label != "example.ts"
+      && i.kind != Some(lsp::CompletionItemKind::TEXT)));
+  }
+
+  #[tokio::test]
+  async fn test_registry_max_concurrent_fetches() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let module_registry = ModuleRegistryBuilder::new()
+      .location(&location)
+      .max_concurrent_fetches(2)
+      .build();
+    // each response is the number of requests the server had in flight when
+    // it received the request
+    let token = uuid::Uuid::new_v4();
+    let urls: Vec<String> = (0..6)
+      .map(|i| {
+        format!(
+          "http://localhost:4545/lsp/registries/concurrent_modules.json?i={}&token={}",
+          i, token
+        )
+      })
+      .collect();
+    let results = future::join_all(
+      urls.iter().map(|url| module_registry.get_items(url, None)),
+    )
+    .await;
+    for result in results {
+      let (items, _, _) = result.expect("no items").into_parts();
+      assert_eq!(items.len(), 1);
+      let in_flight: usize = items[0].0.parse().unwrap();
+      assert!(in_flight <= 2, "{} requests in flight", in_flight);
+    }
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_anchored() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 42,
+      },
+    };
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    let completions = module_registry
+      .get_completions("http://localhost:4545/", 22, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert_eq!(completions.items.len(), 1);
+    assert_eq!(completions.items[0].label, "/x");
+
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-anchored.json",
+      )
+      .await
+      .expect("could not enable");
+    let completions = module_registry
+      .get_completions("http://localhost:4545/", 22, &range, |_| false)
+      .await;
+    assert!(completions.is_none());
+    // once the path has started to be typed, the registry completes it
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 43,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x", 23, &range, |_| false)
+      .await
+      .expect("no completions");
+    let labels: Vec<&str> =
+      completions.items.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(labels, vec!["/a", "/b"]);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_invalid_items() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-invalid.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    let labels: Vec<&str> =
+      completions.items.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(labels, vec!["a", "b"]);
+    assert_eq!(module_registry.stats().invalid_items, 1);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_revalidated() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    let specifier = enable_with_token(
+      &mut module_registry,
+      temp_dir.path(),
+      "revalidated_modules.json",
+    );
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    let get_labels = |completions: lsp::CompletionList| {
+      completions
+        .items
+        .into_iter()
+        .map(|i| i.label)
+        .collect::<Vec<_>>()
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert_eq!(get_labels(completions), vec!["a", "b"]);
+    let (_, _, cached) = module_registry
+      .file_fetcher
+      .http_cache
+      .get(&specifier)
+      .unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    // the endpoint only sends the changed items for an unconditional request,
+    // so the cached items are still used once they have been revalidated
+    module_registry.items_cache.clear();
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert_eq!(get_labels(completions), vec!["a", "b"]);
+    let (_, _, revalidated) = module_registry
+      .file_fetcher
+      .http_cache
+      .get(&specifier)
+      .unwrap();
+    assert!(revalidated > cached);
+  }
+
+  #[tokio::test]
+  async fn test_registry_complete_specifiers() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    for (current_specifier, offset) in [
+      ("http://localhost:4545/x/", 24),
+      ("http://localhost:4545/x/a@", 26),
+      ("http://localhost:4545/x/a@v1.0.0/", 33),
+    ] {
+      let range = lsp::Range {
+        start: lsp::Position {
+          line: 0,
+          character: 20,
+        },
+        end: lsp::Position {
+          line: 0,
+          character: 20 + offset as u32,
+        },
+      };
+      let completions = module_registry
+        .get_completions(current_specifier, offset, &range, |_| false)
+        .await
+        .expect("no completions");
+      let expected: Vec<String> = completions
+        .items
+        .iter()
+        .filter_map(|item| match &item.text_edit {
+          Some(lsp::CompletionTextEdit::Edit(edit)) => {
+            Some(edit.new_text.clone())
+          }
+          _ => None,
+        })
+        .collect();
+      assert!(!expected.is_empty());
+      let specifiers: Vec<String> = module_registry
+        .complete_specifiers(current_specifier, offset)
+        .await
+        .into_iter()
+        .map(String::from)
+        .collect();
+      assert_eq!(specifiers, expected);
+    }
+    // the whole specifier is resolved even when only segments are edited
+    module_registry.set_segment_edits(true);
+    let specifiers = module_registry
+      .complete_specifiers("http://localhost:4545/x/a@", 26)
+      .await;
+    assert_eq!(specifiers[0].as_str(), "http://localhost:4545/x/a@v1.0.0");
+    assert!(module_registry
+      .complete_specifiers("http://example.com/", 19)
+      .await
+      .is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_deprecated_items() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-deprecated.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let mut completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 2);
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(completions[0].label, "a");
+    assert_eq!(completions[0].tags, None);
+    assert_eq!(completions[1].label, "b");
+    assert_eq!(
+      completions[1].tags,
+      Some(vec![lsp::CompletionItemTag::DEPRECATED])
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_label_details() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-described.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .unwrap();
+    assert!(completions.items.iter().all(|i| i.label_details.is_none()));
+
+    module_registry.set_label_details_support(true);
+    let mut completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .unwrap()
+      .items;
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(
+      completions[0].label_details,
+      Some(lsp::CompletionItemLabelDetails {
+        detail: None,
+        description: Some("A module for testing.".to_string()),
+      })
+    );
+    assert_eq!(
+      completions[1].label_details,
+      Some(lsp::CompletionItemLabelDetails {
+        detail: None,
+        description: Some("module".to_string()),
+      })
+    );
+    // the detail is still set for clients which don't show label details
+    assert_eq!(completions[1].detail, Some("(module)".to_string()));
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_kinds() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 53,
+      },
+    };
+    let fixtures = [
+      ("http://localhost:4545/x/", lsp::CompletionItemKind::MODULE),
+      (
+        "http://localhost:4545/x/a@",
+        lsp::CompletionItemKind::FOLDER,
+      ),
+      (
+        "http://localhost:4545/x/a@v1.0.0/",
+        lsp::CompletionItemKind::FILE,
+      ),
+    ];
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-kinds.json",
+      )
+      .await
+      .expect("could not enable");
+    for (specifier, kind) in fixtures {
+      let completions = module_registry
+        .get_completions(specifier, specifier.len(), &range, |_| false)
+        .await;
+      assert!(completions.is_some());
+      let completions = completions.unwrap().items;
+      assert!(!completions.is_empty());
+      for completion in completions {
+        assert_eq!(completion.kind, Some(kind));
+      }
+    }
+
+    // without kind hints, only the last key is completed as files
+    let fixtures = [
+      ("http://localhost:4545/x/", lsp::CompletionItemKind::FOLDER),
+      (
+        "http://localhost:4545/x/a@",
+        lsp::CompletionItemKind::FOLDER,
+      ),
+      (
+        "http://localhost:4545/x/a@v1.0.0/",
+        lsp::CompletionItemKind::FILE,
+      ),
+    ];
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    for (specifier, kind) in fixtures {
+      let completions = module_registry
+        .get_completions(specifier, specifier.len(), &range, |_| false)
+        .await;
+      assert!(completions.is_some());
+      let completions = completions.unwrap().items;
+      assert!(!completions.is_empty());
+      for completion in completions {
+        assert_eq!(completion.kind, Some(kind));
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_commit_characters() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 53,
+      },
+    };
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-kinds.json",
+      )
+      .await
+      .expect("could not enable");
+    let fixtures = [
+      ("http://localhost:4545/x/", Some(vec!["@".to_string()])),
+      ("http://localhost:4545/x/a@", Some(vec!["/".to_string()])),
+      ("http://localhost:4545/x/a@v1.0.0/", None),
+    ];
+    for (specifier, commit_characters) in fixtures {
+      let completions = module_registry
+        .get_completions(specifier, specifier.len(), &range, |_| false)
+        .await;
+      assert!(completions.is_some());
+      let completions = completions.unwrap().items;
+      assert!(!completions.is_empty());
+      for completion in completions {
+        assert_eq!(completion.commit_characters, commit_characters);
+      }
+    }
 
-    let cfg = RegistryConfigurationJson {
-      version: 1,
-      registries: vec![RegistryConfiguration {
-        schema: "/:module@:version/:path*".to_string(),
-        variables: vec![
-          RegistryConfigurationVariable {
-            key: "module".to_string(),
-            documentation: None,
-            url: "https://api.deno.land/modules?short".to_string(),
-          },
-          RegistryConfigurationVariable {
-            key: "version".to_string(),
-            documentation: None,
-            url: "https://deno.land/_vsc1/module/${module}/${path}".to_string(),
-          },
-          RegistryConfigurationVariable {
-            key: "path".to_string(),
-            documentation: None,
-            url: "https://deno.land/_vsc1/module/${module}/v/${{version}}"
-              .to_string(),
-          },
-        ],
-      }],
+    module_registry.set_commit_characters(false);
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    for completion in completions.unwrap().items {
+      assert_eq!(completion.commit_characters, None);
+    }
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_multi_byte() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-unicode.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 44,
+      },
     };
-    assert!(validate_config(&cfg).is_err());
+    let completions = module_registry
+      .get_completions("http://localhost:4545/ñ/", 24, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let mut completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 2);
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(completions[0].label, "a");
+    assert_eq!(
+      completions[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/%C3%B1/a".to_string(),
+      }))
+    );
+    let completions = module_registry
+      .get_completions("http://localhost:4545/ñ", 23, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let mut completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 2);
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(completions[0].label, "/a");
+    let completions = module_registry
+      .get_completions("http://localhost:4545/ñ", 22, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].label, "/ñ");
+  }
 
-    let cfg = RegistryConfigurationJson {
-      version: 1,
-      registries: vec![RegistryConfiguration {
-        schema: "/:module@:version/:path*".to_string(),
-        variables: vec![
-          RegistryConfigurationVariable {
-            key: "module".to_string(),
-            documentation: None,
-            url: "https://api.deno.land/modules?short".to_string(),
-          },
-          RegistryConfigurationVariable {
-            key: "version".to_string(),
-            documentation: None,
-            url: "https://deno.land/_vsc1/module/${module}/v/${{version}}"
-              .to_string(),
-          },
-          RegistryConfigurationVariable {
-            key: "path".to_string(),
-            documentation: None,
-            url: "https://deno.land/_vsc1/module/${module}/v/${{version}}"
-              .to_string(),
-          },
-        ],
-      }],
+  #[tokio::test]
+  async fn test_registry_completions_encoded() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-encoded.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 53,
+      },
     };
-    assert!(validate_config(&cfg).is_err());
+    let fixtures = [
+      (
+        "http://localhost:4545/x/my%20mod@",
+        "http://localhost:4545/x/my%20mod@v1.0.0",
+      ),
+      (
+        "http://localhost:4545/x/a%2Fb@",
+        "http://localhost:4545/x/a%2Fb@v1.0.0",
+      ),
+      (
+        "http://localhost:4545/x/my%20mod@v1.0.0/",
+        "http://localhost:4545/x/my%20mod@v1.0.0/b/c.ts",
+      ),
+    ];
+    for (specifier, expected) in fixtures {
+      let completions = module_registry
+        .get_completions(specifier, specifier.len(), &range, |_| false)
+        .await;
+      assert!(completions.is_some());
+      let mut completions = completions.unwrap().items;
+      completions.sort_by(|a, b| a.label.cmp(&b.label));
+      assert_eq!(
+        completions[0].text_edit,
+        Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+          range,
+          new_text: expected.to_string(),
+        }))
+      );
+    }
+  }
 
-    let cfg = RegistryConfigurationJson {
-      version: 1,
-      registries: vec![RegistryConfiguration {
-        schema: "/:module@:version/:path*".to_string(),
-        variables: vec![
-          RegistryConfigurationVariable {
-            key: "module".to_string(),
-            documentation: None,
-            url: "https://api.deno.land/modules?short".to_string(),
-          },
-          RegistryConfigurationVariable {
-            key: "version".to_string(),
-            documentation: None,
-            url: "https://deno.land/_vsc1/module/${module}".to_string(),
-          },
-          RegistryConfigurationVariable {
-            key: "path".to_string(),
-            documentation: None,
-            url: "https://deno.land/_vsc1/module/${module}/v/${{version}}"
-              .to_string(),
-          },
-        ],
-      }],
+  #[tokio::test]
+  async fn test_registry_clear_cache() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let unrelated = temp_dir.path().join("deps");
+    std::fs::create_dir_all(&unrelated).unwrap();
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    assert!(location.exists());
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 41,
+      },
     };
-    assert!(validate_config(&cfg).is_ok());
+    let completions = module_registry
+      .get_completions("http://localhost:4545", 21, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    module_registry
+      .clear_cache()
+      .expect("could not clear cache");
+    assert!(!location.exists());
+    assert!(unrelated.exists());
+    let completions = module_registry
+      .get_completions("http://localhost:4545", 21, &range, |_| false)
+      .await;
+    assert!(completions.is_none());
+    // clearing a cache which has already been cleared is not an error
+    module_registry
+      .clear_cache()
+      .expect("could not clear cache");
+  }
 
-    let cfg: RegistryConfigurationJson = serde_json::from_value(json!({
-      "version": 2,
-      "registries": [
-        {
-          "schema": "/x/:module([a-z0-9_]+)@:version?/:path",
-          "variables": [
-            {
-              "key": "module",
-              "documentation": "/api/details/mods/${module}",
-              "url": "/api/mods/${module}"
-            },
-            {
-              "key": "version",
-              "documentation": "/api/details/mods/${module}/v/${{version}}",
-              "url": "/api/mods/${module}/v/${{version}}"
-            },
-            {
-              "key": "path",
-              "documentation": "/api/details/mods/${module}/v/${{version}}/p/${path}",
-              "url": "/api/mods/${module}/v/${{version}}/p/${path}"
-            }
-          ]
-        },
-        {
-          "schema": "/x/:module([a-z0-9_]+)/:path",
-          "variables": [
-            {
-              "key": "module",
-              "documentation": "/api/details/mods/${module}",
-              "url": "/api/mods/${module}"
-            },
-            {
-              "key": "path",
-              "documentation": "/api/details/mods/${module}/v/latest/p/${path}",
-              "url": "/api/mods/${module}/v/latest/p/${path}"
-            }
+  #[tokio::test]
+  async fn test_registry_min_client_version() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    let err = module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-min-client.json",
+      )
+      .await
+      .expect_err("should have failed");
+    assert!(err
+      .to_string()
+      .contains("registry requires client protocol v2"));
+    assert!(module_registry.origins.is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_registry_schemas() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    assert!(module_registry.schemas("http://localhost:4545/").is_empty());
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    assert_eq!(
+      module_registry.schemas("http://localhost:4545/"),
+      vec![
+        "/x/:module([a-z0-9_]*)@:version?/:path*".to_string(),
+        "/x/:module([a-z0-9_]*)/:path*".to_string(),
+      ]
+    );
+    assert_eq!(
+      module_registry.variables("http://localhost:4545"),
+      vec![
+        (
+          "/x/:module([a-z0-9_]*)@:version?/:path*".to_string(),
+          vec![
+            "module".to_string(),
+            "version".to_string(),
+            "path".to_string()
           ]
-        }
+        ),
+        (
+          "/x/:module([a-z0-9_]*)/:path*".to_string(),
+          vec!["module".to_string(), "path".to_string()]
+        ),
       ]
-    })).unwrap();
-    assert!(validate_config(&cfg).is_ok());
+    );
+    assert!(module_registry.schemas("https://deno.land/").is_empty());
+    assert!(module_registry.variables("not a url").is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_registry_documentation_url() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    assert_eq!(
+      module_registry.documentation_url(
+        "http://localhost:4545",
+        0,
+        "module",
+        "a"
+      ),
+      None
+    );
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    assert_eq!(
+      module_registry.documentation_url(
+        "http://localhost:4545",
+        1,
+        "module",
+        "a"
+      ),
+      Some(
+        resolve_url("http://localhost:4545/lsp/registries/doc_a.json").unwrap()
+      )
+    );
+    // the key has no documentation
+    assert_eq!(
+      module_registry.documentation_url(
+        "http://localhost:4545",
+        0,
+        "path",
+        "a"
+      ),
+      None
+    );
+    // the key is not part of the schema
+    assert_eq!(
+      module_registry.documentation_url(
+        "http://localhost:4545",
+        1,
+        "version",
+        "a"
+      ),
+      None
+    );
+    assert_eq!(
+      module_registry.documentation_url(
+        "http://localhost:4545",
+        2,
+        "module",
+        "a"
+      ),
+      None
+    );
+    assert_eq!(
+      module_registry.documentation_url("https://deno.land", 0, "module", "a"),
+      None
+    );
   }
 
   #[tokio::test]
-  async fn test_registry_completions_origin_match() {
+  async fn test_registry_completions_superseded() {
     let _g = test_util::http_server();
     let temp_dir = TempDir::new().expect("could not create tmp");
     let location = temp_dir.path().join("registries");
     let mut module_registry = ModuleRegistry::new(&location);
     module_registry
-      .enable("http://localhost:4545/")
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-slow.json",
+      )
       .await
       .expect("could not enable");
     let range = lsp::Range {
@@ -1101,23 +10675,186 @@ mod tests {
       },
       end: lsp::Position {
         line: 0,
-        character: 21,
+        character: 44,
+      },
+    };
+    let start = Instant::now();
+    let (first, second) = future::join(
+      module_registry.get_completions(
+        "http://localhost:4545/x/",
+        24,
+        &range,
+        |_| false,
+      ),
+      async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        module_registry
+          .get_completions("h", 1, &range, |_| false)
+          .await
+      },
+    )
+    .await;
+    // the first request is waiting on a slow endpoint when the second request
+    // supersedes it
+    assert!(first.is_none());
+    assert!(start.elapsed() < Duration::from_secs(1));
+    let second = second.expect("no completions");
+    assert_eq!(second.items[0].label, "http://localhost:4545");
+  }
+
+  #[tokio::test]
+  async fn test_registry_compressed_items() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let module_registry = ModuleRegistry::new(&location);
+    let items = module_registry
+      .get_items(
+        "http://localhost:4545/lsp/registries/gzip_modules.json",
+        None,
+      )
+      .await
+      .expect("could not get items");
+    assert_eq!(
+      items.into_parts().0,
+      vec![("gzip".to_string(), None), ("gzipped".to_string(), None)]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_post() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-post.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
       },
     };
     let completions = module_registry
-      .get_completions("h", 1, &range, |_| false)
-      .await;
-    assert!(completions.is_some());
-    let completions = completions.unwrap().items;
-    assert_eq!(completions.len(), 1);
-    assert_eq!(completions[0].label, "http://localhost:4545");
+      .get_completions("http://localhost:4545/x/ab", 26, &range, |_| false)
+      .await
+      .expect("no completions");
+    let labels: Vec<&str> =
+      completions.items.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(labels, vec!["ab_found"]);
+  }
+
+  #[test]
+  fn test_validate_config_method() {
+    let err = validate_config_str(
+      r#"{
+        "version": 1,
+        "registries": [
+          {
+            "schema": "/:module",
+            "variables": [
+              { "key": "module", "url": "/modules", "method": "PUT" }
+            ]
+          }
+        ]
+      }"#,
+    )
+    .unwrap_err();
     assert_eq!(
-      completions[0].text_edit,
-      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
-        range,
-        new_text: "http://localhost:4545".to_string()
+      err.to_string(),
+      RegistryConfigError::UnsupportedMethod {
+        schema: "/:module".to_string(),
+        variable: "module".to_string(),
+        method: "PUT".to_string(),
+      }
+      .to_string()
+    );
+  }
+
+  #[test]
+  fn test_validate_config_duplicate_key() {
+    let err = validate_config_str(
+      r#"{
+        "version": 1,
+        "registries": [
+          {
+            "schema": "/:scope/:name@:scope",
+            "variables": [
+              { "key": "scope", "url": "/scopes" },
+              { "key": "name", "url": "/names/${scope}" }
+            ]
+          }
+        ]
+      }"#,
+    )
+    .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      RegistryConfigError::DuplicateKey {
+        schema: "/:scope/:name@:scope".to_string(),
+        key: "scope".to_string(),
+      }
+      .to_string()
+    );
+  }
+
+  #[test]
+  fn test_validate_config_default() {
+    let validate = |module_default: Value, version_default: Value| {
+      let config: RegistryConfigurationJson = serde_json::from_value(json!({
+        "version": 2,
+        "registries": [
+          {
+            "schema": "/:module([a-z]+)@:version?",
+            "variables": [
+              { "key": "module", "url": "/modules", "default": module_default },
+              {
+                "key": "version",
+                "url": "/${module}/versions",
+                "default": version_default
+              }
+            ]
+          }
+        ]
       }))
+      .unwrap();
+      validate_config(&config)
+    };
+    assert!(validate(Value::Null, json!("latest")).is_ok());
+    assert_eq!(
+      validate(json!("std"), Value::Null),
+      Err(RegistryConfigError::InvalidDefault {
+        schema: "/:module([a-z]+)@:version?".to_string(),
+        variable: "module".to_string(),
+        default: "std".to_string(),
+      })
     );
+    assert!(validate(Value::Null, json!("")).is_err());
+    assert!(validate(Value::Null, json!("v1/v2")).is_err());
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_default() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-defaults.json",
+      )
+      .await
+      .expect("could not enable");
     let range = lsp::Range {
       start: lsp::Position {
         line: 0,
@@ -1125,33 +10862,284 @@ mod tests {
       },
       end: lsp::Position {
         line: 0,
-        character: 36,
+        character: 47,
       },
     };
+    // the version is omitted, and so the default of "latest" is used in the
+    // url of the path, instead of an empty version
     let completions = module_registry
-      .get_completions("http://localhost", 16, &range, |_| false)
-      .await;
-    assert!(completions.is_some());
-    let completions = completions.unwrap().items;
-    assert_eq!(completions.len(), 1);
-    assert_eq!(completions[0].label, "http://localhost:4545");
+      .get_completions("http://localhost:4545/x/a@/", 27, &range, |_| false)
+      .await
+      .expect("no completions");
+    let mut labels: Vec<&str> =
+      completions.items.iter().map(|i| i.label.as_str()).collect();
+    labels.sort_unstable();
+    assert_eq!(labels, vec!["b/c.ts", "d/e.js"]);
+    // the default isn't part of the completed specifier
+    assert!(completions.items.iter().all(|i| matches!(
+      &i.text_edit,
+      Some(lsp::CompletionTextEdit::Edit(edit))
+        if edit.new_text.starts_with("http://localhost:4545/x/a@/")
+    )));
+  }
+
+  #[test]
+  fn test_validate_config_relative_schema() {
+    let err = validate_config_str(
+      r#"{
+        "version": 1,
+        "registries": [
+          {
+            "schema": "x/:module",
+            "variables": [
+              { "key": "module", "url": "/modules" }
+            ]
+          }
+        ]
+      }"#,
+    )
+    .unwrap_err();
     assert_eq!(
-      completions[0].text_edit,
-      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
-        range,
-        new_text: "http://localhost:4545".to_string()
+      err.to_string(),
+      RegistryConfigError::RelativeSchema {
+        schema: "x/:module".to_string(),
+      }
+      .to_string()
+    );
+  }
+
+  #[test]
+  fn test_validate_config_invalid_url() {
+    let err = validate_config_str(
+      r#"{
+        "version": 2,
+        "registries": [
+          {
+            "schema": "/x/:module",
+            "variables": [
+              {
+                "key": "module",
+                "url": "/modules",
+                "documentation": "https://docs exa mple/${module}"
+              }
+            ]
+          }
+        ]
+      }"#,
+    )
+    .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      RegistryConfigError::InvalidUrl {
+        schema: "/x/:module".to_string(),
+        variable: "module".to_string(),
+        url: "https://docs exa mple/${module}".to_string(),
+        message: "invalid domain character".to_string(),
+        documentation: true,
+      }
+      .to_string()
+    );
+    assert!(validate_config_str(
+      r#"{
+        "version": 2,
+        "registries": [
+          {
+            "schema": "/x/:module",
+            "variables": [
+              {
+                "key": "module",
+                "url": "https://example.com/${module}/${{module}}"
+              }
+            ]
+          }
+        ]
+      }"#,
+    )
+    .is_ok());
+  }
+
+  #[test]
+  fn test_compile_config_unresolvable() {
+    let compile = |variables: Value| {
+      let config: RegistryConfigurationJson = serde_json::from_value(json!({
+        "version": 2,
+        "registries": [
+          {
+            "schema": "/:module@:version?",
+            "variables": variables
+          }
+        ]
+      }))
+      .unwrap();
+      compile_config(config).map(|_| ())
+    };
+    assert!(compile(json!([
+      { "key": "module", "url": "/modules" },
+      { "key": "version", "url": "/recent/${module}", "when": "version == \"\"" },
+      { "key": "version", "url": "/all/${module}", "when": "version != \"\"" }
+    ]))
+    .is_ok());
+    let err = compile(json!([
+      { "key": "module", "url": "/modules" },
+      { "key": "version", "url": "/recent/${module}", "when": "version == \"\"" }
+    ]))
+    .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      RegistryConfigError::UnresolvableKey {
+        schema: "/:module@:version?".to_string(),
+        key: "version".to_string(),
+        condition: "`version != \"\"`".to_string(),
+      }
+      .to_string()
+    );
+    let err = compile(json!([
+      { "key": "module", "url": "http://[::1/modules" },
+      { "key": "version", "url": "/versions/${module}" }
+    ]))
+    .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      RegistryConfigError::InvalidUrl {
+        schema: "/:module@:version?".to_string(),
+        variable: "module".to_string(),
+        url: "http://[::1/modules".to_string(),
+        message: "invalid IPv6 address".to_string(),
+        documentation: false,
+      }
+      .to_string()
+    );
+  }
+
+  #[test]
+  fn test_compile_config_many_conditions() {
+    // a configuration with a lot of keys referenced by conditions is checked
+    // without trying every combination of their values
+    let keys: Vec<String> = (0..100).map(|i| format!("k{:0>3}", i)).collect();
+    let schema = keys
+      .iter()
+      .map(|key| format!("/:{}", key))
+      .chain(std::iter::once("/:module".to_string()))
+      .collect::<String>();
+    let compile = |when: &[String]| {
+      let mut variables: Vec<Value> = keys
+        .iter()
+        .map(|key| json!({ "key": key, "url": "/items" }))
+        .collect();
+      variables.extend(when.iter().map(
+        |when| json!({ "key": "module", "url": "/modules", "when": when }),
+      ));
+      let config: RegistryConfigurationJson = serde_json::from_value(json!({
+        "version": 2,
+        "registries": [{ "schema": schema, "variables": variables }]
       }))
+      .unwrap();
+      compile_config(config).map(|_| ())
+    };
+    let mut when: Vec<String> =
+      keys.iter().map(|key| format!(r#"{} != """#, key)).collect();
+    let err = compile(&when).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      RegistryConfigError::UnresolvableKey {
+        schema: schema.clone(),
+        key: "module".to_string(),
+        condition: keys
+          .iter()
+          .map(|key| format!(r#"`{} == ""`"#, key))
+          .collect::<Vec<_>>()
+          .join(" and "),
+      }
+      .to_string()
+    );
+    when.push(r#"k099 == """#.to_string());
+    assert!(compile(&when).is_ok());
+  }
+
+  #[test]
+  fn test_variable_condition() {
+    let condition = VariableCondition::parse(r#"version == """#).unwrap();
+    assert_eq!(
+      condition,
+      VariableCondition {
+        key: "version".to_string(),
+        empty: true,
+      }
+    );
+    let condition = VariableCondition::parse(r#" version!="" "#).unwrap();
+    assert!(!condition.empty);
+    assert_eq!(VariableCondition::parse(r#"version == "1""#), None);
+    assert_eq!(VariableCondition::parse(r#"version > """#), None);
+    assert_eq!(VariableCondition::parse(r#" == """#), None);
+
+    let mut params = HashMap::new();
+    assert!(!condition.is_met(&params));
+    params.insert(
+      StringOrNumber::String("version".to_string()),
+      StringOrVec::String("".to_string()),
+    );
+    assert!(!condition.is_met(&params));
+    params.insert(
+      StringOrNumber::String("version".to_string()),
+      StringOrVec::String("1.0.0".to_string()),
+    );
+    assert!(condition.is_met(&params));
+  }
+
+  #[test]
+  fn test_validate_config_when() {
+    let config = |when: &str| {
+      format!(
+        r#"{{
+          "version": 1,
+          "registries": [
+            {{
+              "schema": "/:module@:version?",
+              "variables": [
+                {{ "key": "module", "url": "/modules" }},
+                {{ "key": "version", "url": "/versions", "when": {} }},
+                {{ "key": "version", "url": "/all" }}
+              ]
+            }}
+          ]
+        }}"#,
+        json!(when)
+      )
+    };
+    assert!(validate_config_str(&config(r#"module != """#)).is_ok());
+    let err = validate_config_str(&config("module")).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      RegistryConfigError::InvalidCondition {
+        schema: "/:module@:version?".to_string(),
+        variable: "version".to_string(),
+        condition: "module".to_string(),
+      }
+      .to_string()
+    );
+    let err = validate_config_str(&config(r#"path == """#)).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      RegistryConfigError::MissingConditionKey {
+        schema: "/:module@:version?".to_string(),
+        variable: "version".to_string(),
+        key: "path".to_string(),
+      }
+      .to_string()
     );
   }
 
   #[tokio::test]
-  async fn test_registry_completions() {
+  async fn test_registry_completions_when() {
     let _g = test_util::http_server();
     let temp_dir = TempDir::new().expect("could not create tmp");
     let location = temp_dir.path().join("registries");
     let mut module_registry = ModuleRegistry::new(&location);
     module_registry
-      .enable("http://localhost:4545/")
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-when.json",
+      )
       .await
       .expect("could not enable");
     let range = lsp::Range {
@@ -1161,22 +11149,71 @@ mod tests {
       },
       end: lsp::Position {
         line: 0,
-        character: 41,
+        character: 46,
       },
     };
     let completions = module_registry
-      .get_completions("http://localhost:4545", 21, &range, |_| false)
+      .get_completions("http://localhost:4545/x/a@", 26, &range, |_| false)
+      .await
+      .expect("no completions");
+    let labels: Vec<&str> =
+      completions.items.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(labels, vec!["v2.0.0"]);
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a@v1", 28, &range, |_| false)
+      .await
+      .expect("no completions");
+    let mut labels: Vec<&str> =
+      completions.items.iter().map(|i| i.label.as_str()).collect();
+    labels.sort_unstable();
+    assert_eq!(labels, vec!["v1.0.0", "v1.0.1", "v2.0.0"]);
+  }
+
+  #[tokio::test]
+  async fn test_registry_last_error() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    assert_eq!(module_registry.last_error("http://localhost:4545/"), None);
+    let result = module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/missing.json",
+      )
       .await;
-    assert!(completions.is_some());
-    let completions = completions.unwrap().items;
-    assert_eq!(completions.len(), 1);
-    assert_eq!(completions[0].label, "/x");
+    assert!(result.is_err());
+    let err = module_registry.last_error("http://localhost:4545/");
+    assert_eq!(err, Some(result.unwrap_err().to_string()));
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
+    assert_eq!(module_registry.last_error("http://localhost:4545"), None);
+  }
+
+  #[tokio::test]
+  async fn test_registry_includes() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-index.json",
+      )
+      .await
+      .expect("could not enable");
+    // the index is included again by one of the configurations it includes,
+    // and the schema of the first configuration is repeated by the second
+    let schemas: Vec<&str> = module_registry.origins["http://localhost:4545"]
+      .iter()
+      .map(|r| r.schema.as_str())
+      .collect();
     assert_eq!(
-      completions[0].text_edit,
-      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
-        range,
-        new_text: "http://localhost:4545/x".to_string()
-      }))
+      schemas,
+      vec!["/x/:module([a-z0-9_]*)/:path*", "/y/:module([a-z0-9_]*)"]
     );
     let range = lsp::Range {
       start: lsp::Position {
@@ -1185,23 +11222,86 @@ mod tests {
       },
       end: lsp::Position {
         line: 0,
-        character: 42,
+        character: 41,
       },
     };
     let completions = module_registry
-      .get_completions("http://localhost:4545/", 22, &range, |_| false)
+      .get_completions("http://localhost:4545", 21, &range, |_| false)
+      .await
+      .expect("no completions");
+    let mut labels: Vec<&str> =
+      completions.items.iter().map(|i| i.label.as_str()).collect();
+    labels.sort_unstable();
+    assert_eq!(labels, vec!["/x", "/y"]);
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_match_query() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-query.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 48,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
       .await;
     assert!(completions.is_some());
-    let completions = completions.unwrap().items;
-    assert_eq!(completions.len(), 1);
-    assert_eq!(completions[0].label, "/x");
+    let mut completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 2);
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
     assert_eq!(
       completions[0].text_edit,
       Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
         range,
-        new_text: "http://localhost:4545/x".to_string()
+        new_text: "http://localhost:4545/x/a".to_string(),
+      }))
+    );
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a?v=", 28, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let mut completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 3);
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(completions[0].label, "v1.0.0");
+    assert_eq!(
+      completions[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/x/a?v=v1.0.0".to_string(),
       }))
     );
+  }
+
+  #[tokio::test]
+  async fn test_registry_completions_match_fragment() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-fragment.json",
+      )
+      .await
+      .expect("could not enable");
     let range = lsp::Range {
       start: lsp::Position {
         line: 0,
@@ -1209,24 +11309,35 @@ mod tests {
       },
       end: lsp::Position {
         line: 0,
-        character: 44,
+        character: 49,
       },
     };
     let completions = module_registry
-      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
-      .await;
-    assert!(completions.is_some());
-    let completions = completions.unwrap();
-    assert_eq!(completions.items.len(), 2);
-    assert!(completions.is_incomplete);
-    assert!(
-      completions.items[0].label == *"a" || completions.items[0].label == *"b"
-    );
-    assert!(
-      completions.items[1].label == *"a" || completions.items[1].label == *"b"
+      .get_completions("http://localhost:4545/docs/a#", 29, &range, |_| false)
+      .await
+      .expect("no completions")
+      .items;
+    assert_eq!(completions.len(), 3);
+    assert_eq!(completions[0].label, "v1.0.0");
+    assert_eq!(
+      completions[0].text_edit,
+      Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+        range,
+        new_text: "http://localhost:4545/docs/a#v1.0.0".to_string(),
+      }))
     );
+  }
 
-    // testing for incremental searching for a module
+  #[tokio::test]
+  async fn test_registry_completions_strip_fragment() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
     let range = lsp::Range {
       start: lsp::Position {
         line: 0,
@@ -1234,35 +11345,33 @@ mod tests {
       },
       end: lsp::Position {
         line: 0,
-        character: 45,
+        character: 51,
       },
     };
+    // a specifier pasted with a fragment still matches its path, where the
+    // cursor is before the fragment
     let completions = module_registry
-      .get_completions("http://localhost:4545/x/a", 25, &range, |_| false)
-      .await;
-    assert!(completions.is_some());
-    let completions = completions.unwrap();
-    assert_eq!(completions.items.len(), 4);
-    assert!(!completions.is_incomplete);
-    assert_eq!(
-      completions.items[0].data,
-      Some(json!({
-        "documentation": format!("http://localhost:4545/lsp/registries/doc_{}.json", completions.items[0].label),
-      }))
-    );
+      .get_completions("http://localhost:4545/x/a@#L10", 26, &range, |_| false)
+      .await
+      .expect("no completions")
+      .items;
+    let labels: Vec<&str> =
+      completions.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(labels, vec!["v1.0.0", "v1.0.1", "v2.0.0"]);
+  }
 
-    // testing getting the documentation
-    let documentation = module_registry
-      .get_documentation("http://localhost:4545/lsp/registries/doc_a.json")
-      .await;
-    assert_eq!(
-      documentation,
-      Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
-        kind: lsp::MarkupKind::Markdown,
-        value: "**a**".to_string(),
-      }))
+  #[tokio::test]
+  async fn test_registry_fetch_retries() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry.set_retries(2, Duration::from_millis(10));
+    let specifier = enable_with_token(
+      &mut module_registry,
+      temp_dir.path(),
+      "flaky_modules.json",
     );
-
     let range = lsp::Range {
       start: lsp::Position {
         line: 0,
@@ -1270,15 +11379,39 @@ mod tests {
       },
       end: lsp::Position {
         line: 0,
-        character: 46,
+        character: 44,
       },
     };
-    let completions = module_registry
-      .get_completions("http://localhost:4545/x/a@", 26, &range, |_| false)
-      .await;
-    assert!(completions.is_some());
-    let completions = completions.unwrap().items;
-    assert_eq!(completions.len(), 3);
+    // the endpoint fails every other request, so a single retry is enough
+    for _ in 0..2 {
+      module_registry.items_cache.clear();
+      module_registry
+        .file_fetcher
+        .http_cache
+        .remove(&specifier)
+        .unwrap();
+      let completions = module_registry
+        .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+        .await;
+      assert!(completions.is_some());
+      let completions = completions.unwrap().items;
+      assert_eq!(completions.len(), 1);
+      assert_eq!(completions[0].label, "flaky");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_registry_retry_after() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry.set_min_fetch_interval(Duration::ZERO);
+    let specifier = enable_with_token(
+      &mut module_registry,
+      temp_dir.path(),
+      "limited_modules.json",
+    );
     let range = lsp::Range {
       start: lsp::Position {
         line: 0,
@@ -1286,33 +11419,46 @@ mod tests {
       },
       end: lsp::Position {
         line: 0,
-        character: 53,
+        character: 44,
       },
     };
+    // the endpoint only succeeds for the first request, after which it
+    // responds with a 429 and a `Retry-After`
     let completions = module_registry
-      .get_completions("http://localhost:4545/x/a@v1.0.0/", 33, &range, |_| {
-        false
-      })
-      .await;
-    assert!(completions.is_some());
-    let completions = completions.unwrap().items;
-    assert_eq!(completions.len(), 2);
-    assert_eq!(completions[0].detail, Some("(path)".to_string()));
-    assert_eq!(completions[0].kind, Some(lsp::CompletionItemKind::FILE));
-    assert!(completions[0].command.is_some());
-    assert_eq!(completions[1].detail, Some("(path)".to_string()));
-    assert_eq!(completions[0].kind, Some(lsp::CompletionItemKind::FILE));
-    assert!(completions[1].command.is_some());
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert_eq!(completions.items.len(), 1);
+    assert_eq!(completions.items[0].label, "limited");
+    assert!(!module_registry.is_backing_off(&specifier));
+    module_registry.items_cache.clear();
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert!(completions.items.is_empty());
+    assert!(module_registry.is_backing_off(&specifier));
+    // while backing off, the response cached on disk is used instead
+    module_registry.items_cache.clear();
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert_eq!(completions.items.len(), 1);
+    assert_eq!(completions.items[0].label, "limited");
   }
 
   #[tokio::test]
-  async fn test_registry_completions_key_first() {
+  async fn test_registry_items_pages() {
     let _g = test_util::http_server();
     let temp_dir = TempDir::new().expect("could not create tmp");
     let location = temp_dir.path().join("registries");
     let mut module_registry = ModuleRegistry::new(&location);
     module_registry
-      .enable_custom("http://localhost:4545/lsp/registries/deno-import-intellisense-key-first.json")
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-paged.json",
+      )
       .await
       .expect("could not enable");
     let range = lsp::Range {
@@ -1322,28 +11468,106 @@ mod tests {
       },
       end: lsp::Position {
         line: 0,
-        character: 42,
+        character: 44,
       },
     };
     let completions = module_registry
-      .get_completions("http://localhost:4545/", 22, &range, |_| false)
-      .await;
-    assert!(completions.is_some());
-    let completions = completions.unwrap().items;
-    assert_eq!(completions.len(), 3);
-    for completion in completions {
-      assert!(completion.text_edit.is_some());
-      if let lsp::CompletionTextEdit::Edit(edit) = completion.text_edit.unwrap()
-      {
-        assert_eq!(
-          edit.new_text,
-          format!("http://localhost:4545/{}", completion.label)
-        );
-      } else {
-        unreachable!("unexpected text edit");
-      }
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    // the preselect and if the items are incomplete come from the last page
+    assert!(!completions.is_incomplete);
+    let mut items = completions.items;
+    items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+    let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(labels, vec!["a", "b", "c"]);
+    assert_eq!(items[0].preselect, None);
+    assert_eq!(items[2].preselect, Some(true));
+    // the next page isn't fetched when there are already more items than can
+    // be completed
+    module_registry.set_max_completion_items(1);
+    module_registry.items_cache.clear();
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    assert!(completions.is_incomplete);
+    assert_eq!(completions.items.len(), 1);
+    assert_eq!(completions.items[0].label, "a");
+    // when fuzzy matching, as many items are fetched as are scored
+    module_registry.set_fuzzy(true);
+    module_registry.items_cache.clear();
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 45,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/c", 25, &range, |_| false)
+      .await
+      .expect("no completions");
+    let labels: Vec<&str> =
+      completions.items.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(labels, vec!["c"]);
+  }
+
+  #[test]
+  fn test_registry_could_match() {
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .origins
+      .insert("http://localhost:4545".to_string(), Vec::new());
+    module_registry
+      .origins
+      .insert("https://example.com/registry".to_string(), Vec::new());
+    assert!(module_registry.could_match(""));
+    assert!(module_registry.could_match("http://loc"));
+    assert!(module_registry.could_match("loc"));
+    assert!(module_registry.could_match("http://localhost:4545/x/a@"));
+    assert!(module_registry.could_match("HTTP://LOCALHOST:4545/x/"));
+    assert!(module_registry.could_match("https://example.com/registry/x"));
+    assert!(!module_registry.could_match("https://example.com/other/x"));
+    assert!(!module_registry.could_match("https://deno.land/x/"));
+    assert!(!module_registry.could_match("std/path"));
+    // the check is made on every keystroke, so it needs to stay cheap with a
+    // lot of enabled registries and a lot of unrelated specifiers
+    for i in 0..1_000 {
+      module_registry
+        .origins
+        .insert(format!("https://registry{}.example.com", i), Vec::new());
     }
+    let specifiers: Vec<String> = (0..1_000)
+      .map(|i| format!("https://cdn.example.com/pkg{}@1.0.0/mod.ts", i))
+      .collect();
+    let start = Instant::now();
+    assert!(!specifiers.iter().any(|s| module_registry.could_match(s)));
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert!(
+      module_registry.could_match("https://registry999.example.com/x/mod.ts")
+    );
+    module_registry
+      .patterns
+      .push(OriginPattern::parse("https://*.deno.dev").unwrap());
+    assert!(module_registry.could_match("https://deno.land/x/"));
+  }
 
+  #[tokio::test]
+  async fn test_registry_completions_for() {
+    let _g = test_util::http_server();
+    let temp_dir = TempDir::new().expect("could not create tmp");
+    let location = temp_dir.path().join("registries");
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable("http://localhost:4545/")
+      .await
+      .expect("could not enable");
     let range = lsp::Range {
       start: lsp::Position {
         line: 0,
@@ -1351,37 +11575,43 @@ mod tests {
       },
       end: lsp::Position {
         line: 0,
-        character: 46,
+        character: 44,
       },
     };
-    let completions = module_registry
-      .get_completions("http://localhost:4545/cde@", 26, &range, |_| false)
-      .await;
-    assert!(completions.is_some());
-    let completions = completions.unwrap().items;
+    let specifier = resolve_url("http://localhost:4545/x/").unwrap();
+    let mut completions = module_registry
+      .get_completions_for(&specifier, 24, &range, |_| false)
+      .await
+      .expect("no completions")
+      .items;
+    let mut expected = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions")
+      .items;
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    expected.sort_by(|a, b| a.label.cmp(&b.label));
     assert_eq!(completions.len(), 2);
-    for completion in completions {
-      assert!(completion.text_edit.is_some());
-      if let lsp::CompletionTextEdit::Edit(edit) = completion.text_edit.unwrap()
-      {
-        assert_eq!(
-          edit.new_text,
-          format!("http://localhost:4545/cde@{}", completion.label)
-        );
-      } else {
-        unreachable!("unexpected text edit");
-      }
-    }
+    assert_eq!(completions, expected);
+    // a specifier which can't belong to a registry has no completions
+    let specifier = resolve_url("file:///a/b.ts").unwrap();
+    assert!(module_registry
+      .get_completions_for(&specifier, 14, &range, |_| false)
+      .await
+      .is_none());
   }
 
   #[tokio::test]
-  async fn test_registry_completions_complex() {
+  async fn test_registry_completions_ndjson() {
     let _g = test_util::http_server();
     let temp_dir = TempDir::new().expect("could not create tmp");
     let location = temp_dir.path().join("registries");
     let mut module_registry = ModuleRegistry::new(&location);
     module_registry
-      .enable_custom("http://localhost:4545/lsp/registries/deno-import-intellisense-complex.json")
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-ndjson.json",
+      )
       .await
       .expect("could not enable");
     let range = lsp::Range {
@@ -1391,67 +11621,104 @@ mod tests {
       },
       end: lsp::Position {
         line: 0,
-        character: 42,
+        character: 44,
       },
     };
     let completions = module_registry
-      .get_completions("http://localhost:4545/", 22, &range, |_| false)
-      .await;
-    assert!(completions.is_some());
-    let completions = completions.unwrap().items;
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await
+      .expect("no completions");
+    let mut completions = completions.items;
     assert_eq!(completions.len(), 3);
-    for completion in completions {
-      assert!(completion.text_edit.is_some());
-      if let lsp::CompletionTextEdit::Edit(edit) = completion.text_edit.unwrap()
-      {
-        assert_eq!(
-          edit.new_text,
-          format!("http://localhost:4545/{}", completion.label)
-        );
-      } else {
-        unreachable!("unexpected text edit");
-      }
-    }
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(completions[0].label, "a");
+    assert_eq!(completions[0].detail, Some("(module)".to_string()));
+    assert_eq!(completions[1].label, "b");
+    assert_eq!(completions[1].detail, Some("the b module".to_string()));
+    assert_eq!(completions[2].label, "c");
   }
 
   #[test]
-  fn test_parse_replacement_variables() {
-    let actual = parse_replacement_variables(
-      "https://deno.land/_vsc1/modules/${module}/v/${{version}}",
+  fn test_get_retry_after() {
+    use deno_core::error::generic_error;
+    let err = |retry_after: Option<&str>| {
+      let message = "Import 'http://localhost/' failed: 429".to_string();
+      generic_error(message.clone())
+        .context(TooManyRequests::new(message, retry_after.map(String::from)))
+    };
+    assert_eq!(
+      get_retry_after(&err(Some("120"))),
+      Some(Duration::from_secs(120))
     );
-    assert_eq!(actual.len(), 2);
-    assert!(actual.contains(&"module".to_owned()));
-    assert!(actual.contains(&"version".to_owned()));
+    assert_eq!(
+      get_retry_after(&err(Some("Wed, 21 Oct 2015 07:28:00 GMT"))),
+      Some(Duration::ZERO)
+    );
+    // a registry can't hold off requests for longer than the maximum
+    assert_eq!(
+      get_retry_after(&err(Some("18446744073709551615"))),
+      Some(MAX_RETRY_AFTER)
+    );
+    assert_eq!(
+      get_retry_after(&err(Some("Fri, 31 Dec 9999 23:59:59 GMT"))),
+      Some(MAX_RETRY_AFTER)
+    );
+    assert_eq!(get_retry_after(&err(Some("soon"))), None);
+    assert_eq!(get_retry_after(&err(None)), None);
+    assert_eq!(get_retry_after(&anyhow!("failed")), None);
   }
 
-  #[tokio::test]
-  async fn test_check_origin_supported() {
-    let _g = test_util::http_server();
+  #[test]
+  fn test_registry_back_off_huge_delay() {
     let temp_dir = TempDir::new().expect("could not create tmp");
     let location = temp_dir.path().join("registries");
     let module_registry = ModuleRegistry::new(&location);
-    let result = module_registry.check_origin("http://localhost:4545").await;
-    assert!(result.is_ok());
+    let specifier = resolve_url("http://localhost:4545/x/a").unwrap();
+    module_registry.back_off(&specifier, Duration::MAX);
+    assert!(module_registry.is_backing_off(&specifier));
   }
 
   #[tokio::test]
-  async fn test_check_origin_not_supported() {
+  async fn test_registry_completions_warning() {
     let _g = test_util::http_server();
     let temp_dir = TempDir::new().expect("could not create tmp");
     let location = temp_dir.path().join("registries");
-    let module_registry = ModuleRegistry::new(&location);
-    let result = module_registry.check_origin("https://deno.com").await;
-    assert!(result.is_err());
-    let err = result.unwrap_err().to_string();
-    assert!(err
-      .contains("https://deno.com/.well-known/deno-import-intellisense.json"));
-
-    // because we are caching an empty file when we hit an error with import
-    // detection when fetching the config file, we should have an error now that
-    // indicates trying to parse an empty file.
-    let result = module_registry.check_origin("https://deno.com").await;
-    assert!(result.is_err());
-    let err = result.unwrap_err().to_string();
-    assert!(err.contains("EOF while parsing a value at line 1 column 0"));
+    let mut module_registry = ModuleRegistry::new(&location);
+    module_registry
+      .enable_with_config_url(
+        "http://localhost:4545/",
+        "http://localhost:4545/lsp/registries/deno-import-intellisense-warning.json",
+      )
+      .await
+      .expect("could not enable");
+    let range = lsp::Range {
+      start: lsp::Position {
+        line: 0,
+        character: 20,
+      },
+      end: lsp::Position {
+        line: 0,
+        character: 46,
+      },
+    };
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/a@", 26, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    let completions = completions.unwrap().items;
+    assert_eq!(completions.len(), 2);
+    for completion in completions {
+      assert_eq!(
+        completion.detail,
+        Some("(version) - Warning: This module is deprecated.".to_string())
+      );
+    }
+    let completions = module_registry
+      .get_completions("http://localhost:4545/x/", 24, &range, |_| false)
+      .await;
+    assert!(completions.is_some());
+    for completion in completions.unwrap().items {
+      assert_eq!(completion.detail, Some("(module)".to_string()));
+    }
   }
 }
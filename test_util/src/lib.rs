@@ -39,6 +39,7 @@ use std::sync::Mutex;
 use std::sync::MutexGuard;
 use std::task::Context;
 use std::task::Poll;
+use std::time::Duration;
 use tempfile::TempDir;
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpListener;
@@ -83,8 +84,49 @@ lazy_static! {
   ).unwrap();
 
   static ref GUARD: Mutex<HttpServerCount> = Mutex::new(HttpServerCount::default());
+
+  /// The number of requests to each of the registry endpoints whose responses
+  /// depend on the requests made before, by endpoint and token.
+  static ref REGISTRY_REQUESTS: Mutex<HashMap<String, usize>> =
+    Mutex::new(HashMap::new());
+}
+
+/// The key of the state of a registry endpoint whose responses depend on the
+/// requests made before. Each test passes a `token` of its own in the query,
+/// so that it doesn't see the requests of other tests, or of earlier runs
+/// against the same server.
+fn registry_requests_key(req: &Request<Body>) -> String {
+  let token = req
+    .uri()
+    .query()
+    .unwrap_or_default()
+    .split('&')
+    .find_map(|pair| pair.strip_prefix("token="))
+    .unwrap_or_default();
+  format!("{}?{}", req.uri().path(), token)
+}
+
+/// Count a request to a registry endpoint, returning the number of requests
+/// to the endpoint with the same token which were counted before it.
+fn count_registry_request(req: &Request<Body>) -> usize {
+  let mut requests = REGISTRY_REQUESTS.lock().unwrap();
+  let count = requests.entry(registry_requests_key(req)).or_insert(0);
+  *count += 1;
+  *count - 1
 }
 
+/// Stop counting a request to a registry endpoint once it has been responded
+/// to, for endpoints which count the requests in flight.
+fn finish_registry_request(req: &Request<Body>) {
+  let mut requests = REGISTRY_REQUESTS.lock().unwrap();
+  if let Some(count) = requests.get_mut(&registry_requests_key(req)) {
+    *count -= 1;
+  }
+}
+
+/// The `Last-Modified` of the revalidated registry endpoint.
+const REVALIDATED_LAST_MODIFIED: &str = "Wed, 21 Oct 2015 07:28:00 GMT";
+
 pub fn root_path() -> PathBuf {
   PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR")))
     .parent()
@@ -864,6 +906,153 @@ async fn main_server(
         Ok(Response::new(Body::empty()))
       }
     }
+    (_, "/lsp/registries/private_modules.json") => {
+      let auth = req.headers().get("x-registry-auth");
+      if auth == Some(&HeaderValue::from_static("secret")) {
+        let mut res = Response::new(Body::from(r#"["private"]"#));
+        res
+          .headers_mut()
+          .insert("Content-type", HeaderValue::from_static("application/json"));
+        Ok(res)
+      } else {
+        Response::builder()
+          .status(StatusCode::UNAUTHORIZED)
+          .body(Body::empty())
+      }
+    }
+    (_, "/lsp/registries/slow_modules.json") => {
+      tokio::time::sleep(Duration::from_secs(2)).await;
+      let mut res = Response::new(Body::from(r#"["slow"]"#));
+      res
+        .headers_mut()
+        .insert("Content-type", HeaderValue::from_static("application/json"));
+      Ok(res)
+    }
+    (_, "/lsp/registries/flaky_modules.json") => {
+      if count_registry_request(&req) % 2 == 0 {
+        Response::builder()
+          .status(StatusCode::SERVICE_UNAVAILABLE)
+          .body(Body::empty())
+      } else {
+        let mut res = Response::new(Body::from(r#"["flaky"]"#));
+        res
+          .headers_mut()
+          .insert("Content-type", HeaderValue::from_static("application/json"));
+        Ok(res)
+      }
+    }
+    (_, "/lsp/registries/limited_modules.json") => {
+      if count_registry_request(&req) == 0 {
+        let mut res = Response::new(Body::from(r#"["limited"]"#));
+        res
+          .headers_mut()
+          .insert("Content-type", HeaderValue::from_static("application/json"));
+        Ok(res)
+      } else {
+        Response::builder()
+          .status(StatusCode::TOO_MANY_REQUESTS)
+          .header("Retry-After", "60")
+          .body(Body::empty())
+      }
+    }
+    (_, "/lsp/registries/revalidated_modules.json") => {
+      let if_modified_since = req.headers().get("if-modified-since");
+      if if_modified_since
+        == Some(&HeaderValue::from_static(REVALIDATED_LAST_MODIFIED))
+      {
+        return Response::builder()
+          .status(StatusCode::NOT_MODIFIED)
+          .body(Body::empty());
+      }
+      let body = if count_registry_request(&req) == 0 {
+        r#"["a","b"]"#
+      } else {
+        r#"["changed"]"#
+      };
+      let mut res = Response::new(Body::from(body));
+      let headers = res.headers_mut();
+      headers
+        .insert("Content-type", HeaderValue::from_static("application/json"));
+      headers.insert(
+        "Last-Modified",
+        HeaderValue::from_static(REVALIDATED_LAST_MODIFIED),
+      );
+      headers.insert("Cache-Control", HeaderValue::from_static("no-cache"));
+      Ok(res)
+    }
+    (_, "/lsp/registries/concurrent_modules.json") => {
+      // responds with the number of requests in flight when the request was
+      // received, holding on to the request for a while so that requests
+      // made at the same time overlap
+      let in_flight = count_registry_request(&req) + 1;
+      tokio::time::sleep(Duration::from_millis(200)).await;
+      finish_registry_request(&req);
+      let mut res = Response::new(Body::from(format!(r#"["{}"]"#, in_flight)));
+      res
+        .headers_mut()
+        .insert("Content-type", HeaderValue::from_static("application/json"));
+      Ok(res)
+    }
+    (_, "/lsp/registries/doc_negotiated") => {
+      let accept = req
+        .headers()
+        .get("accept")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+      let (body, content_type) = if accept.contains("text/markdown") {
+        ("# a\n\nThe **a** module.\n", "text/markdown; charset=utf-8")
+      } else {
+        ("The a module.\n", "text/plain; charset=utf-8")
+      };
+      let mut res = Response::new(Body::from(body));
+      res
+        .headers_mut()
+        .insert("Content-type", HeaderValue::from_static(content_type));
+      Ok(res)
+    }
+    (_, "/lsp/registries/doc_plaintext") => {
+      let mut res = Response::new(Body::from("The a module.\n"));
+      res.headers_mut().insert(
+        "Content-type",
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+      );
+      Ok(res)
+    }
+    (_, "/lsp/registries/ndjson_modules") => {
+      let mut res = Response::new(Body::from(
+        "\"a\"\n{\"value\":\"b\",\"description\":\"the b module\"}\n\n\"c\"\n",
+      ));
+      res.headers_mut().insert(
+        "Content-type",
+        HeaderValue::from_static("application/x-ndjson"),
+      );
+      Ok(res)
+    }
+    (&hyper::Method::POST, "/lsp/registries/search_modules.json") => {
+      let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+      let search: serde_json::Value = serde_json::from_slice(&body).unwrap();
+      let query = search["query"].as_str().unwrap_or_default();
+      let items = serde_json::json!([format!("{}_found", query)]);
+      let mut res = Response::new(Body::from(items.to_string()));
+      res
+        .headers_mut()
+        .insert("Content-type", HeaderValue::from_static("application/json"));
+      Ok(res)
+    }
+    (_, "/lsp/registries/bearer_modules.json") => {
+      let auth = req.headers().get("authorization");
+      if auth == Some(&HeaderValue::from_static("Bearer secret")) {
+        let mut res = Response::new(Body::from(r#"["bearer"]"#));
+        res
+          .headers_mut()
+          .insert("Content-type", HeaderValue::from_static("application/json"));
+        Ok(res)
+      } else {
+        Response::builder()
+          .status(StatusCode::UNAUTHORIZED)
+          .body(Body::empty())
+      }
+    }
     (_, "/http_version") => {
       let version = format!("{:?}", req.version());
       Ok(Response::new(version.into()))
@@ -1328,6 +1517,15 @@ fn custom_headers(p: &str, body: Vec<u8>) -> Response<Body> {
       .insert("Content-Length", HeaderValue::from_static("26"));
     return response;
   }
+  if p.ends_with("/lsp/registries/gzip_modules.json") {
+    response
+      .headers_mut()
+      .insert("Content-Encoding", HeaderValue::from_static("gzip"));
+    response
+      .headers_mut()
+      .insert("Content-Type", HeaderValue::from_static("application/json"));
+    return response;
+  }
   if p.ends_with("/053_import_compression/gziped") {
     response
       .headers_mut()